@@ -0,0 +1,60 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, ser::SerializeMap};
+
+/// Matches application names ignoring case, either exactly or with a regular expression
+#[derive(Clone, Debug)]
+pub enum AppMatcher {
+    /// Lowercase name of the application
+    Name(String),
+    Regex(Regex),
+}
+
+impl AppMatcher {
+    pub fn name(name: &str) -> Self {
+        Self::Name(name.to_lowercase())
+    }
+
+    pub fn regex(regex: &str) -> Result<Self, regex::Error> {
+        RegexBuilder::new(regex)
+            .case_insensitive(true)
+            .build()
+            .map(Self::Regex)
+    }
+
+    pub fn matches(&self, app_name: &str) -> bool {
+        match self {
+            Self::Name(name) => app_name.to_lowercase() == *name,
+            Self::Regex(regex) => regex.is_match(app_name),
+        }
+    }
+}
+
+/// Either `"name"` or `{ regex = "..." }` in the config
+impl<'de> Deserialize<'de> for AppMatcher {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Matcher {
+            Name(String),
+            Regex { regex: String },
+        }
+
+        match Matcher::deserialize(deserializer)? {
+            Matcher::Name(name) => Ok(Self::name(&name)),
+            Matcher::Regex { regex } => Self::regex(&regex).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl Serialize for AppMatcher {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Name(name) => serializer.serialize_str(name),
+            Self::Regex(regex) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("regex", regex.as_str())?;
+                map.end()
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+use gtk4 as gtk;
+use serde::Serialize;
+
+use crate::{
+    config::{Config, THEMES, expand_path, load_style, read_config, style_paths},
+    output::CommandOutput,
+};
+
+/// The problems found in the config and style files
+#[derive(Serialize)]
+pub struct Report {
+    config: PathBuf,
+    /// Questionable but working options, which don't fail the check
+    warnings: Vec<String>,
+    problems: Vec<String>,
+}
+
+impl Report {
+    pub fn ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl CommandOutput for Report {
+    fn print_text(&self) {
+        for warning in &self.warnings {
+            println!("{}: warning: {warning}", self.config.display());
+        }
+        for problem in &self.problems {
+            println!("{problem}");
+        }
+        if self.ok() {
+            println!("{}: OK", self.config.display());
+        }
+    }
+}
+
+/// Validate the config and style files without touching D-Bus or opening any windows
+pub fn run(config_path: &Path, style_path: &Path) -> Report {
+    let mut report = Report {
+        config: config_path.to_path_buf(),
+        warnings: Vec::new(),
+        problems: Vec::new(),
+    };
+
+    let config = match read_config(config_path, &[]) {
+        Ok(read) => {
+            report.problems.extend(read.skipped);
+            report
+                .warnings
+                .extend(read.unknown.iter().map(ToString::to_string));
+            read.config
+        }
+        Err(why) => {
+            // Nothing else can be checked without knowing what is configured
+            report
+                .problems
+                .push(format!("{}: {why}", config_path.display()));
+            return report;
+        }
+    };
+
+    report.warnings.extend(config.warnings());
+    report
+        .problems
+        .extend(check_paths(config_path, &config, style_path));
+    report.problems.extend(check_style(&config, style_path));
+    report
+}
+
+/// Files the config refers to that do not exist, or can't be created
+fn check_paths(config_path: &Path, config: &Config, style_path: &Path) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Some(theme) = &config.theme {
+        if !THEMES.contains(&theme.as_str()) {
+            problems.push(format!(
+                "{}: unknown theme {theme}, available themes are {}",
+                config_path.display(),
+                THEMES.join(", ")
+            ));
+        }
+    }
+
+    if let Some(paths) = &config.style {
+        for path in paths {
+            if !expand_path(path).exists() {
+                problems.push(format!(
+                    "{}: style file {path} does not exist",
+                    config_path.display()
+                ));
+            }
+        }
+    } else if !style_path.exists() {
+        log::debug!(
+            "{} does not exist, the default style is used",
+            style_path.display()
+        );
+    }
+
+    let log_files = [
+        ("log_file", config.log_file.clone()),
+        (
+            "log_notifications",
+            config.log_notifications.as_deref().map(expand_path),
+        ),
+    ];
+    for (option, path) in log_files {
+        let Some(path) = path else {
+            continue;
+        };
+        let parent = path.parent().unwrap_or(Path::new("/"));
+        if !parent.as_os_str().is_empty() && !parent.is_dir() {
+            problems.push(format!(
+                "{}: the directory of {option} {} does not exist",
+                config_path.display(),
+                path.display()
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Parsing errors in the style files, which can only be checked with a display
+fn check_style(config: &Config, style_path: &Path) -> Vec<String> {
+    if gtk::init().is_err() {
+        eprintln!("No display available, skipping the style files");
+        return Vec::new();
+    }
+
+    style_paths(config, style_path)
+        .iter()
+        .filter(|path| path.exists())
+        .flat_map(|path| {
+            let (_, errors, _) = load_style(path, "");
+            errors
+        })
+        .collect()
+}
@@ -0,0 +1,806 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    process::ExitCode,
+    rc::Rc,
+    time::Duration,
+};
+
+use clap::{ArgGroup, Parser, Subcommand};
+use gtk4::{self as gtk, gio, glib, prelude::*};
+use log::error;
+
+use crate::{
+    StartupError, check,
+    config::default_paths,
+    config_overlay::ConfigOverlay,
+    daemon::{NotificationLevel, start_daemon},
+    dbus::{
+        CONTROL_IFACE, CONTROL_PATH, INTERFACE_XML, NOTIFICATIONS_IFACE, NOTIFICATIONS_PATH,
+        ReloadOutcome,
+    },
+    default_config,
+    headless::Headless,
+    history::{self, HistoryRecord},
+    logger,
+    notification::{NotificationCloseReason, Urgency},
+    output,
+    stats::{self, AppStats},
+};
+
+// Either a command or one of the default config flags is required
+#[derive(Parser)]
+#[command(subcommand_negates_reqs = true)]
+#[command(group(ArgGroup::new("default_config").required(true)))]
+struct Args {
+    /// Print the output of commands and errors as JSON
+    #[arg(long, global = true)]
+    json: bool,
+    /// Print a config file with every option at its default value
+    #[arg(long, group = "default_config")]
+    print_default_config: bool,
+    /// Write the default config file to the config directory, unless one already exists
+    #[arg(long, group = "default_config")]
+    init_config: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Start the notification daemon
+    Daemon {
+        /// Print notifications to stdout as JSON lines instead of showing them.
+        /// Does not require a display.
+        #[arg(long)]
+        headless: bool,
+        /// Set a top-level config option over the config file, surviving reloads.
+        /// Can be given multiple times.
+        #[arg(long = "set", value_name = "KEY=VALUE")]
+        overlay: Vec<ConfigOverlay>,
+    },
+    /// Reload config and style files
+    Reload,
+    /// Set a top-level config option in the running daemon until the next reload
+    Set {
+        #[arg(required_unless_present = "clear")]
+        key: Option<String>,
+        #[arg(required_unless_present = "clear")]
+        value: Option<String>,
+        /// Drop the options set before, going back to the config file
+        #[arg(long, conflicts_with_all = ["key", "value"])]
+        clear: bool,
+    },
+    /// Manage notification level
+    Level {
+        /// Set the notification level to this value
+        level: Option<NotificationLevel>,
+    },
+    /// Set a vertical offset for notifications temporarily.
+    /// Useful for making sure notifications align with possibly dynamic UI elements.
+    SetOffset { offset: i32 },
+    /// List the shown notifications as `id<TAB>app name<TAB>summary`
+    List {
+        /// List snoozed notifications instead
+        #[arg(long)]
+        snoozed: bool,
+    },
+    /// Close a notification, including snoozed ones
+    Close {
+        #[arg(required_unless_present = "app")]
+        id: Option<u32>,
+        /// Close all notifications from this application instead, ignoring case
+        #[arg(long, conflicts_with = "id")]
+        app: Option<String>,
+        /// Match the application name as a regular expression
+        #[arg(long, requires = "app")]
+        regex: bool,
+    },
+    /// Control the notification center panel
+    Panel {
+        #[command(subcommand)]
+        action: PanelAction,
+    },
+    /// Manage the notification history
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+    /// Print how many notifications each application sent since the daemon started, and
+    /// what happened to them
+    Stats {
+        /// Start counting from zero
+        #[arg(long)]
+        reset: bool,
+    },
+    /// Print the config the daemon runs with, marking the options given with `--set` or set
+    /// with `yand set`
+    Config,
+    /// Select the first notification to control the notifications with the keyboard
+    Keyboard,
+    /// Print the minimum urgency of notifications shown on the screen and how many were kept
+    /// off it, or set it until the daemon exits
+    MinUrgency { urgency: Option<Urgency> },
+    /// Query do not disturb
+    Dnd {
+        #[command(subcommand)]
+        action: DndAction,
+    },
+    /// Check the config and style files for errors without affecting the running daemon,
+    /// exiting with 1 if any are found
+    CheckConfig {
+        /// The config file to check instead of the default one
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// The style file to check instead of the default one, if the config does not
+        /// list style files
+        #[arg(long)]
+        style: Option<PathBuf>,
+    },
+    /// Wait until a notification is closed or one of its actions is invoked,
+    /// printing either `closed:<reason>` or `action:<key>`
+    Wait {
+        id: u32,
+        /// Give up after this many seconds, exiting with code 124
+        #[arg(long)]
+        timeout: Option<u32>,
+    },
+    /// Send a notification, printing its ID
+    Send {
+        summary: String,
+        body: Option<String>,
+        /// Name of the application sending the notification
+        #[arg(long, default_value = "yand")]
+        app_name: String,
+        /// An action button, can be given multiple times
+        #[arg(long = "action", value_name = "KEY=LABEL", value_parser = parse_action)]
+        actions: Vec<(String, String)>,
+        /// Milliseconds until the notification expires, -1 for the default timeout
+        #[arg(long, default_value_t = -1, allow_negative_numbers = true)]
+        expire_time: i32,
+        /// Wait until an action is invoked and print its key instead of the ID, exiting with 1
+        /// if the notification is closed without one
+        #[arg(long)]
+        wait: bool,
+        /// Give up waiting after this many seconds, exiting with code 124
+        #[arg(long, requires = "wait")]
+        wait_timeout: Option<u32>,
+    },
+}
+
+/// Parse an action given on the command line as `key=label`
+fn parse_action(action: &str) -> Result<(String, String), String> {
+    let (key, label) = action
+        .split_once('=')
+        .ok_or_else(|| "expected key=label".to_string())?;
+    Ok((key.to_string(), label.to_string()))
+}
+
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// Write the history as a JSON array, oldest first
+    Export {
+        /// The file to write to, `-` for stdout
+        path: PathBuf,
+        /// Overwrite the file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DndAction {
+    /// Print `on` or `off`, exiting with 0 if do not disturb is enabled and 1 otherwise.
+    /// The JSON output also includes what enabled do not disturb and how many notifications
+    /// it holds back
+    Status,
+}
+
+#[derive(Subcommand)]
+enum PanelAction {
+    /// Show the panel if it is hidden, and hide it otherwise
+    Toggle,
+}
+
+/// Run the command given on the command line, which is all the yand binary does
+pub fn main() -> ExitCode {
+    let args = Args::parse();
+
+    logger::init();
+
+    let json = args.json;
+    match run(args) {
+        Ok(exit_code) => exit_code,
+        Err(why) => {
+            output::error(&why, json);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Write the default config, refusing to replace an existing one
+fn init_config() -> Result<ExitCode, StartupError> {
+    let (config_path, _) = default_paths()?;
+    if let Some(config_dir) = config_path.parent() {
+        fs::create_dir_all(config_dir)
+            .map_err(|why| StartupError::ConfigDir(config_dir.to_path_buf(), why))?;
+    }
+
+    let result = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&config_path)
+        .and_then(|mut file| {
+            io::Write::write_all(&mut file, default_config::generate().as_bytes())
+        });
+    match result {
+        Ok(()) => {
+            println!("Wrote {}", config_path.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(why) if why.kind() == io::ErrorKind::AlreadyExists => {
+            error!("{} already exists", config_path.display());
+            Ok(ExitCode::FAILURE)
+        }
+        Err(why) => {
+            error!("Failed to write {}: {why}", config_path.display());
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+fn run(args: Args) -> Result<ExitCode, StartupError> {
+    if args.print_default_config {
+        print!("{}", default_config::generate());
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.init_config {
+        return init_config();
+    }
+    let Some(command) = args.command else {
+        unreachable!("clap requires a command without the config flags");
+    };
+
+    // Checked without registering the application, which would require the daemon
+    if let Command::CheckConfig { config, style } = command {
+        let (default_config, default_style) = default_paths()?;
+        let report = check::run(
+            &config.unwrap_or(default_config),
+            &style.unwrap_or(default_style),
+        );
+        output::print(&report, args.json);
+        return Ok(if report.ok() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+    let headless = matches!(command, Command::Daemon { headless: true, .. });
+    let flags = if let Command::Daemon { headless, .. } = command {
+        // Make sure a usable display exists before claiming the bus name, as otherwise
+        // GTK and gtk4-layer-shell would abort later on
+        if !headless {
+            gtk::init().map_err(|_| StartupError::NoDisplay)?;
+            if !gtk4_layer_shell::is_supported() {
+                return Err(StartupError::NoLayerShell);
+            }
+        }
+        // Let other daemons started with a flag like --replace take over
+        gio::ApplicationFlags::IS_SERVICE | gio::ApplicationFlags::ALLOW_REPLACEMENT
+    } else {
+        Default::default()
+    };
+
+    // A GTK application requires a display, so headless mode makes do with a plain GIO one
+    let app: gio::Application = if headless {
+        gio::Application::new(Some(NOTIFICATIONS_IFACE), flags)
+    } else {
+        gtk::Application::new(Some(NOTIFICATIONS_IFACE), flags).upcast()
+    };
+    app.register(Option::<&gio::Cancellable>::None)?;
+
+    let dbus_conn = app.dbus_connection().ok_or_else(|| {
+        StartupError::Dbus(glib::Error::new(
+            gio::IOErrorEnum::NotConnected,
+            "No session bus connection",
+        ))
+    })?;
+
+    let node_info = gio::DBusNodeInfo::for_xml(INTERFACE_XML).unwrap();
+
+    let control_iface = node_info.lookup_interface(CONTROL_IFACE).unwrap();
+
+    let control_proxy = gio::DBusProxy::new_sync(
+        &dbus_conn,
+        gio::DBusProxyFlags::empty(),
+        Some(&control_iface),
+        Some(NOTIFICATIONS_IFACE),
+        CONTROL_PATH,
+        CONTROL_IFACE,
+        Option::<&gio::Cancellable>::None,
+    )?;
+
+    match command {
+        Command::SetOffset { offset } => {
+            control_proxy.call_sync(
+                "SetOffset",
+                Some(&(offset,).to_variant()),
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::Level { level } => {
+            let property_proxy = gio::DBusProxy::new_sync(
+                &dbus_conn,
+                gio::DBusProxyFlags::empty(),
+                Some(&control_iface),
+                Some(NOTIFICATIONS_IFACE),
+                CONTROL_PATH,
+                "org.freedesktop.DBus.Properties",
+                Option::<&gio::Cancellable>::None,
+            )?;
+            match level {
+                Some(level) => {
+                    property_proxy.call_sync(
+                        "Set",
+                        Some(&(level,).to_variant()),
+                        gio::DBusCallFlags::NONE,
+                        100,
+                        Option::<&gio::Cancellable>::None,
+                    )?;
+                }
+                None => {
+                    let level: (NotificationLevel,) =
+                        FromVariant::from_variant(&property_proxy.call_sync(
+                            "Get",
+                            None,
+                            gio::DBusCallFlags::NONE,
+                            100,
+                            Option::<&gio::Cancellable>::None,
+                        )?)
+                        .unwrap();
+                    output::print(
+                        &output::Level {
+                            level: level.0.to_string(),
+                        },
+                        args.json,
+                    );
+                }
+            }
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::Set { key, value, clear } => {
+            let reply = if clear {
+                control_proxy.call_sync(
+                    "ClearOptions",
+                    None,
+                    gio::DBusCallFlags::NONE,
+                    1000,
+                    Option::<&gio::Cancellable>::None,
+                )?
+            } else {
+                control_proxy.call_sync(
+                    "SetOption",
+                    Some(&(key.unwrap_or_default(), value.unwrap_or_default()).to_variant()),
+                    gio::DBusCallFlags::NONE,
+                    1000,
+                    Option::<&gio::Cancellable>::None,
+                )?
+            };
+            let reload = reply.get::<ReloadOutcome>().map(output::Reload::from);
+            if let Some(reload) = &reload {
+                output::print(reload, args.json);
+            }
+            app.run_with_args(&Vec::<String>::new());
+            if !reload.is_some_and(|reload| reload.ok()) {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Reload => {
+            let reply = match control_proxy.call_sync(
+                "Reload",
+                None,
+                gio::DBusCallFlags::NONE,
+                1000,
+                Option::<&gio::Cancellable>::None,
+            ) {
+                Ok(reply) => reply,
+                // The daemon may still be reloading, so this is not known to have failed
+                Err(why) if why.matches(gio::IOErrorEnum::TimedOut) => {
+                    log::warn!(
+                        "The daemon did not answer in time, the result of the reload is unknown"
+                    );
+                    // Same as timeout(1)
+                    return Ok(ExitCode::from(124));
+                }
+                Err(why) => return Err(why.into()),
+            };
+            let reload = reply.get::<ReloadOutcome>().map(output::Reload::from);
+            if let Some(reload) = &reload {
+                output::print(reload, args.json);
+            }
+            app.run_with_args(&Vec::<String>::new());
+            // Makes editing the config, reloading and checking that it went well scriptable
+            if !reload.is_some_and(|reload| reload.ok()) {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Daemon { headless, overlay } => {
+            let _hold_guard = app.hold();
+            start_daemon(&app, &dbus_conn, headless.then(Headless::default), overlay)?;
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::Panel {
+            action: PanelAction::Toggle,
+        } => {
+            control_proxy.call_sync(
+                "TogglePanel",
+                None,
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::Config => {
+            let reply = control_proxy.call_sync(
+                "GetConfig",
+                None,
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            if let Some((config, options)) = reply.get::<(String, Vec<(String, String)>)>() {
+                let options = options
+                    .into_iter()
+                    .map(|(key, source)| output::SetOption { key, source })
+                    .collect();
+                output::print(
+                    &output::RunningConfig {
+                        config: config.parse().unwrap_or_default(),
+                        options,
+                    },
+                    args.json,
+                );
+            }
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::Keyboard => {
+            control_proxy.call_sync(
+                "KeyboardMode",
+                None,
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::List { snoozed } => {
+            let reply = control_proxy.call_sync(
+                "ListNotifications",
+                Some(&(snoozed,).to_variant()),
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            if let Some((notifications,)) = reply.get::<(Vec<(u32, String, String)>,)>() {
+                let notifications = notifications
+                    .into_iter()
+                    .map(|(id, app_name, summary)| output::ListedNotification {
+                        id,
+                        app_name,
+                        summary,
+                    })
+                    .collect();
+                output::print(&output::NotificationList(notifications), args.json);
+            }
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::Close {
+            app: Some(app_name),
+            regex,
+            ..
+        } => {
+            let reply = control_proxy.call_sync(
+                "CloseApp",
+                Some(&(app_name, regex).to_variant()),
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            if let Some((closed,)) = reply.get::<(u32,)>() {
+                output::print(&output::Closed { closed }, args.json);
+            }
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::Close { id: Some(id), .. } => {
+            dbus_conn.call_sync(
+                Some(NOTIFICATIONS_IFACE),
+                NOTIFICATIONS_PATH,
+                NOTIFICATIONS_IFACE,
+                "CloseNotification",
+                Some(&(id,).to_variant()),
+                None,
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::History {
+            action: HistoryAction::Export { path, force },
+        } => {
+            let reply = control_proxy.call_sync(
+                "GetHistory",
+                None,
+                gio::DBusCallFlags::NONE,
+                1000,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            let (records,) = reply.get::<(Vec<HistoryRecord>,)>().unwrap_or_default();
+
+            let result = if path.as_os_str() == "-" {
+                history::export(&records, io::BufWriter::new(io::stdout().lock()))
+            } else {
+                // Refuse to overwrite anything unless asked to
+                let mut options = fs::OpenOptions::new();
+                options.write(true);
+                if force {
+                    options.create(true).truncate(true);
+                } else {
+                    options.create_new(true);
+                }
+                options
+                    .open(&path)
+                    .and_then(|file| history::export(&records, io::BufWriter::new(file)))
+            };
+
+            if let Err(why) = result {
+                output::error(
+                    &format!("Failed to export history to {}: {why}", path.display()),
+                    args.json,
+                );
+                return Ok(ExitCode::FAILURE);
+            }
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::MinUrgency {
+            urgency: Some(urgency),
+        } => {
+            control_proxy.call_sync(
+                "SetMinUrgency",
+                Some(&(urgency.to_string(),).to_variant()),
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::MinUrgency { urgency: None } => {
+            let reply = control_proxy.call_sync(
+                "MinUrgencyStatus",
+                None,
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            let (urgency, suppressed) = reply.get::<(String, u32)>().unwrap_or_default();
+            output::print(
+                &output::MinUrgency {
+                    urgency,
+                    suppressed,
+                },
+                args.json,
+            );
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::Dnd {
+            action: DndAction::Status,
+        } => {
+            let reply = control_proxy.call_sync(
+                "DndStatus",
+                None,
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            let (enabled, source, queued) = reply.get::<(bool, String, u32)>().unwrap_or_default();
+            output::print(
+                &output::DndStatus {
+                    enabled,
+                    source,
+                    queued,
+                },
+                args.json,
+            );
+            app.run_with_args(&Vec::<String>::new());
+            // Scripts can check the state without parsing the output
+            return Ok(if enabled {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
+        }
+        Command::Stats { reset: true } => {
+            control_proxy.call_sync(
+                "ResetStats",
+                None,
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::Stats { reset: false } => {
+            let reply = control_proxy.call_sync(
+                "GetStats",
+                None,
+                gio::DBusCallFlags::NONE,
+                100,
+                Option::<&gio::Cancellable>::None,
+            )?;
+            let (apps, uptime) = reply
+                .get::<(HashMap<String, AppStats>, u64)>()
+                .unwrap_or_default();
+            output::print(&stats::Report { apps, uptime }, args.json);
+            app.run_with_args(&Vec::<String>::new());
+        }
+        Command::Close {
+            id: None,
+            app: None,
+            ..
+        } => unreachable!("clap requires either an ID or --app"),
+        Command::Wait { id, timeout } => {
+            let resolved = match wait(&dbus_conn, timeout, || Ok(id))? {
+                WaitOutcome::Closed(reason) => output::Resolved::closed(reason),
+                WaitOutcome::Action(action) => output::Resolved::Action(action),
+                // Same as timeout(1)
+                WaitOutcome::TimedOut => return Ok(ExitCode::from(124)),
+            };
+            output::print(&resolved, args.json);
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Send {
+            summary,
+            body,
+            app_name,
+            actions,
+            expire_time,
+            wait: wait_for_action,
+            wait_timeout,
+        } => {
+            let actions = actions
+                .into_iter()
+                .flat_map(|(key, label)| [key, label])
+                .collect::<Vec<_>>();
+            let notify = || -> Result<u32, glib::Error> {
+                let reply = dbus_conn.call_sync(
+                    Some(NOTIFICATIONS_IFACE),
+                    NOTIFICATIONS_PATH,
+                    NOTIFICATIONS_IFACE,
+                    "Notify",
+                    Some(
+                        &(
+                            app_name,
+                            0u32,
+                            "",
+                            summary,
+                            body.unwrap_or_default(),
+                            actions,
+                            HashMap::<String, glib::Variant>::new(),
+                            expire_time,
+                        )
+                            .to_variant(),
+                    ),
+                    None,
+                    gio::DBusCallFlags::NONE,
+                    1000,
+                    Option::<&gio::Cancellable>::None,
+                )?;
+                Ok(reply.get::<(u32,)>().map(|(id,)| id).unwrap_or_default())
+            };
+
+            if !wait_for_action {
+                output::print(&output::Sent { id: notify()? }, args.json);
+                app.run_with_args(&Vec::<String>::new());
+                return Ok(ExitCode::SUCCESS);
+            }
+            let (action, exit_code) = match wait(&dbus_conn, wait_timeout, notify)? {
+                WaitOutcome::Action(action) => (Some(action), ExitCode::SUCCESS),
+                WaitOutcome::Closed(_) => (None, ExitCode::FAILURE),
+                WaitOutcome::TimedOut => return Ok(ExitCode::from(124)),
+            };
+            output::print(&output::Invoked { action }, args.json);
+            return Ok(exit_code);
+        }
+        Command::CheckConfig { .. } => {
+            unreachable!("handled before registering the application")
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// How waiting for a notification ended
+enum WaitOutcome {
+    Closed(NotificationCloseReason),
+    Action(String),
+    TimedOut,
+}
+
+/// Wait until the notification whose ID `notify` returns is closed or one of its actions is
+/// invoked.
+///
+/// The signals are subscribed to before `notify` is called, so that a notification closed
+/// right after it is sent can't be missed.
+fn wait(
+    conn: &gio::DBusConnection,
+    timeout: Option<u32>,
+    notify: impl FnOnce() -> Result<u32, glib::Error>,
+) -> Result<WaitOutcome, glib::Error> {
+    let main_loop = glib::MainLoop::new(None, false);
+    let id = Rc::new(Cell::new(None));
+    let outcome = Rc::new(RefCell::new(WaitOutcome::TimedOut));
+
+    let _subscription = conn.subscribe_to_signal(
+        Some(NOTIFICATIONS_IFACE),
+        Some(NOTIFICATIONS_IFACE),
+        None,
+        Some(NOTIFICATIONS_PATH),
+        None,
+        gio::DBusSignalFlags::NONE,
+        glib::clone!(
+            #[strong]
+            main_loop,
+            #[strong]
+            id,
+            #[strong]
+            outcome,
+            move |signal| {
+                // Signals are only dispatched once the main loop runs, after the ID is known
+                let Some(id) = id.get() else {
+                    return;
+                };
+                let resolved = match signal.signal_name {
+                    "NotificationClosed" => signal
+                        .parameters
+                        .get::<(u32, u32)>()
+                        .filter(|(signal_id, _)| *signal_id == id)
+                        .map(|(_, reason)| {
+                            WaitOutcome::Closed(NotificationCloseReason::from(reason))
+                        }),
+                    "ActionInvoked" => signal
+                        .parameters
+                        .get::<(u32, String)>()
+                        .filter(|(signal_id, _)| *signal_id == id)
+                        .map(|(_, action)| WaitOutcome::Action(action)),
+                    _ => None,
+                };
+
+                if let Some(resolved) = resolved {
+                    *outcome.borrow_mut() = resolved;
+                    main_loop.quit();
+                }
+            }
+        ),
+    );
+    id.set(Some(notify()?));
+
+    if let Some(timeout) = timeout {
+        glib::timeout_add_local_once(
+            Duration::from_secs(timeout as u64),
+            glib::clone!(
+                #[strong]
+                main_loop,
+                move || main_loop.quit()
+            ),
+        );
+    }
+
+    main_loop.run();
+    Ok(outcome.replace(WaitOutcome::TimedOut))
+}
@@ -0,0 +1,1157 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use gtk::{gdk, pango, prelude::*};
+use gtk4::{self as gtk, glib};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    StartupError,
+    app_matcher::AppMatcher,
+    config_keys::{self, UnknownKey},
+    config_overlay::ConfigOverlay,
+    hooks::Hook,
+    keys::Keys,
+    layer::Position,
+    monitor::{self, Output},
+    notification::{NotificationCloseReason, NotificationInit, Urgency},
+    sound::SoundConfig,
+};
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct AppOverride {
+    pub app_name: Option<String>,
+    /// Matched against the desktop-entry hint, ignoring case and the `.desktop` suffix
+    pub desktop_entry: Option<String>,
+    pub timeout: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_max_lines_override")]
+    pub max_lines: Option<i32>,
+    pub body_ellipsize: Option<BodyEllipsize>,
+    pub media_layout: Option<MediaLayout>,
+    pub low_urgency_compact: Option<bool>,
+    pub critical_sticky: Option<bool>,
+    /// Never play sounds for the notifications of the application
+    pub silent: Option<bool>,
+    /// Never expire the notifications of the application, whatever their timeout
+    pub sticky: Option<bool>,
+    /// Keep the notifications of the application in the history, false being the same as
+    /// listing it in `history_ignore`
+    pub history: Option<bool>,
+    /// Replace the body of the notifications of the application with `hidden_body`, true being
+    /// the same as listing it in `hide_body_apps`
+    pub hide_body: Option<bool>,
+    pub max_per_app: Option<usize>,
+    pub summary_format: Option<String>,
+    pub body_format: Option<String>,
+    pub replace_timeout: Option<ReplaceTimeout>,
+}
+
+impl AppOverride {
+    /// Whether the override applies to the application, which has to match both selectors
+    /// if both are given
+    pub fn matches(&self, app_name: &str, desktop_entry: Option<&str>) -> bool {
+        if self.app_name.is_none() && self.desktop_entry.is_none() {
+            return false;
+        }
+        self.app_name.as_ref().is_none_or(|name| name == app_name)
+            && self.desktop_entry.as_ref().is_none_or(|entry| {
+                desktop_entry.is_some_and(|desktop_entry| {
+                    let strip = |entry: &str| entry.strip_suffix(".desktop").unwrap_or(entry);
+                    strip(entry).eq_ignore_ascii_case(strip(desktop_entry))
+                })
+            })
+    }
+
+    /// How the override is referred to, by its selectors
+    pub fn selector(&self) -> String {
+        match (&self.app_name, &self.desktop_entry) {
+            (Some(app_name), Some(desktop_entry)) => format!("{app_name} ({desktop_entry})"),
+            (Some(app_name), None) => app_name.clone(),
+            (None, Some(desktop_entry)) => desktop_entry.clone(),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// A line count, where 0 hides the body
+fn deserialize_max_lines<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<i32, D::Error> {
+    let max_lines = i32::deserialize(deserializer)?;
+    if max_lines < 0 {
+        return Err(serde::de::Error::custom(format!(
+            "max_lines must be 0 or more, 0 hiding the body, not {max_lines}"
+        )));
+    }
+    Ok(max_lines)
+}
+
+fn deserialize_max_lines_override<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<i32>, D::Error> {
+    deserialize_max_lines(deserializer).map(Some)
+}
+
+/// Options applied when notifications are placed on a specific output
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct OutputOverride {
+    /// Connector name or description of the output
+    pub output: String,
+    pub width: Option<i32>,
+    pub position: Option<Position>,
+    pub margin_anchor: Option<i32>,
+    pub margin_side: Option<i32>,
+    pub max_visible: Option<usize>,
+}
+
+/// Multipliers for the timeouts of notifications of each urgency
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct TimeoutScale {
+    pub low: f64,
+    pub normal: f64,
+    pub critical: f64,
+}
+
+impl Default for TimeoutScale {
+    fn default() -> Self {
+        Self {
+            low: 1.0,
+            normal: 1.0,
+            critical: 1.0,
+        }
+    }
+}
+
+impl TimeoutScale {
+    pub fn for_urgency(&self, urgency: Urgency) -> f64 {
+        match urgency {
+            Urgency::Low => self.low,
+            Urgency::Normal => self.normal,
+            Urgency::Critical => self.critical,
+        }
+    }
+}
+
+/// Placement of critical notifications, separate from the rest
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct CriticalConfig {
+    pub position: Position,
+    pub margin_anchor: Option<i32>,
+    pub margin_side: Option<i32>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub enum ConfigLayer {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
+
+impl From<ConfigLayer> for gtk4_layer_shell::Layer {
+    fn from(value: ConfigLayer) -> Self {
+        match value {
+            ConfigLayer::Background => Self::Background,
+            ConfigLayer::Bottom => Self::Bottom,
+            ConfigLayer::Top => Self::Top,
+            ConfigLayer::Overlay => Self::Overlay,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Off => Self::Off,
+            LogLevel::Error => Self::Error,
+            LogLevel::Warn => Self::Warn,
+            LogLevel::Info => Self::Info,
+            LogLevel::Debug => Self::Debug,
+            LogLevel::Trace => Self::Trace,
+        }
+    }
+}
+
+/// What happens to the timeout of a notification when it is replaced
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplaceTimeout {
+    /// Start the timeout over
+    #[default]
+    Reset,
+    /// Expire at the same time as the replaced notification would have
+    Keep,
+}
+
+/// Where the body is shortened when it does not fit
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyEllipsize {
+    #[default]
+    End,
+    Middle,
+    Start,
+    /// Clipped at `max_lines` without an ellipsis
+    None,
+}
+
+impl From<BodyEllipsize> for pango::EllipsizeMode {
+    fn from(value: BodyEllipsize) -> Self {
+        match value {
+            BodyEllipsize::End => Self::End,
+            BodyEllipsize::Middle => Self::Middle,
+            BodyEllipsize::Start => Self::Start,
+            BodyEllipsize::None => Self::None,
+        }
+    }
+}
+
+/// When notifications use the layout for media players
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaLayout {
+    /// Notifications with only media control actions shown as icons
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// What happens to notifications arriving while the queue is full
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueueOverflow {
+    /// The notification that has waited the longest is dropped
+    #[default]
+    DropOldest,
+    /// The arriving notification is dropped
+    DropNewest,
+    /// Dropped notifications are counted in a single notification at the end of the queue
+    Summarize,
+}
+
+/// What clicking the row counting the queued notifications does
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowClick {
+    /// The next queued notification is shown
+    #[default]
+    Promote,
+    /// Every queued notification is shown, however many are already on the screen
+    ShowAll,
+}
+
+/// What happens when another notification daemon takes over the bus name
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnNameLost {
+    /// Close all notifications and exit
+    #[default]
+    Exit,
+    /// Keep the notifications on the screen until they are closed, then exit
+    Linger,
+}
+
+/// The direction notifications are laid out in
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TextDirection {
+    /// Detected from the text of each notification
+    #[default]
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+/// Whether notifications reserve space on the screen
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExclusiveZone {
+    #[default]
+    None,
+    /// Sticky notifications reserve their own size
+    Auto,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub(crate) width: i32,
+    pub(crate) spacing: i32,
+    pub(crate) margin_anchor: i32,
+    pub(crate) margin_side: i32,
+    pub(crate) position: Position,
+    /// Outputs tried in order, letting the compositor choose if empty
+    #[serde(deserialize_with = "monitor::deserialize_outputs")]
+    pub(crate) output: Vec<Output>,
+    pub(crate) timeout: u32,
+    /// Multiplies the timeouts by urgency, 0 making notifications sticky
+    pub(crate) timeout_scale: TimeoutScale,
+    /// Critical notifications never time out, whatever the timeout
+    pub(crate) critical_sticky: bool,
+    pub(crate) layer: ConfigLayer,
+    /// Layers for notifications of each urgency, `layer` if unset
+    pub(crate) layer_low: Option<ConfigLayer>,
+    pub(crate) layer_normal: Option<ConfigLayer>,
+    pub(crate) layer_critical: Option<ConfigLayer>,
+    /// Maximum amount of text lines in notification body, 0 hiding the body
+    #[serde(deserialize_with = "deserialize_max_lines")]
+    pub(crate) max_lines: i32,
+    pub(crate) body_ellipsize: BodyEllipsize,
+    pub(crate) icon_size: i32,
+    /// Show the app icon on the corner of the image when a notification has both
+    pub(crate) show_app_icon_badge: bool,
+    /// Show a themed icon for the urgency on notifications without any icon
+    pub(crate) fallback_icons: bool,
+    pub(crate) fallback_icon_low: String,
+    pub(crate) fallback_icon_normal: String,
+    pub(crate) fallback_icon_critical: String,
+    pub(crate) media_layout: MediaLayout,
+    /// The size of the album art in the media layout
+    pub(crate) media_icon_size: i32,
+    /// Show low urgency notifications as a single row with the icon and summary
+    pub(crate) low_urgency_compact: bool,
+    /// Pango font description used for notifications, like "Inter 11"
+    pub(crate) font: Option<String>,
+    /// Overrides `font` for the summary
+    pub(crate) summary_font: Option<String>,
+    /// Overrides `font` for the body
+    pub(crate) body_font: Option<String>,
+    /// Multiplier for font sizes
+    pub(crate) font_scale: f64,
+    /// Largest accepted image-data hint, larger images are ignored
+    pub(crate) max_image_bytes: usize,
+    /// Format string for the summary, see `format::expand`
+    pub(crate) summary_format: String,
+    /// Format string for the body, see `format::expand`
+    pub(crate) body_format: String,
+    pub(crate) exclusive_zone: ExclusiveZone,
+    pub(crate) text_direction: TextDirection,
+    pub(crate) replace_timeout: ReplaceTimeout,
+    pub(crate) log_level: LogLevel,
+    /// Reject configs with unknown options instead of warning about them
+    pub(crate) strict: bool,
+    /// Serve the notification portal backend for sandboxed applications
+    pub(crate) portal: bool,
+    /// Keep received notifications around after they are closed
+    pub(crate) history: bool,
+    /// Maximum amount of notifications kept in the history
+    pub(crate) history_size: usize,
+    /// Applications whose notifications are never kept in the history or the notification log
+    pub(crate) history_ignore: Vec<AppMatcher>,
+    /// Applications whose notifications are shown with `hidden_body` instead of their body
+    pub(crate) hide_body_apps: Vec<AppMatcher>,
+    /// Shown in place of hidden bodies
+    pub(crate) hidden_body: String,
+    /// Keep the real body of notifications with a hidden body in the history, the notification
+    /// log and hooks
+    pub(crate) record_hidden_body: bool,
+    /// Maximum amount of notifications on the screen at once, 0 for no limit.
+    /// The rest wait for their turn.
+    pub(crate) max_visible: usize,
+    /// How many notifications critical ones may exceed `max_visible` by
+    pub(crate) critical_overflow: usize,
+    /// How many notifications are shown before older ones are collapsed into a stack,
+    /// 0 to disable
+    pub(crate) stack_after: usize,
+    /// Maximum amount of notifications waiting for room on the screen, 0 for no limit
+    pub(crate) queue_limit: usize,
+    pub(crate) queue_overflow: QueueOverflow,
+    pub(crate) overflow_click: OverflowClick,
+    /// How many times reconnecting to D-Bus is attempted after losing the connection
+    pub(crate) reconnect_attempts: u32,
+    pub(crate) on_name_lost: OnNameLost,
+    /// Maximum amount of notifications from a single application on the screen at once,
+    /// 0 for no limit. The oldest one is closed to make room for a new one.
+    pub(crate) max_per_app: usize,
+    /// The reason given for notifications closed to make room under max_per_app
+    pub(crate) max_per_app_reason: NotificationCloseReason,
+    /// Seconds after which snoozed notifications are shown again, 0 disables snoozing
+    pub(crate) snooze_duration: u32,
+    /// Show the default action as a button next to the other actions
+    pub(crate) show_default_action_button: bool,
+    /// Close notifications after opening their image by clicking it
+    pub(crate) image_click_dismiss: bool,
+    /// Close notifications after a link in their body is opened
+    pub(crate) close_on_link: bool,
+    /// Turn bare URLs in the body into links
+    pub(crate) linkify: bool,
+    /// Let all pointer input through notifications to the windows underneath
+    pub(crate) click_through: bool,
+    /// Applications whose notifications are shown during do not disturb
+    pub(crate) dnd_allow: Vec<AppMatcher>,
+    /// Enable do not disturb while the screen is cast through the portal
+    pub(crate) dnd_on_screencast: bool,
+    /// Notifications less urgent than this are only recorded, not shown
+    pub(crate) min_display_urgency: Urgency,
+    /// Seconds added to the timeouts of notifications after the system wakes up
+    pub(crate) resume_grace: u32,
+    /// Milliseconds during which identical notifications are absorbed, 0 to disable
+    pub(crate) coalesce_window: u64,
+    /// Layer shell namespace of the notifications, and the prefix of the panel's
+    pub(crate) namespace: String,
+    /// Bundled style that is used as the base of the style files
+    pub(crate) theme: Option<String>,
+    /// Style files loaded in order instead of the default `style.css`
+    pub(crate) style: Option<Vec<String>>,
+    /// File that log messages are appended to in addition to stderr
+    pub(crate) log_file: Option<PathBuf>,
+    /// File that a line is appended to for every received and closed notification
+    pub(crate) log_notifications: Option<String>,
+    /// Whether notifications with the transient hint are written to `log_notifications`
+    pub(crate) log_transient: bool,
+    // Looks nicer in TOML. Left out of the default config, where the README covers them
+    #[serde(rename = "app_override", skip_serializing_if = "Vec::is_empty")]
+    pub(crate) app_overrides: Vec<AppOverride>,
+    #[serde(rename = "output_override", skip_serializing_if = "Vec::is_empty")]
+    pub(crate) output_overrides: Vec<OutputOverride>,
+    #[serde(rename = "hook", skip_serializing_if = "Vec::is_empty")]
+    pub(crate) hooks: Vec<Hook>,
+    /// Shows critical notifications somewhere else if present
+    pub(crate) critical: Option<CriticalConfig>,
+    /// Played when notifications are shown
+    pub(crate) sounds: SoundConfig,
+    /// Keys of the keyboard mode
+    pub(crate) keys: Keys,
+    /// Milliseconds after a sound during which further sounds of the same urgency are
+    /// skipped, 0 to disable
+    pub(crate) sound_cooldown_ms: u64,
+}
+
+/// Builds a config from top-level options, written like in the config file, leaving the rest
+/// at their defaults
+#[derive(Default)]
+pub struct ConfigBuilder {
+    table: toml::Table,
+}
+
+impl ConfigBuilder {
+    /// Set a top-level option that takes a single value, like `yand set` does
+    pub fn option(mut self, key: &str, value: &str) -> Result<Self, String> {
+        let option = format!("{key}={value}").parse::<ConfigOverlay>()?;
+        ConfigOverlay::apply(&[option], &mut self.table);
+        Ok(self)
+    }
+
+    /// The config, with the defaults for the options that were not set
+    pub fn build(self) -> Config {
+        // Every option was checked on its own when it was set
+        self.table.try_into().unwrap_or_default()
+    }
+}
+
+/// The overridden fields
+#[derive(Default)]
+pub struct ConfigOverrides {
+    pub timeout: bool,
+    pub max_lines: bool,
+    pub sticky: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            width: 400,
+            spacing: 20,
+            margin_side: 10,
+            margin_anchor: 10,
+            position: Position::TopRight,
+            output: Vec::new(),
+            timeout: 10,
+            timeout_scale: TimeoutScale::default(),
+            critical_sticky: false,
+            layer: ConfigLayer::Overlay,
+            layer_low: None,
+            layer_normal: None,
+            layer_critical: None,
+            max_lines: 5,
+            body_ellipsize: BodyEllipsize::End,
+            icon_size: 64,
+            show_app_icon_badge: false,
+            fallback_icons: false,
+            fallback_icon_low: "dialog-information".to_string(),
+            fallback_icon_normal: "dialog-information".to_string(),
+            fallback_icon_critical: "dialog-error".to_string(),
+            media_layout: MediaLayout::Auto,
+            media_icon_size: 96,
+            low_urgency_compact: false,
+            font: None,
+            summary_font: None,
+            body_font: None,
+            font_scale: 1.0,
+            max_image_bytes: 8 * 1024 * 1024,
+            summary_format: "%s".to_string(),
+            body_format: "%b".to_string(),
+            exclusive_zone: ExclusiveZone::None,
+            text_direction: TextDirection::Auto,
+            replace_timeout: ReplaceTimeout::Reset,
+            log_level: LogLevel::Info,
+            strict: false,
+            portal: false,
+            history: false,
+            history_size: 100,
+            history_ignore: vec![],
+            hide_body_apps: vec![],
+            hidden_body: String::new(),
+            record_hidden_body: false,
+            coalesce_window: 0,
+            resume_grace: 0,
+            snooze_duration: 0,
+            show_default_action_button: false,
+            image_click_dismiss: false,
+            close_on_link: false,
+            linkify: false,
+            click_through: false,
+            dnd_allow: vec![],
+            dnd_on_screencast: false,
+            min_display_urgency: Urgency::Low,
+            max_visible: 0,
+            stack_after: 0,
+            critical_overflow: 1,
+            queue_limit: 50,
+            queue_overflow: QueueOverflow::DropOldest,
+            overflow_click: OverflowClick::Promote,
+            max_per_app: 0,
+            max_per_app_reason: NotificationCloseReason::Expired,
+            reconnect_attempts: 10,
+            on_name_lost: OnNameLost::Exit,
+            namespace: "yand".to_string(),
+            theme: None,
+            style: None,
+            log_file: None,
+            log_notifications: None,
+            log_transient: false,
+            app_overrides: vec![],
+            output_overrides: vec![],
+            hooks: vec![],
+            critical: None,
+            sounds: SoundConfig::default(),
+            keys: Keys::default(),
+            sound_cooldown_ms: 1000,
+        }
+    }
+}
+
+impl Config {
+    /// Read the config file and the files in `config.d` next to it, like the daemon does
+    pub fn load(config_path: &Path) -> Result<Self, String> {
+        read_config(config_path, &[]).map(|read| read.config)
+    }
+
+    /// Set options one by one instead of reading them from a file
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// The width of notifications
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    /// The seconds before notifications expire when the application leaves it to the daemon
+    pub fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// The lines of the body shown before it is cut off
+    pub fn max_lines(&self) -> i32 {
+        self.max_lines
+    }
+
+    /// The size of the icon of notifications
+    pub fn icon_size(&self) -> i32 {
+        self.icon_size
+    }
+
+    /// How many notifications are shown at once, 0 for no limit
+    pub fn max_visible(&self) -> usize {
+        self.max_visible
+    }
+
+    /// Whether closed notifications are kept in the history
+    pub fn history(&self) -> bool {
+        self.history
+    }
+
+    /// The icon name shown on notifications of the given urgency that have no icon
+    pub(crate) fn fallback_icon_for(&self, urgency: Urgency) -> &str {
+        match urgency {
+            Urgency::Low => &self.fallback_icon_low,
+            Urgency::Normal => &self.fallback_icon_normal,
+            Urgency::Critical => &self.fallback_icon_critical,
+        }
+    }
+
+    /// The layer that notifications of the given urgency are placed on
+    pub(crate) fn layer_for(&self, urgency: Urgency) -> gtk4_layer_shell::Layer {
+        match urgency {
+            Urgency::Low => &self.layer_low,
+            Urgency::Normal => &self.layer_normal,
+            Urgency::Critical => &self.layer_critical,
+        }
+        .as_ref()
+        .unwrap_or(&self.layer)
+        .clone()
+        .into()
+    }
+
+    /// Return the same config with the override for the given monitor applied
+    pub(crate) fn for_monitor(mut self, monitor: Option<&gdk::Monitor>) -> Self {
+        let Some(monitor) = monitor else {
+            return self;
+        };
+        let Some(output_override) = self.output_overrides.iter().find(|output_override| {
+            monitor.connector().as_deref() == Some(&output_override.output)
+                || monitor.description().as_deref() == Some(&output_override.output)
+        }) else {
+            return self;
+        };
+
+        if let Some(val) = output_override.width {
+            self.width = val;
+        }
+        if let Some(val) = output_override.position {
+            self.position = val;
+        }
+        if let Some(val) = output_override.margin_anchor {
+            self.margin_anchor = val;
+        }
+        if let Some(val) = output_override.margin_side {
+            self.margin_side = val;
+        }
+        if let Some(val) = output_override.max_visible {
+            self.max_visible = val;
+        }
+        self
+    }
+
+    /// Options that are valid on their own but don't work together
+    pub(crate) fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for app_override in &self.app_overrides {
+            if app_override.app_name.is_none() && app_override.desktop_entry.is_none() {
+                warnings.push(
+                    "an app_override without app_name or desktop_entry never applies".to_string(),
+                );
+            }
+        }
+        if !self.click_through {
+            return warnings;
+        }
+        // Nothing but timeouts and yand commands can close notifications then
+        if self.timeout == 0 {
+            warnings.push(
+                "click_through with timeout = 0 leaves notifications open until `yand close`"
+                    .to_string(),
+            );
+        }
+        for app_override in &self.app_overrides {
+            if app_override.timeout == Some(0) {
+                warnings.push(format!(
+                    "click_through with timeout = 0 for {} leaves its notifications open until \
+                     `yand close`",
+                    app_override.selector()
+                ));
+            }
+        }
+        warnings
+    }
+
+    /// The app override of the application
+    pub(crate) fn app_override(
+        &self,
+        app_name: &str,
+        desktop_entry: Option<&str>,
+    ) -> Option<&AppOverride> {
+        self.app_overrides
+            .iter()
+            .find(|app_override| app_override.matches(app_name, desktop_entry))
+    }
+
+    /// Whether sounds are turned off for the application by its app override
+    pub fn silent(&self, app_name: &str, desktop_entry: Option<&str>) -> bool {
+        self.app_override(app_name, desktop_entry)
+            .and_then(|app_override| app_override.silent)
+            .unwrap_or(false)
+    }
+
+    /// The sound played when the notification is shown, if any. Transient notifications, the
+    /// ones asking for no sound and those of silent applications are never heard, and during do
+    /// not disturb only the allowed applications are.
+    pub(crate) fn sound(&self, init: &NotificationInit, dnd: bool) -> Option<&str> {
+        if init.hints.transient.unwrap_or_default()
+            || init.hints.suppress_sound.unwrap_or_default()
+            || self.silent(&init.app_name, init.hints.desktop_entry.as_deref())
+            || (dnd && !self.allowed_during_dnd(init))
+        {
+            return None;
+        }
+        self.sounds
+            .for_urgency(init.hints.urgency.unwrap_or_default())
+    }
+
+    /// Whether the notifications of the application never expire by its app override
+    pub fn sticky(&self, app_name: &str, desktop_entry: Option<&str>) -> bool {
+        self.app_override(app_name, desktop_entry)
+            .and_then(|app_override| app_override.sticky)
+            .unwrap_or(false)
+    }
+
+    /// Whether notifications from the application may be kept in the history and the
+    /// notification log
+    pub fn recorded(&self, app_name: &str, desktop_entry: Option<&str>) -> bool {
+        let ignored = self.history_ignore.iter().any(|matcher| {
+            matcher.matches(app_name) || desktop_entry.is_some_and(|entry| matcher.matches(entry))
+        });
+        !ignored
+            && self
+                .app_override(app_name, desktop_entry)
+                .and_then(|app_override| app_override.history)
+                .unwrap_or(true)
+    }
+
+    /// Whether the body of notifications from the application is replaced by `hidden_body`
+    pub fn hides_body(&self, app_name: &str, desktop_entry: Option<&str>) -> bool {
+        self.app_override(app_name, desktop_entry)
+            .and_then(|app_override| app_override.hide_body)
+            .unwrap_or_else(|| {
+                self.hide_body_apps.iter().any(|matcher| {
+                    matcher.matches(app_name)
+                        || desktop_entry.is_some_and(|entry| matcher.matches(entry))
+                })
+            })
+    }
+
+    /// Whether notifications from the application are shown during do not disturb, matching
+    /// either the app name or the desktop entry
+    pub fn allowed_during_dnd(&self, init: &NotificationInit) -> bool {
+        self.dnd_allow.iter().any(|matcher| {
+            matcher.matches(&init.app_name)
+                || init
+                    .hints
+                    .desktop_entry
+                    .as_deref()
+                    .is_some_and(|desktop_entry| matcher.matches(desktop_entry))
+        })
+    }
+
+    /// The position of notifications of the given urgency
+    pub(crate) fn position_for(&self, urgency: Urgency) -> Position {
+        match (urgency, &self.critical) {
+            (Urgency::Critical, Some(critical)) => critical.position,
+            _ => self.position,
+        }
+    }
+
+    /// Return the same config with the placement for notifications of the given urgency
+    pub(crate) fn with_urgency(mut self, urgency: Urgency) -> Self {
+        if let (Urgency::Critical, Some(critical)) = (urgency, &self.critical) {
+            self.position = critical.position;
+            self.margin_anchor = critical.margin_anchor.unwrap_or(self.margin_anchor);
+            self.margin_side = critical.margin_side.unwrap_or(self.margin_side);
+        }
+        self
+    }
+
+    /// Return the same config entry with overridden options
+    pub(crate) fn overridden(
+        mut self,
+        app_name: &str,
+        desktop_entry: Option<&str>,
+    ) -> (Self, ConfigOverrides) {
+        let Some(app_override) = self.app_override(app_name, desktop_entry) else {
+            return (self, ConfigOverrides::default());
+        };
+        let mut overrides = ConfigOverrides::default();
+
+        if let Some(val) = app_override.max_lines {
+            self.max_lines = val;
+            overrides.max_lines = true;
+        }
+        if let Some(val) = app_override.body_ellipsize {
+            self.body_ellipsize = val;
+        }
+        if let Some(val) = app_override.media_layout {
+            self.media_layout = val;
+        }
+        if let Some(val) = app_override.low_urgency_compact {
+            self.low_urgency_compact = val;
+        }
+        if let Some(val) = app_override.critical_sticky {
+            self.critical_sticky = val;
+        }
+        if let Some(val) = app_override.max_per_app {
+            self.max_per_app = val;
+        }
+        if let Some(val) = app_override.timeout {
+            self.timeout = val;
+            overrides.timeout = true;
+        }
+        if let Some(val) = app_override.sticky {
+            overrides.sticky = val;
+        }
+        if let Some(val) = &app_override.summary_format {
+            self.summary_format = val.clone();
+        }
+        if let Some(val) = &app_override.body_format {
+            self.body_format = val.clone();
+        }
+        if let Some(val) = app_override.replace_timeout {
+            self.replace_timeout = val;
+        }
+        (self, overrides)
+    }
+}
+
+/// The config as read from the config file and the files in `config.d` next to it
+pub struct ReadConfig {
+    pub config: Config,
+    /// The files in `config.d` that were skipped, with why
+    pub skipped: Vec<String>,
+    /// The `config.d` file that last set each top-level option
+    pub origins: HashMap<String, PathBuf>,
+    /// Options that are not used by anything, unless the config is strict
+    pub unknown: Vec<UnknownKey>,
+}
+
+impl ReadConfig {
+    /// Mention the `config.d` file the changed option came from, if any
+    pub fn with_origin(&self, change: String) -> String {
+        let key = change
+            .split([':', '[', '.'])
+            .next()
+            .unwrap_or_default()
+            .trim();
+        match self.origins.get(key) {
+            Some(path) => format!("{change} (from {})", path.display()),
+            None => change,
+        }
+    }
+}
+
+/// Read the config file, which is entirely optional, merge the files in `config.d` over it
+/// in order, and lay the options from the command line over all of them
+pub fn read_config(config_path: &Path, overlay: &[ConfigOverlay]) -> Result<ReadConfig, String> {
+    let str = match fs::read_to_string(config_path) {
+        Ok(str) => str,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(why) => return Err(why.to_string()),
+    };
+    // Parsed as is first, so that errors point to where they are in the file
+    toml::from_str::<Config>(&str).map_err(|why| why.to_string())?;
+    let mut table = toml::from_str::<toml::Table>(&str).map_err(|why| why.to_string())?;
+
+    let mut skipped = Vec::new();
+    let mut origins = HashMap::new();
+    for path in config_d_files(config_path) {
+        // A broken file is left out rather than taking the rest of the config with it
+        let fragment = fs::read_to_string(&path)
+            .map_err(|why| why.to_string())
+            .and_then(|str| {
+                toml::from_str::<Config>(&str).map_err(|why| why.to_string())?;
+                toml::from_str::<toml::Table>(&str).map_err(|why| why.to_string())
+            });
+        match fragment {
+            Ok(fragment) => {
+                for key in fragment.keys() {
+                    origins.insert(key.clone(), path.clone());
+                }
+                merge_config(&mut table, fragment);
+            }
+            Err(why) => skipped.push(format!("{}: {why}", path.display())),
+        }
+    }
+
+    // The command line only takes known options
+    let unknown = config_keys::unknown_keys(&table);
+    ConfigOverlay::apply(overlay, &mut table);
+    let config = table.try_into::<Config>().map_err(|why| why.to_string())?;
+    if config.strict && !unknown.is_empty() {
+        let unknown = unknown.iter().map(ToString::to_string).collect::<Vec<_>>();
+        return Err(unknown.join("; "));
+    }
+    Ok(ReadConfig {
+        config,
+        skipped,
+        origins,
+        unknown,
+    })
+}
+
+/// The `.toml` files in the `config.d` directory next to the config file, in lexical order
+fn config_d_files(config_path: &Path) -> Vec<PathBuf> {
+    let Some(dir) = config_path.parent().map(|dir| dir.join("config.d")) else {
+        return Vec::new();
+    };
+    let mut paths = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "toml")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Merge a config file over the config read so far. Values replace the ones before, except
+/// for tables, which are merged, and arrays of tables like `app_override`, which are added to
+fn merge_config(table: &mut toml::Table, fragment: toml::Table) {
+    for (key, value) in fragment {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(fragment)) => {
+                merge_config(table, fragment)
+            }
+            (Some(toml::Value::Array(array)), toml::Value::Array(fragment))
+                if !fragment.is_empty() && fragment.iter().all(toml::Value::is_table) =>
+            {
+                array.extend(fragment)
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The style files to load in order, skipping missing ones from the config
+pub fn style_paths(config: &Config, style_path: &Path) -> Vec<PathBuf> {
+    match &config.style {
+        Some(paths) => paths
+            .iter()
+            .map(|path| expand_path(path))
+            .filter(|path| {
+                let exists = path.exists();
+                if !exists {
+                    log::debug!("Skipping missing style file {}", path.display());
+                }
+                exists
+            })
+            .collect(),
+        None => vec![style_path.to_path_buf()],
+    }
+}
+
+/// The default config and style file paths
+pub fn default_paths() -> Result<(PathBuf, PathBuf), StartupError> {
+    let dirs = xdg::BaseDirectories::with_prefix("yand");
+
+    let config_path = dirs
+        .get_config_file("config.toml")
+        .ok_or(StartupError::NoHome)?;
+    let style_path = dirs
+        .get_config_file("style.css")
+        .ok_or(StartupError::NoHome)?;
+    Ok((config_path, style_path))
+}
+
+/// The names of the bundled themes
+pub const THEMES: &[&str] = &["default", "dark", "light", "high-contrast", "compact"];
+
+/// The bundled style, with the rules of the theme on top of it
+pub fn theme_css(theme: Option<&str>) -> String {
+    let variant = match theme {
+        None | Some("default") => "",
+        Some("dark") => include_str!("../res/themes/dark.css"),
+        Some("light") => include_str!("../res/themes/light.css"),
+        Some("high-contrast") => include_str!("../res/themes/high-contrast.css"),
+        Some("compact") => include_str!("../res/themes/compact.css"),
+        Some(theme) => {
+            log::warn!("Unknown theme {theme}, using the default one");
+            ""
+        }
+    };
+    format!("{}\n{variant}", include_str!("../res/style.css"))
+}
+
+/// Load a style file, falling back to `base` if it is missing or entirely broken
+pub fn load_style(style_path: &Path, base: &str) -> (gtk::CssProvider, Vec<String>, bool) {
+    let css_provider = gtk::CssProvider::new();
+
+    let Ok(style) = fs::read_to_string(style_path) else {
+        css_provider.load_from_string(base);
+        return (css_provider, Vec::new(), true);
+    };
+
+    let file_name = style_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let errors = Rc::new(RefCell::new(Vec::new()));
+
+    css_provider.connect_parsing_error(glib::clone!(
+        #[strong]
+        errors,
+        move |_, section, why| {
+            let location = section.start_location();
+            errors.borrow_mut().push(format!(
+                "{file_name}:{}:{} — {}",
+                location.lines() + 1,
+                location.line_chars() + 1,
+                why.message()
+            ));
+        }
+    ));
+    css_provider.load_from_string(&style);
+
+    let mut errors = errors.take();
+
+    // Nothing usable could be parsed, so the default style is better than no style at all
+    let fallback = !errors.is_empty() && css_provider.to_str().trim().is_empty();
+    if fallback {
+        errors.push(format!(
+            "{file_name} could not be parsed, falling back to the default style"
+        ));
+        css_provider.load_from_string(base);
+    }
+
+    (css_provider, errors, fallback)
+}
+
+/// Expand a leading `~` and `$VAR` or `${VAR}` environment variables in a path from the config
+pub fn expand_path(path: &str) -> PathBuf {
+    let path = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{rest}", glib::home_dir().display())
+        }
+        _ => path.to_string(),
+    };
+
+    let mut expanded = String::new();
+    let mut rest = path.as_str();
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let (name, remainder) = if let Some(braced) = rest.strip_prefix('{') {
+            match braced.split_once('}') {
+                Some(split) => split,
+                None => ("", rest),
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            rest.split_at(end)
+        };
+
+        let value = match name {
+            "" => None,
+            // Unset more often than not, in which case the default is used
+            "XDG_CONFIG_HOME" => Some(glib::user_config_dir().display().to_string()),
+            _ => env::var(name).ok(),
+        };
+
+        match value {
+            Some(value) => expanded.push_str(&value),
+            // Left as is if it can't be expanded
+            None => {
+                expanded.push_str(&path[path.len() - rest.len() - 1..path.len() - remainder.len()])
+            }
+        }
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+
+    PathBuf::from(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(app_name: &str) -> NotificationInit {
+        NotificationInit {
+            app_name: app_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn silent_apps_play_no_sound() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [sounds]
+            normal = "message-new-instant"
+            critical = "dialog-warning"
+
+            [[app_override]]
+            app_name = "Discord"
+            silent = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.sound(&notification("Firefox"), false),
+            Some("message-new-instant")
+        );
+        assert_eq!(config.sound(&notification("Discord"), false), None);
+        // Whatever the urgency
+        let mut critical = notification("Discord");
+        critical.hints.urgency = Some(Urgency::Critical);
+        assert_eq!(config.sound(&critical, false), None);
+    }
+
+    #[test]
+    fn hints_and_dnd_silence_notifications() {
+        let config = toml::from_str::<Config>(
+            r#"
+            dnd_allow = ["Alarm"]
+
+            [sounds]
+            normal = "message-new-instant"
+            "#,
+        )
+        .unwrap();
+
+        let mut suppressed = notification("Firefox");
+        suppressed.hints.suppress_sound = Some(true);
+        assert_eq!(config.sound(&suppressed, false), None);
+        assert_eq!(config.sound(&notification("Firefox"), true), None);
+        assert_eq!(
+            config.sound(&notification("Alarm"), true),
+            Some("message-new-instant")
+        );
+    }
+
+    #[test]
+    fn builder_sets_options_over_the_defaults() {
+        let config = Config::builder()
+            .option("width", "300")
+            .unwrap()
+            .option("history", "true")
+            .unwrap()
+            .build();
+        assert_eq!(config.width(), 300);
+        assert!(config.history());
+        assert_eq!(config.timeout(), Config::default().timeout());
+
+        assert!(Config::builder().option("width", "wide").is_err());
+        assert!(Config::builder().option("no_such_option", "1").is_err());
+    }
+}
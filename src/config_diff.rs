@@ -0,0 +1,200 @@
+use std::fmt::Debug;
+
+use crate::config::{AppOverride, Config, OutputOverride};
+
+/// Push a line for every listed field that differs between the two values
+macro_rules! diff_fields {
+    ($changes:expr, $prefix:expr, $old:expr, $new:expr, [$($field:ident),* $(,)?]) => {
+        $(
+            let (old, new) = (show(&$old.$field), show(&$new.$field));
+            if old != new {
+                $changes.push(format!("{}{}: {old} -> {new}", $prefix, stringify!($field)));
+            }
+        )*
+    };
+}
+
+/// Human readable lines describing how the effective config changed
+pub fn diff(old: &Config, new: &Config) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    diff_fields!(
+        changes,
+        "",
+        old,
+        new,
+        [
+            output,
+            layer,
+            layer_low,
+            layer_normal,
+            layer_critical,
+            spacing,
+            timeout,
+            timeout_scale,
+            critical_sticky,
+            width,
+            max_lines,
+            body_ellipsize,
+            icon_size,
+            show_app_icon_badge,
+            fallback_icons,
+            fallback_icon_low,
+            fallback_icon_normal,
+            fallback_icon_critical,
+            media_layout,
+            media_icon_size,
+            low_urgency_compact,
+            font,
+            summary_font,
+            body_font,
+            font_scale,
+            max_image_bytes,
+            summary_format,
+            body_format,
+            position,
+            margin_anchor,
+            margin_side,
+            text_direction,
+            exclusive_zone,
+            replace_timeout,
+            log_level,
+            strict,
+            portal,
+            history,
+            history_size,
+            max_visible,
+            critical_overflow,
+            stack_after,
+            queue_limit,
+            queue_overflow,
+            overflow_click,
+            max_per_app,
+            max_per_app_reason,
+            snooze_duration,
+            show_default_action_button,
+            image_click_dismiss,
+            close_on_link,
+            linkify,
+            click_through,
+            dnd_allow,
+            dnd_on_screencast,
+            min_display_urgency,
+            history_ignore,
+            hide_body_apps,
+            hidden_body,
+            record_hidden_body,
+            coalesce_window,
+            resume_grace,
+            reconnect_attempts,
+            on_name_lost,
+            namespace,
+            theme,
+            style,
+            log_file,
+            log_notifications,
+            log_transient,
+            critical,
+            sounds,
+            keys,
+            sound_cooldown_ms,
+        ]
+    );
+
+    diff_keyed(
+        &mut changes,
+        "app_override",
+        &old.app_overrides,
+        &new.app_overrides,
+        AppOverride::selector,
+        |changes, prefix, old: &AppOverride, new: &AppOverride| {
+            diff_fields!(
+                changes,
+                prefix,
+                old,
+                new,
+                [
+                    desktop_entry,
+                    timeout,
+                    max_lines,
+                    body_ellipsize,
+                    media_layout,
+                    low_urgency_compact,
+                    critical_sticky,
+                    silent,
+                    sticky,
+                    history,
+                    hide_body,
+                    max_per_app,
+                    summary_format,
+                    body_format,
+                    replace_timeout,
+                ]
+            );
+        },
+    );
+    diff_keyed(
+        &mut changes,
+        "output_override",
+        &old.output_overrides,
+        &new.output_overrides,
+        |output_override| output_override.output.clone(),
+        |changes, prefix, old: &OutputOverride, new: &OutputOverride| {
+            diff_fields!(
+                changes,
+                prefix,
+                old,
+                new,
+                [width, position, margin_anchor, margin_side, max_visible]
+            );
+        },
+    );
+
+    // Hooks have nothing to tell them apart by, so any change shows up as the whole list
+    if show(&old.hooks) != show(&new.hooks) {
+        changes.push(format!(
+            "hook: {} -> {} hooks, changed",
+            old.hooks.len(),
+            new.hooks.len()
+        ));
+    }
+
+    changes
+}
+
+/// Compare sections identified by a key, like the app name of app overrides
+fn diff_keyed<T>(
+    changes: &mut Vec<String>,
+    section: &str,
+    old: &[T],
+    new: &[T],
+    key: impl Fn(&T) -> String,
+    diff: impl Fn(&mut Vec<String>, &str, &T, &T),
+) {
+    for old_item in old {
+        match new.iter().find(|new_item| key(new_item) == key(old_item)) {
+            Some(new_item) => diff(
+                changes,
+                &format!("{section}[{}].", key(old_item)),
+                old_item,
+                new_item,
+            ),
+            None => changes.push(format!("{section}[{}] removed", key(old_item))),
+        }
+    }
+    for new_item in new {
+        if !old.iter().any(|old_item| key(old_item) == key(new_item)) {
+            changes.push(format!("{section}[{}] added", key(new_item)));
+        }
+    }
+}
+
+/// The value as written in the log, with unset options as `unset`
+fn show(value: &impl Debug) -> String {
+    let value = format!("{value:?}");
+    match value.strip_prefix("Some(") {
+        Some(inner) => inner.strip_suffix(')').unwrap_or(inner).to_string(),
+        None if value == "None" => "unset".to_string(),
+        None => value,
+    }
+}
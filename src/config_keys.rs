@@ -0,0 +1,151 @@
+use std::fmt::Display;
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, Visitor},
+};
+
+use crate::{
+    config::{AppOverride, Config, CriticalConfig, OutputOverride, TimeoutScale},
+    hooks::Hook,
+    sound::SoundConfig,
+};
+
+/// An option in the config that Yand doesn't know, which serde would otherwise ignore
+#[derive(Debug)]
+pub struct UnknownKey {
+    /// The table the key is in, empty for the top level
+    table: String,
+    key: String,
+    /// The known option closest to the key, if any is close enough
+    suggestion: Option<&'static str>,
+}
+
+impl Display for UnknownKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.table.is_empty() {
+            write!(f, "unknown option `{}`", self.key)?;
+        } else {
+            write!(f, "unknown option `{}` in {}", self.key, self.table)?;
+        }
+        if let Some(suggestion) = self.suggestion {
+            write!(f, ", did you mean `{suggestion}`?")?;
+        }
+        Ok(())
+    }
+}
+
+/// The options in the config table that no part of the config has a field for
+pub fn unknown_keys(table: &toml::Table) -> Vec<UnknownKey> {
+    let mut unknown = Vec::new();
+    check_table(&mut unknown, "", table, fields::<Config>());
+
+    let tables: [(&str, &[&str]); 3] = [
+        ("timeout_scale", fields::<TimeoutScale>()),
+        ("critical", fields::<CriticalConfig>()),
+        ("sounds", fields::<SoundConfig>()),
+    ];
+    for (name, known) in tables {
+        if let Some(toml::Value::Table(nested)) = table.get(name) {
+            check_table(&mut unknown, &format!("[{name}]"), nested, known);
+        }
+    }
+
+    let arrays: [(&str, &[&str]); 3] = [
+        ("app_override", fields::<AppOverride>()),
+        ("output_override", fields::<OutputOverride>()),
+        ("hook", fields::<Hook>()),
+    ];
+    for (name, known) in arrays {
+        let Some(toml::Value::Array(entries)) = table.get(name) else {
+            continue;
+        };
+        for (i, entry) in entries.iter().enumerate() {
+            if let toml::Value::Table(entry) = entry {
+                check_table(
+                    &mut unknown,
+                    &format!("[[{name}]] #{}", i + 1),
+                    entry,
+                    known,
+                );
+            }
+        }
+    }
+
+    unknown
+}
+
+fn check_table(
+    unknown: &mut Vec<UnknownKey>,
+    name: &str,
+    table: &toml::Table,
+    known: &'static [&'static str],
+) {
+    for key in table.keys() {
+        if known.contains(&key.as_str()) {
+            continue;
+        }
+        let suggestion = known
+            .iter()
+            .map(|known| (*known, edit_distance(key, known)))
+            .filter(|(known, distance)| *distance <= (known.len() / 3).max(1))
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(known, _)| known);
+        unknown.push(UnknownKey {
+            table: name.to_string(),
+            key: key.clone(),
+            suggestion,
+        });
+    }
+}
+
+/// How many characters have to be inserted, removed or replaced to turn one string into the
+/// other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a != *b);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The names of the fields of a struct deriving `Deserialize`, which it hands to the
+/// deserializer when asked to deserialize itself
+pub(crate) fn fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldsProbe(&mut fields));
+    fields
+}
+
+/// A deserializer that only takes note of the fields of the struct it is asked for
+struct FieldsProbe<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldsProbe<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("only structs can be probed"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("probed"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
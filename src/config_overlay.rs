@@ -0,0 +1,129 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{config::Config, config_keys};
+
+/// A top-level option given on the command line as `key=value`, laid over the config file
+#[derive(Clone, Debug)]
+pub struct ConfigOverlay {
+    key: String,
+    value: toml::Value,
+}
+
+impl ConfigOverlay {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Set the options in the parsed config file, replacing what the file says
+    pub fn apply(overlay: &[Self], table: &mut toml::Table) {
+        for option in overlay {
+            table.insert(option.key.clone(), option.value.clone());
+        }
+    }
+}
+
+impl FromStr for ConfigOverlay {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let Some((key, value)) = str.split_once('=') else {
+            return Err("expected key=value".to_string());
+        };
+        let key = key.trim();
+
+        let keys = scalar_keys();
+        if !keys.contains(&key) {
+            return Err(format!(
+                "unknown option `{key}`, expected one of: {}",
+                keys.join(", ")
+            ));
+        }
+
+        // Anything that isn't a TOML value, like `layer=Top`, is taken as a string
+        let value = value.trim();
+        let value = format!("value = {value}")
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .filter(|value| !value.is_table() && !value.is_array())
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+
+        // Catch bad values at startup rather than on every reload
+        let mut table = toml::Table::new();
+        table.insert(key.to_string(), value.clone());
+        table
+            .try_into::<Config>()
+            .map_err(|why| why.message().to_string())?;
+
+        Ok(Self {
+            key: key.to_string(),
+            value,
+        })
+    }
+}
+
+impl Display for ConfigOverlay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.key, self.value)
+    }
+}
+
+/// The top-level options that take a single value, which are the ones that can't be set to an
+/// empty array or table
+fn scalar_keys() -> Vec<&'static str> {
+    config_keys::fields::<Config>()
+        .iter()
+        .copied()
+        .filter(|key| {
+            [
+                toml::Value::Array(Vec::new()),
+                toml::Value::Table(toml::Table::new()),
+            ]
+            .into_iter()
+            .all(|value| {
+                toml::Table::from_iter([(key.to_string(), value)])
+                    .try_into::<Config>()
+                    .is_err()
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_scalar_options_can_be_set() {
+        let keys = scalar_keys();
+        for key in [
+            "width",
+            "timeout",
+            "layer",
+            "position",
+            "font",
+            "queue_overflow",
+        ] {
+            assert!(keys.contains(&key), "{key}");
+        }
+        for key in [
+            "output",
+            "critical",
+            "sounds",
+            "keys",
+            "app_override",
+            "hook",
+        ] {
+            assert!(!keys.contains(&key), "{key}");
+        }
+    }
+
+    #[test]
+    fn bad_options_are_rejected() {
+        assert!("width=500".parse::<ConfigOverlay>().is_ok());
+        assert!("layer=Top".parse::<ConfigOverlay>().is_ok());
+        assert!("width=wide".parse::<ConfigOverlay>().is_err());
+        assert!("widht=500".parse::<ConfigOverlay>().is_err());
+        assert!("sounds=off".parse::<ConfigOverlay>().is_err());
+    }
+}
@@ -0,0 +1,2069 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use clap::ValueEnum;
+use gtk::{gdk, prelude::*};
+use gtk4::{self as gtk, gio, glib};
+use gtk4_layer_shell::LayerShell;
+use log::error;
+use relm4::{ComponentBuilder, Sender, prelude::*};
+
+use crate::{
+    StartupError,
+    config::{
+        Config, OverflowClick, ReadConfig, default_paths, expand_path, load_style, read_config,
+        style_paths, theme_css,
+    },
+    config_diff,
+    config_overlay::ConfigOverlay,
+    dbus::{
+        NOTIFICATIONS_IFACE, NotificationMethod, NotifyArgs, ReloadOutcome, activate_if_connected,
+        emit_notification_added, emit_notification_closed, emit_notification_removed,
+        emit_properties_changed, emit_signal, emit_signal_on, serve, watch_connection,
+    },
+    font, format,
+    headless::{Headless, RendererMessage, ServerMessage},
+    hints::Hints,
+    history::{History, HistoryEntry},
+    hooks::{self, HookEvent},
+    icon_cache::IconCache,
+    keys::KeyAction,
+    layer::Position,
+    logger, monitor,
+    notification::{
+        self, Notification, NotificationCloseReason, NotificationInit, NotificationInput,
+        NotificationOutput, Urgency,
+    },
+    notification_log::NotificationLog,
+    notification_stack::{NotificationStack, NotificationStackInput, NotificationStackOutput},
+    overflow_indicator::{OverflowIndicator, OverflowIndicatorInput},
+    panel::{Panel, PanelInput, PanelOutput},
+    portal::{self, Portal},
+    queue::{self, Queued},
+    screencast, sound,
+    stats::Stats,
+    systemd,
+};
+
+#[derive(Debug, Clone, Copy, glib::Variant, ValueEnum, Default)]
+pub enum NotificationLevel {
+    #[default]
+    Normal,
+    Dnd,
+}
+
+impl Display for NotificationLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotificationLevel::Normal => f.write_str("normal"),
+            NotificationLevel::Dnd => f.write_str("dnd"),
+        }
+    }
+}
+
+// Daemon side state per every notification
+pub struct NotificationState {
+    pub id: u32,
+    pub sender: Sender<NotificationInput>,
+    pub window: gtk::Window,
+    // What the notification was last shown with, for showing it again after a snooze
+    pub init: NotificationInit,
+    // How many identical notifications were absorbed into this one, including itself
+    pub count: u32,
+    // Set once the notification was closed to make room, until it is gone
+    pub closing: bool,
+}
+
+// A notification hidden until its snooze is over
+pub struct SnoozedNotification {
+    pub init: NotificationInit,
+    pub source_id: glib::SourceId,
+}
+
+pub struct DaemonState {
+    // The connection the D-Bus interfaces are currently served on
+    pub conn: gio::DBusConnection,
+    // Keeps track of the owner of the bus name
+    pub signal_subscriptions: Vec<gio::SignalSubscription>,
+    // Notifications signals that failed to be emitted on conn in a row, replaced along with it
+    pub failed_emissions: Rc<Cell<u32>>,
+    // Set once another daemon owns the bus name, after which no signals are emitted
+    pub name_lost: bool,
+    // Keeps track of the system going to sleep on the system bus
+    pub sleep_subscription: Option<gio::SignalSubscription>,
+    // The config with the override for the current monitor applied
+    pub config: Config,
+    // The config as it was read from the file, with the options from the command line
+    pub file_config: Config,
+    pub config_path: PathBuf,
+    // Options given on the command line, applied over the file on every reload
+    pub config_overlay: Vec<ConfigOverlay>,
+    // Options set with `yand set`, applied over the file until the next reload
+    pub runtime_options: Vec<ConfigOverlay>,
+    pub style_path: PathBuf,
+    /// The loaded style files, in the order they were registered
+    pub css_providers: Vec<gtk::CssProvider>,
+    // Set when running without a display
+    pub headless: Option<Headless>,
+
+    pub notification_level: NotificationLevel,
+    // The open screencast sessions of the portal, during which do not disturb is enabled
+    pub screencasts: HashSet<String>,
+    // The connection monitoring the portal for screencasts
+    pub screencast_conn: Option<gio::DBusConnection>,
+    // Set with `yand min-urgency`, taking precedence over the config
+    pub min_urgency_override: Option<Urgency>,
+    // Notifications kept off the screen for being below the minimum urgency
+    pub suppressed: u32,
+    pub notifications: Vec<NotificationState>,
+    // Received notifications, empty unless enabled in the config
+    pub history: History,
+    // Counters since the daemon started, kept across reloads
+    pub stats: Stats,
+    pub sound_player: sound::Player,
+    // Notifications waiting for room on the screen, in the order they will be shown
+    pub pending: VecDeque<NotificationInit>,
+    // Notifications received during do not disturb, shown once it is over
+    pub held: Vec<NotificationInit>,
+    // How many identical notifications were absorbed into queued ones, including themselves,
+    // shown on them once they are launched
+    pub pending_counts: HashMap<u32, u32>,
+    // The ID of the notification counting notifications dropped from the full queue, and how
+    // many it counts
+    pub overflow_summary: Option<(u32, u32)>,
+    // Notifications hidden by the user for a while
+    pub snoozed: HashMap<u32, SnoozedNotification>,
+    // Where received and closed notifications are written, if configured
+    pub notification_log: Option<NotificationLog>,
+    // Open notifications that hooks are run for once they are closed, with the action
+    // invoked on them
+    pub awaiting_close: HashMap<u32, (NotificationInit, Option<String>)>,
+    // The notification center, None when headless
+    pub panel: Option<Controller<Panel>>,
+    // Counts the queued notifications below the shown ones, None when headless
+    pub overflow_indicator: Option<Controller<OverflowIndicator>>,
+    // Stands in for older notifications past stack_after, None when headless
+    pub stack: Option<Controller<NotificationStack>>,
+    // Whether the stack was clicked open, until there is nothing to collapse anymore
+    pub stack_expanded: Cell<bool>,
+    // The notification that has the keyboard in keyboard mode
+    pub keyboard_selected: Option<u32>,
+    // Shared by all notifications so that repeated icons are only decoded once
+    pub icon_cache: Rc<RefCell<IconCache>>,
+    // The monitor notifications are placed on, None leaves the choice to the compositor
+    pub monitor: Option<gdk::Monitor>,
+    // The ID for the next notification that will be created
+    pub next_id: u32,
+    // A temporary extra offset managed with IPC. Useful for making sure notifications
+    // align with dynamically placed panels
+    pub offset: i32,
+    // A reload that has been requested but not yet performed
+    pub pending_reload: Option<glib::SourceId>,
+    // Hashes of recently received notifications, when they were received and their IDs, for
+    // coalescing
+    pub recent: HashMap<u64, (Instant, u32)>,
+    // The notifications sent through the portal backend
+    pub portal: Portal,
+}
+
+impl DaemonState {
+    /// The ID of an identical notification received within the coalescing window, remembering
+    /// this one otherwise
+    fn duplicate_of(&mut self, args: &NotifyArgs, id: u32) -> Option<u32> {
+        let window = Duration::from_millis(self.config.coalesce_window);
+        if window.is_zero() {
+            return None;
+        }
+
+        let now = Instant::now();
+        self.recent
+            .retain(|_, (received, _)| now.duration_since(*received) < window);
+
+        let mut hasher = DefaultHasher::new();
+        (&args.app_name, &args.summary, &args.body).hash(&mut hasher);
+        let key = hasher.finish();
+
+        if let Some((_, original)) = self.recent.get(&key) {
+            return Some(*original);
+        }
+        self.recent.insert(key, (now, id));
+        None
+    }
+
+    /// The shown notification that a notification with the `x-canonical-append` hint is appended
+    /// to, which is the most recent one from the same application
+    fn append_target(&self, args: &NotifyArgs) -> Option<u32> {
+        if !Hints::append(&args.hints) || args.replaces_id != 0 {
+            return None;
+        }
+        self.notifications
+            .iter()
+            .rev()
+            .find(|state| state.init.app_name == args.app_name)
+            .map(|state| state.id)
+    }
+
+    /// Count an absorbed notification on the identical one still on the screen or in the queue
+    fn merge_into(&mut self, id: u32) {
+        if let Some(notification_state) = self
+            .notifications
+            .iter_mut()
+            .find(|notification_state| notification_state.id == id)
+        {
+            notification_state.count += 1;
+            notification_state
+                .sender
+                .emit(NotificationInput::SetCount(notification_state.count));
+        } else if self.pending.iter().any(|pending| pending.id == id) {
+            // Counts of notifications that left the queue without being shown are dropped here
+            let pending = &self.pending;
+            self.pending_counts
+                .retain(|id, _| pending.iter().any(|pending| pending.id == *id));
+            *self.pending_counts.entry(id).or_insert(1) += 1;
+        }
+    }
+
+    /// Reload the config and style files, dropping the options set at runtime
+    pub fn reload(&mut self) -> ReloadOutcome {
+        self.runtime_options.clear();
+        self.load_config()
+    }
+
+    /// Set a config option until the next reload, taking the place of the same option set
+    /// before
+    pub fn set_option(&mut self, option: ConfigOverlay) -> ReloadOutcome {
+        self.runtime_options
+            .retain(|existing| existing.key() != option.key());
+        self.runtime_options.push(option);
+        self.load_config()
+    }
+
+    /// Load the config and style files with the options from the command line and the ones
+    /// set at runtime laid over them, returning how the config changed and the errors found
+    /// in the config and style files
+    fn load_config(&mut self) -> ReloadOutcome {
+        let overlay = [self.config_overlay.as_slice(), &self.runtime_options].concat();
+        let mut config_errors = Vec::new();
+        let read = read_config(&self.config_path, &overlay).unwrap_or_else(|why| {
+            // Anything is better than falling back to the defaults, especially with a typo
+            // in a strict config
+            error!("Failed to parse config file, keeping the previous config: {why}");
+            config_errors.push(format!("Failed to parse config file: {why}"));
+            ReadConfig {
+                config: self.file_config.clone(),
+                skipped: Vec::new(),
+                origins: HashMap::new(),
+                unknown: Vec::new(),
+            }
+        });
+        for why in &read.skipped {
+            error!("Skipping config file {why}");
+            config_errors.push(format!("Skipping config file {why}"));
+        }
+        for unknown in &read.unknown {
+            log::warn!("{unknown}");
+        }
+        for option in &self.config_overlay {
+            log::info!("Config option from the command line: {option}");
+        }
+        for option in &self.runtime_options {
+            log::info!("Config option set at runtime: {option}");
+        }
+        for warning in read.config.warnings() {
+            log::warn!("{warning}");
+        }
+        let changes = config_diff::diff(&self.file_config, &read.config)
+            .into_iter()
+            .map(|change| read.with_origin(change))
+            .collect::<Vec<_>>();
+        let file_config = read.config;
+        // Surfaces can't change their namespace, so only new ones get it
+        if self.panel.is_some() && self.file_config.namespace != file_config.namespace {
+            log::info!(
+                "The namespace {} is used for new notifications, the panel keeps the old one \
+                 until restarting",
+                file_config.namespace
+            );
+        }
+        self.file_config = file_config;
+        self.config = self.file_config.clone().for_monitor(self.monitor.as_ref());
+
+        logger::configure(
+            self.config.log_level.into(),
+            self.config.log_file.as_deref(),
+        );
+
+        let log_path = self.config.log_notifications.as_deref().map(expand_path);
+        if log_path.as_deref() != self.notification_log.as_ref().map(NotificationLog::path) {
+            self.notification_log = log_path.and_then(|path| {
+                NotificationLog::open(&path)
+                    .inspect_err(|why| {
+                        error!("Failed to open notification log {}: {why}", path.display())
+                    })
+                    .ok()
+            });
+        }
+
+        if self.config.history {
+            // Applications added to history_ignore are forgotten right away
+            let config = &self.config;
+            self.history
+                .retain(|entry| config.recorded(&entry.app_name, entry.desktop_entry.as_deref()));
+            self.history.truncate(self.config.history_size);
+        } else {
+            self.history = History::default();
+        }
+        self.update_panel();
+
+        if changes.is_empty() {
+            log::info!("No changes in the config");
+        }
+        for change in &changes {
+            log::info!("Config changed: {change}");
+        }
+
+        // Without a display there is nothing to style or place
+        if self.headless.is_some() {
+            log::info!("Config reloaded");
+            return ReloadOutcome {
+                changes,
+                config_errors,
+                style_errors: Vec::new(),
+            };
+        }
+
+        let display = gdk::Display::default().unwrap();
+
+        for css_provider in self.css_providers.drain(..) {
+            gtk::style_context_remove_provider_for_display(&display, &css_provider);
+        }
+
+        let style_paths = style_paths(&self.config, &self.style_path);
+
+        let mut style_errors = Vec::new();
+
+        let base = theme_css(self.config.theme.as_deref());
+        // A theme is laid underneath the style files, so that they only need to change
+        // what they want to
+        if self.config.theme.is_some() {
+            let css_provider = gtk::CssProvider::new();
+            css_provider.load_from_string(&base);
+            gtk::style_context_add_provider_for_display(
+                &display,
+                &css_provider,
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION - 2,
+            );
+            self.css_providers.push(css_provider);
+        }
+
+        // With none of the configured files present, the bundled style is used instead
+        let loaded = if style_paths.is_empty() {
+            vec![load_style(&self.style_path, &base)]
+        } else {
+            style_paths
+                .iter()
+                .map(|path| load_style(path, &base))
+                .collect()
+        };
+
+        for (css_provider, errors, fallback) in loaded {
+            // The font options take precedence over the bundled style, but not the user's own
+            let priority = if fallback {
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION - 2
+            } else {
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION
+            };
+            // Providers with the same priority take precedence over the ones added before them
+            gtk::style_context_add_provider_for_display(&display, &css_provider, priority);
+            self.css_providers.push(css_provider);
+            style_errors.extend(errors);
+        }
+
+        let font_provider = gtk::CssProvider::new();
+        font_provider.load_from_string(&font::css(&self.config));
+        gtk::style_context_add_provider_for_display(
+            &display,
+            &font_provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION - 1,
+        );
+        self.css_providers.push(font_provider);
+
+        for why in &style_errors {
+            error!("{why}");
+        }
+
+        log::info!("Config reloaded");
+
+        for state in &self.notifications {
+            state
+                .sender
+                .emit(NotificationInput::ConfigChanged(self.config.clone()));
+        }
+        if let Some(panel) = &self.panel {
+            panel.emit(PanelInput::ConfigChanged(self.config.clone()));
+        }
+        if let Some(indicator) = &self.overflow_indicator {
+            indicator.emit(OverflowIndicatorInput::ConfigChanged(self.config.clone()));
+        }
+        if let Some(stack) = &self.stack {
+            stack.emit(NotificationStackInput::ConfigChanged(self.config.clone()));
+        }
+
+        self.update_monitor();
+        self.recalculate_offsets();
+
+        ReloadOutcome {
+            changes,
+            config_errors,
+            style_errors,
+        }
+    }
+
+    /// Reload after a short delay, so that a burst of reload requests only reloads once
+    fn schedule_reload(state: &Rc<RefCell<DaemonState>>) {
+        let mut _state = state.borrow_mut();
+        if _state.pending_reload.is_some() {
+            return;
+        }
+
+        _state.pending_reload = Some(glib::timeout_add_local_once(
+            Duration::from_millis(200),
+            glib::clone!(
+                #[strong]
+                state,
+                move || {
+                    let mut state = state.borrow_mut();
+                    state.pending_reload = None;
+                    systemd::reloading();
+                    state.reload();
+                    systemd::notify("READY=1");
+                }
+            ),
+        ));
+    }
+
+    /// Close all notifications, letting the applications know that they are gone
+    fn shutdown(&mut self, conn: &gio::DBusConnection) {
+        systemd::notify("STOPPING=1");
+        let mut closed = Vec::new();
+        for state in self.notifications.drain(..) {
+            state.window.destroy();
+            closed.push(state.id);
+        }
+        closed.extend(
+            self.pending
+                .drain(..)
+                .chain(self.held.drain(..))
+                .map(|init| init.id),
+        );
+        for (id, snoozed) in self.snoozed.drain() {
+            snoozed.source_id.remove();
+            closed.push(id);
+        }
+        if let Some(headless) = &mut self.headless {
+            for id in headless.ids() {
+                headless.close(id, NotificationCloseReason::Undefined);
+                closed.push(id);
+            }
+        }
+        for id in closed {
+            self.record_closed(id, NotificationCloseReason::Undefined);
+            // The IDs mean nothing to applications anymore, and may belong to the new daemon
+            if !self.name_lost {
+                emit_notification_closed(
+                    conn,
+                    &self.failed_emissions,
+                    id,
+                    NotificationCloseReason::Undefined,
+                );
+            }
+        }
+        // Make sure the signals are sent before the connection goes away
+        if let Err(why) = conn.flush_sync(Option::<&gio::Cancellable>::None) {
+            error!("Failed to flush the D-Bus connection: {why}");
+        }
+    }
+
+    /// Select the monitor for notifications again, and move visible notifications over if it changed
+    fn update_monitor(&mut self) {
+        let found = monitor::find(&self.config.output);
+        let monitor = found.as_ref().map(|found| found.monitor.clone());
+
+        if monitor == self.monitor {
+            return;
+        }
+
+        match &found {
+            Some(found) => {
+                log::info!(
+                    "Placing notifications on output {}, matched {} by {}",
+                    found.monitor.connector().as_deref().unwrap_or("unknown"),
+                    found.output,
+                    found.matched_by
+                );
+                if found.ambiguous {
+                    log::warn!(
+                        "Multiple outputs match {} by {}, using the first one",
+                        found.output,
+                        found.matched_by
+                    );
+                }
+            }
+            None if !self.config.output.is_empty() => log::info!(
+                "No configured output is available, letting the compositor choose the output"
+            ),
+            None => (),
+        }
+
+        self.monitor = monitor;
+        // A different output may have its own override
+        self.config = self.file_config.clone().for_monitor(self.monitor.as_ref());
+
+        for state in &self.notifications {
+            state
+                .sender
+                .emit(NotificationInput::ChangeMonitor(self.monitor.clone()));
+            state
+                .sender
+                .emit(NotificationInput::ConfigChanged(self.config.clone()));
+        }
+        if let Some(panel) = &self.panel {
+            panel.emit(PanelInput::ChangeMonitor(self.monitor.clone()));
+            panel.emit(PanelInput::ConfigChanged(self.config.clone()));
+        }
+        if let Some(indicator) = &self.overflow_indicator {
+            indicator.emit(OverflowIndicatorInput::ChangeMonitor(self.monitor.clone()));
+            indicator.emit(OverflowIndicatorInput::ConfigChanged(self.config.clone()));
+        }
+        if let Some(stack) = &self.stack {
+            stack.emit(NotificationStackInput::ChangeMonitor(self.monitor.clone()));
+            stack.emit(NotificationStackInput::ConfigChanged(self.config.clone()));
+        }
+        self.recalculate_offsets();
+    }
+
+    /// Whether there is room on the screen for another notification with the given urgency
+    fn has_room(&self, urgency: Urgency) -> bool {
+        queue::has_room(&self.config, self.notifications.len(), urgency)
+    }
+
+    /// Close the oldest notifications of the application until there is room for another one
+    fn make_room_for_app(&mut self, init: &NotificationInit) {
+        let app_name = &init.app_name;
+        let (config, overrides) = self
+            .config
+            .clone()
+            .overridden(app_name, init.hints.desktop_entry.as_deref());
+        // Sticky notifications only leave when dismissed
+        if config.max_per_app == 0 || overrides.sticky {
+            return;
+        }
+
+        // Notifications already on their way out have made room already
+        let shown = self
+            .notifications
+            .iter_mut()
+            .filter(|state| state.init.app_name == *app_name && !state.closing)
+            .collect::<Vec<_>>();
+        let excess = (shown.len() + 1).saturating_sub(config.max_per_app);
+
+        for state in shown.into_iter().take(excess) {
+            log::info!(
+                "Closing notification {} to make room for another one from {app_name}",
+                state.id
+            );
+            state.closing = true;
+            state
+                .sender
+                .emit(NotificationInput::Close(config.max_per_app_reason));
+        }
+    }
+
+    /// Queue a notification until there is room for it, dropping it or another one if the queue
+    /// is full
+    fn queue(&mut self, init: NotificationInit) {
+        let id = init.id;
+        let summary_id = self.overflow_summary.map(|(id, _)| id);
+        match queue::enqueue(&mut self.pending, &self.config, summary_id, init) {
+            Queued::Waiting { dropped } => {
+                if let Some(dropped) = dropped {
+                    self.drop_queued(dropped);
+                }
+            }
+            Queued::Dropped => self.drop_queued(id),
+            Queued::Summarized => {
+                self.drop_queued(id);
+                self.summarize_overflow();
+            }
+        }
+    }
+
+    /// Pause the timeouts of the notifications while the system is suspended, so that they
+    /// don't all expire at once after waking up
+    fn prepare_for_sleep(&self, sleeping: bool) {
+        let grace = Duration::from_secs(self.config.resume_grace as u64);
+        for notification in &self.notifications {
+            notification.sender.emit(if sleeping {
+                NotificationInput::PauseTimeout
+            } else {
+                NotificationInput::ResumeTimeout(grace)
+            });
+        }
+    }
+
+    /// Keep track of a closed notification in the stats, the history and the notification log,
+    /// and run the hooks waiting for it to close. Called wherever notifications are closed, as
+    /// the applications may not be listening anymore, such as after losing the bus name
+    pub fn record_closed(&mut self, id: u32, reason: NotificationCloseReason) {
+        self.stats.closed(id, reason);
+        if let Some(entry) = self.history.get_mut(id) {
+            entry.closed = Some((SystemTime::now(), reason));
+        }
+        if let Some(notification_log) = &self.notification_log {
+            notification_log.closed(id, reason);
+        }
+        if let Some((init, action)) = self.awaiting_close.remove(&id) {
+            hooks::run_closed(&self.config.hooks, &init, reason, action.as_deref());
+        }
+    }
+
+    /// Keep track of an action invoked on a notification in the stats and the history, and for
+    /// the hooks waiting for it to close
+    fn record_action(&mut self, id: u32, action: &str) {
+        self.stats.action_invoked(id);
+        if let Some((_, invoked)) = self.awaiting_close.get_mut(&id) {
+            *invoked = Some(action.to_string());
+        }
+        if let Some(entry) = self.history.get_mut(id) {
+            entry.action = Some(action.to_string());
+        }
+    }
+
+    /// Tell the application that its notification was dropped from the full queue
+    fn drop_queued(&mut self, id: u32) {
+        log::info!("Notification {id} dropped, as the queue is full");
+        self.portal.remove(id);
+        self.record_closed(id, NotificationCloseReason::Expired);
+        if self.name_lost {
+            return;
+        }
+        // Applications may not know the ID yet if the notification was just received
+        let conn = self.conn.clone();
+        let failed_emissions = self.failed_emissions.clone();
+        glib::timeout_add_local_once(Duration::from_millis(100), move || {
+            emit_notification_closed(
+                &conn,
+                &failed_emissions,
+                id,
+                NotificationCloseReason::Expired,
+            );
+        });
+    }
+
+    /// Count a dropped notification in the summary notification, queueing a new one if the
+    /// previous one is gone
+    fn summarize_overflow(&mut self) {
+        let summary = self.overflow_summary.filter(|(id, _)| {
+            self.pending.iter().any(|pending| pending.id == *id)
+                || self
+                    .notifications
+                    .iter()
+                    .any(|notification| notification.id == *id)
+        });
+        let (id, count) = match summary {
+            Some((id, count)) => (id, count + 1),
+            None => {
+                let id = self.next_id;
+                self.next_id += 1;
+                (id, 1)
+            }
+        };
+        self.overflow_summary = Some((id, count));
+
+        let init = NotificationInit {
+            id,
+            app_name: "Yand".to_string(),
+            summary: format!("+{count} more notifications"),
+            body: "Click to show the notification center".to_string(),
+            actions: vec![(notification::DEFAULT_ACTION.to_string(), "Show".to_string())],
+            expire_timeout: -1,
+            hints: Hints {
+                urgency: Some(Urgency::Low),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        if let Some(notification) = self
+            .notifications
+            .iter_mut()
+            .find(|notification| notification.id == id)
+        {
+            notification.init = init.clone();
+            notification
+                .sender
+                .emit(NotificationInput::Replace(Box::new((
+                    init,
+                    self.config.clone(),
+                    self.monitor.clone(),
+                    self.icon_cache.clone(),
+                ))));
+        } else if let Some(pending) = self.pending.iter_mut().find(|pending| pending.id == id) {
+            *pending = init;
+            self.pending_counts.remove(&id);
+        } else {
+            // Stays behind everything else in the queue
+            self.pending.push_back(init);
+        }
+    }
+
+    /// Dismiss the shown, queued, snoozed and held back notifications of the matching applications,
+    /// returning how many were closed
+    pub fn close_app(&mut self, conn: &gio::DBusConnection, matches: impl Fn(&str) -> bool) -> u32 {
+        let mut closed = 0;
+
+        for state in &self.notifications {
+            if matches(&state.init.app_name) {
+                state.sender.emit(NotificationInput::Close(
+                    NotificationCloseReason::DismissedByUser,
+                ));
+                closed += 1;
+            }
+        }
+
+        let mut removed = Vec::new();
+        self.pending.retain(|init| {
+            let remove = matches(&init.app_name);
+            if remove {
+                removed.push(init.id);
+            }
+            !remove
+        });
+        self.snoozed.retain(|id, snoozed| {
+            let remove = matches(&snoozed.init.app_name);
+            if remove {
+                removed.push(*id);
+            }
+            !remove
+        });
+        self.held.retain(|init| {
+            let remove = matches(&init.app_name);
+            if remove {
+                removed.push(init.id);
+            }
+            !remove
+        });
+        for id in removed {
+            self.portal.remove(id);
+            self.record_closed(id, NotificationCloseReason::DismissedByUser);
+            if !self.name_lost {
+                emit_notification_closed(
+                    conn,
+                    &self.failed_emissions,
+                    id,
+                    NotificationCloseReason::DismissedByUser,
+                );
+            }
+            closed += 1;
+        }
+        self.recalculate_offsets();
+
+        closed
+    }
+
+    /// The level set manually, or do not disturb while the screen is cast
+    pub fn level(&self) -> NotificationLevel {
+        if self.screencasts.is_empty() {
+            self.notification_level
+        } else {
+            NotificationLevel::Dnd
+        }
+    }
+
+    /// Whether do not disturb is enabled, what enabled it and how many notifications it holds
+    /// back
+    pub fn dnd_status(&self) -> (bool, String, u32) {
+        let source = match (self.notification_level, self.screencasts.is_empty()) {
+            (NotificationLevel::Dnd, _) => "manual",
+            (NotificationLevel::Normal, false) => "screencast",
+            (NotificationLevel::Normal, true) => "none",
+        };
+        (
+            matches!(self.level(), NotificationLevel::Dnd),
+            source.to_string(),
+            self.held.len() as u32,
+        )
+    }
+
+    /// Notifications less urgent than this are not shown
+    pub fn min_display_urgency(&self) -> Urgency {
+        self.min_urgency_override
+            .unwrap_or(self.config.min_display_urgency)
+    }
+
+    /// Whether the notification is on the screen, queued, snoozed or held back by do not
+    /// disturb
+    fn is_open(&self, id: u32) -> bool {
+        self.notifications.iter().any(|state| state.id == id)
+            || self.pending.iter().any(|init| init.id == id)
+            || self.snoozed.contains_key(&id)
+            || self.held.iter().any(|init| init.id == id)
+            || self
+                .headless
+                .as_ref()
+                .is_some_and(|headless| headless.contains(id))
+    }
+
+    /// The ID, app name and summary of the shown or snoozed notifications
+    pub fn list(&self, snoozed: bool) -> Vec<(u32, String, String)> {
+        let mut notifications = if snoozed {
+            self.snoozed
+                .iter()
+                .map(|(id, snoozed)| (*id, &snoozed.init))
+                .collect::<Vec<_>>()
+        } else {
+            self.notifications
+                .iter()
+                .map(|state| (state.id, &state.init))
+                .collect()
+        };
+        notifications.sort_by_key(|(id, _)| *id);
+
+        notifications
+            .into_iter()
+            .map(|(id, init)| (id, init.app_name.clone(), init.summary.clone()))
+            .collect()
+    }
+
+    /// Show the current history in the panel
+    fn update_panel(&self) {
+        if let Some(panel) = &self.panel {
+            panel.emit(PanelInput::SetEntries(
+                self.history.entries().rev().cloned().collect(),
+            ));
+        }
+    }
+
+    fn handle_panel_output(&mut self, conn: &gio::DBusConnection, output: PanelOutput) {
+        match output {
+            PanelOutput::Dismiss(id) => {
+                self.history.remove(id);
+            }
+            PanelOutput::Clear => self.history.clear(),
+            PanelOutput::Activate(id) => {
+                let Some(entry) = self.history.remove(id) else {
+                    return;
+                };
+                // The notification may still be on screen
+                if let Some(state) = self.notifications.iter().find(|state| state.id == id) {
+                    state.sender.emit(NotificationInput::Close(
+                        NotificationCloseReason::DismissedByUser,
+                    ));
+                }
+                if let (true, Some(sender), false) =
+                    (entry.default_action, entry.sender, self.name_lost)
+                {
+                    self.record_action(id, notification::DEFAULT_ACTION);
+                    activate_if_connected(conn, self.failed_emissions.clone(), id, sender);
+                }
+            }
+        }
+        self.update_panel();
+    }
+
+    /// The oldest notifications in the default position, which are collapsed into the stack
+    /// unless it was expanded
+    fn collapsed(&self) -> Vec<&NotificationState> {
+        if self.config.stack_after == 0 {
+            return Vec::new();
+        }
+        let mut stacked = self
+            .notifications
+            .iter()
+            .filter(|state| {
+                self.config
+                    .position_for(state.init.hints.urgency.unwrap_or_default())
+                    == self.config.position
+            })
+            .collect::<Vec<_>>();
+        let excess = stacked.len().saturating_sub(self.config.stack_after);
+        // Once expanded, the stack stays open until there is nothing to collapse
+        if excess == 0 {
+            self.stack_expanded.set(false);
+        }
+        if self.stack_expanded.get() {
+            return Vec::new();
+        }
+        stacked.truncate(excess);
+        stacked
+    }
+
+    /// The notifications that can be selected in keyboard mode, in the order they are stacked
+    pub fn keyboard_targets(&self) -> Vec<u32> {
+        let collapsed = self.collapsed();
+        self.notifications
+            .iter()
+            .filter(|state| !collapsed.iter().any(|collapsed| collapsed.id == state.id))
+            .map(|state| state.id)
+            .collect()
+    }
+
+    /// Give the keyboard to the notification, or leave keyboard mode with None
+    pub fn select(&mut self, id: Option<u32>) {
+        for state in &self.notifications {
+            let selected = Some(state.id) == id;
+            if selected || Some(state.id) == self.keyboard_selected {
+                state.sender.emit(NotificationInput::SetSelected(selected));
+            }
+        }
+        match id {
+            Some(id) => log::debug!("Notification {id} selected with the keyboard"),
+            None if self.keyboard_selected.is_some() => log::debug!("Keyboard mode left"),
+            None => (),
+        }
+        self.keyboard_selected = id;
+    }
+
+    /// Handle the keyboard mode actions that concern more than the selected notification
+    fn keyboard_action(&mut self, id: u32, action: KeyAction) {
+        let targets = self.keyboard_targets();
+        let index = targets.iter().position(|target| *target == id);
+        match action {
+            KeyAction::Next => {
+                if let Some(next) = index.and_then(|index| targets.get(index + 1)) {
+                    self.select(Some(*next));
+                }
+            }
+            KeyAction::Prev => {
+                if let Some(prev) = index
+                    .and_then(|index| index.checked_sub(1))
+                    .and_then(|index| targets.get(index))
+                {
+                    self.select(Some(*prev));
+                }
+            }
+            KeyAction::DismissAll => {
+                self.select(None);
+                for state in &self.notifications {
+                    state.sender.emit(NotificationInput::Close(
+                        NotificationCloseReason::DismissedByUser,
+                    ));
+                }
+            }
+            KeyAction::Exit => self.select(None),
+            // Handled by the notification itself
+            KeyAction::Dismiss | KeyAction::InvokeDefault | KeyAction::Invoke(_) => (),
+        }
+    }
+
+    // Before this is called, the notifications vector should be "clean"
+    pub fn recalculate_offsets(&self) {
+        let collapsed = self.collapsed();
+        // Notifications in different positions are stacked separately
+        let mut offsets: HashMap<Position, i32> = HashMap::new();
+        for state in &self.notifications {
+            let is_collapsed = collapsed.iter().any(|collapsed| collapsed.id == state.id);
+            state
+                .sender
+                .emit(NotificationInput::SetCollapsed(is_collapsed));
+            if is_collapsed {
+                continue;
+            }
+            let position = self
+                .config
+                .position_for(state.init.hints.urgency.unwrap_or_default());
+            let offset = offsets.entry(position).or_insert(self.offset);
+            // The compositor stacks windows with an exclusive zone by itself, and places the rest
+            // below the space they reserve
+            if state.window.auto_exclusive_zone_is_enabled() {
+                state
+                    .sender
+                    .emit(NotificationInput::ChangeOffset(self.offset));
+            } else {
+                state.sender.emit(NotificationInput::ChangeOffset(*offset));
+                *offset += self.config.spacing + state.window.height();
+            }
+        }
+
+        // Placed below the notifications in the default position
+        let mut offset = offsets
+            .get(&self.config.position)
+            .copied()
+            .unwrap_or(self.offset);
+        if let Some(stack) = &self.stack {
+            stack.emit(NotificationStackInput::ChangeOffset(offset));
+            stack.emit(NotificationStackInput::SetCollapsed(
+                collapsed
+                    .iter()
+                    .map(|state| state.init.app_icon.clone())
+                    .collect(),
+            ));
+            if !collapsed.is_empty() {
+                offset += self.config.spacing + stack.widget().height();
+            }
+        }
+        if let Some(indicator) = &self.overflow_indicator {
+            indicator.emit(OverflowIndicatorInput::ChangeOffset(offset));
+            indicator.emit(OverflowIndicatorInput::SetCount(self.pending.len()));
+        }
+    }
+}
+
+pub fn serve_renderer(
+    rx: relm4::Receiver<RendererMessage>,
+    tx: Sender<ServerMessage>,
+) -> Result<(), StartupError> {
+    let app = gio::Application::new(
+        Some(NOTIFICATIONS_IFACE),
+        gio::ApplicationFlags::IS_SERVICE | gio::ApplicationFlags::ALLOW_REPLACEMENT,
+    );
+    app.register(Option::<&gio::Cancellable>::None)?;
+    let dbus_conn = app.dbus_connection().ok_or_else(|| {
+        StartupError::Dbus(glib::Error::new(
+            gio::IOErrorEnum::NotConnected,
+            "No session bus connection",
+        ))
+    })?;
+
+    let _hold_guard = app.hold();
+    let state = start_daemon(
+        &app,
+        &dbus_conn,
+        Some(Headless::with_renderer(tx)),
+        Vec::new(),
+    )?;
+    glib::MainContext::default().spawn_local(async move {
+        while let Some(message) = rx.recv().await {
+            renderer_message(&mut state.borrow_mut(), message);
+        }
+    });
+    app.run_with_args(&Vec::<String>::new());
+    Ok(())
+}
+
+/// Set up the daemon, which starts serving notifications once the main loop of `app` runs.
+/// Notifications are shown on the screen unless `headless` stands in for the windows.
+pub fn start_daemon(
+    app: &gio::Application,
+    dbus_conn: &gio::DBusConnection,
+    headless: Option<Headless>,
+    overlay: Vec<ConfigOverlay>,
+) -> Result<Rc<RefCell<DaemonState>>, StartupError> {
+    // Owned, as it is kept by the closures
+    let app = app.clone();
+    let has_display = headless.is_none();
+    let (config_path, style_path) = default_paths()?;
+    if let Some(config_dir) = config_path.parent() {
+        fs::create_dir_all(config_dir)
+            .map_err(|why| StartupError::ConfigDir(config_dir.to_path_buf(), why))?;
+    }
+
+    let state = Rc::new(RefCell::new(DaemonState {
+        conn: dbus_conn.clone(),
+        signal_subscriptions: Vec::new(),
+        failed_emissions: Rc::default(),
+        name_lost: false,
+        sleep_subscription: None,
+        config_path,
+        style_path,
+        config_overlay: overlay,
+        runtime_options: Vec::new(),
+        css_providers: Vec::new(),
+        headless,
+        config: Config::default(),
+        file_config: Config::default(),
+        notifications: Vec::new(),
+        history: History::default(),
+        stats: Stats::default(),
+        sound_player: sound::Player::default(),
+        pending: VecDeque::new(),
+        held: Vec::new(),
+        pending_counts: HashMap::new(),
+        overflow_summary: None,
+        snoozed: HashMap::new(),
+        panel: None,
+        overflow_indicator: None,
+        stack: None,
+        stack_expanded: Cell::new(false),
+        keyboard_selected: None,
+        notification_log: None,
+        awaiting_close: HashMap::new(),
+        icon_cache: Rc::default(),
+        next_id: 1,
+        notification_level: NotificationLevel::default(),
+        screencasts: HashSet::new(),
+        screencast_conn: None,
+        min_urgency_override: None,
+        suppressed: 0,
+        offset: 0,
+        monitor: None,
+        pending_reload: None,
+        recent: HashMap::new(),
+        portal: Portal::default(),
+    }));
+
+    state.borrow_mut().reload();
+
+    if has_display {
+        let panel = Panel::builder()
+            .launch((
+                state.borrow().config.clone(),
+                state.borrow().monitor.clone(),
+            ))
+            .connect_receiver(glib::clone!(
+                #[strong]
+                state,
+                move |_, output| {
+                    let mut _state = state.borrow_mut();
+                    let conn = _state.conn.clone();
+                    _state.handle_panel_output(&conn, output);
+                }
+            ));
+
+        let overflow_indicator = OverflowIndicator::builder()
+            .launch((
+                state.borrow().config.clone(),
+                state.borrow().monitor.clone(),
+            ))
+            .connect_receiver(glib::clone!(
+                #[strong]
+                app,
+                #[strong]
+                state,
+                move |_, ()| {
+                    let mut _state = state.borrow_mut();
+                    promote_pending(&app, &state, &mut _state);
+                }
+            ));
+        let stack = NotificationStack::builder()
+            .launch((
+                state.borrow().config.clone(),
+                state.borrow().monitor.clone(),
+            ))
+            .connect_receiver(glib::clone!(
+                #[strong]
+                state,
+                move |_, output| {
+                    let _state = state.borrow();
+                    match output {
+                        NotificationStackOutput::Expand => {
+                            _state.stack_expanded.set(true);
+                            _state.recalculate_offsets();
+                        }
+                        NotificationStackOutput::Clear => {
+                            for notification in _state.collapsed() {
+                                notification.sender.emit(NotificationInput::Close(
+                                    NotificationCloseReason::DismissedByUser,
+                                ));
+                            }
+                        }
+                    }
+                }
+            ));
+        if let Some(app) = app.downcast_ref::<gtk::Application>() {
+            app.add_window(panel.widget());
+            app.add_window(overflow_indicator.widget());
+            app.add_window(stack.widget());
+        }
+
+        let mut _state = state.borrow_mut();
+        _state.panel = Some(panel);
+        _state.overflow_indicator = Some(overflow_indicator);
+        _state.stack = Some(stack);
+        _state.update_panel();
+    }
+
+    // Follow monitors being plugged in and out
+    if has_display {
+        gdk::Display::default()
+            .unwrap()
+            .monitors()
+            .connect_items_changed(glib::clone!(
+                #[strong]
+                state,
+                move |_, _, _, _| {
+                    state.borrow_mut().update_monitor();
+                }
+            ));
+    }
+
+    serve(&app, &state, dbus_conn)?;
+
+    // Losing the bus name is handled by the subscription in serve instead of quitting
+    // right away
+    app.connect_local("name-lost", false, |_| Some(true.to_value()));
+
+    // The connection of the application is used until it is lost the first time
+    dbus_conn.set_exit_on_close(false);
+    watch_connection(&app, &state, dbus_conn);
+    if has_display {
+        watch_sleep(&state);
+    }
+    // Only read at startup, as the bus is monitored on a connection of its own
+    if state.borrow().config.dnd_on_screencast {
+        screencast::watch(&app, &state);
+    }
+
+    for signal in [libc::SIGUSR1, libc::SIGHUP] {
+        glib::unix_signal_add_local(
+            signal,
+            glib::clone!(
+                #[strong]
+                state,
+                move || {
+                    DaemonState::schedule_reload(&state);
+                    glib::ControlFlow::Continue
+                }
+            ),
+        );
+    }
+
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        glib::unix_signal_add_local(
+            signal,
+            glib::clone!(
+                #[weak]
+                app,
+                #[upgrade_or]
+                glib::ControlFlow::Break,
+                move || {
+                    log::info!("Shutting down");
+                    app.quit();
+                    glib::ControlFlow::Break
+                }
+            ),
+        );
+    }
+
+    // However the daemon exits, applications should know that their notifications are gone
+    app.connect_shutdown(glib::clone!(
+        #[strong]
+        state,
+        move |_| {
+            let mut _state = state.borrow_mut();
+            let conn = _state.conn.clone();
+            _state.shutdown(&conn);
+        }
+    ));
+
+    log::info!("Starting Yand");
+
+    // The bus name is owned since registering the application, and everything else is
+    // set up once the main loop runs
+    glib::idle_add_local_once(|| systemd::notify("READY=1"));
+
+    Ok(state)
+}
+
+/// Pause the timeouts of notifications while the system is suspended, as announced by logind on
+/// the system bus
+fn watch_sleep(state: &Rc<RefCell<DaemonState>>) {
+    gio::bus_get(
+        gio::BusType::System,
+        None::<&gio::Cancellable>,
+        glib::clone!(
+            #[strong]
+            state,
+            move |conn| {
+                let conn = match conn {
+                    Ok(conn) => conn,
+                    Err(why) => {
+                        log::warn!("Failed to connect to the system bus to follow suspend: {why}");
+                        return;
+                    }
+                };
+                let subscription = conn.subscribe_to_signal(
+                    Some("org.freedesktop.login1"),
+                    Some("org.freedesktop.login1.Manager"),
+                    Some("PrepareForSleep"),
+                    Some("/org/freedesktop/login1"),
+                    None,
+                    gio::DBusSignalFlags::NONE,
+                    glib::clone!(
+                        #[strong]
+                        state,
+                        move |signal| {
+                            if let Some((sleeping,)) = signal.parameters.get::<(bool,)>() {
+                                log::debug!("Preparing for sleep: {sleeping}");
+                                state.borrow().prepare_for_sleep(sleeping);
+                            }
+                        }
+                    ),
+                );
+                state.borrow_mut().sleep_subscription = Some(subscription);
+            }
+        ),
+    );
+}
+
+/// Show a new notification, or update the shown notification with the same ID
+fn show_notification(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    _state: &mut DaemonState,
+    init: NotificationInit,
+) {
+    let id = init.id;
+
+    // Replacing a snoozed notification keeps it snoozed
+    if let Some(snoozed) = _state.snoozed.get_mut(&id) {
+        snoozed.init = init;
+        return;
+    }
+
+    glib::idle_add_local_once(glib::clone!(
+        #[strong]
+        state,
+        move || {
+            state.borrow().recalculate_offsets();
+        }
+    ));
+
+    if let Some(notification_state) = _state
+        .notifications
+        .iter_mut()
+        .find(|notification_state| notification_state.id == id)
+    {
+        notification_state.init = init.clone();
+        // Mirrors see a replacement as the notification going away and coming back
+        emit_notification_removed(&_state.conn, id, NotificationCloseReason::Undefined);
+        emit_notification_added(&_state.conn, &init);
+        // The absorbed notifications were identical to the previous content
+        if notification_state.count > 1 {
+            notification_state.count = 1;
+            notification_state
+                .sender
+                .emit(NotificationInput::SetCount(1));
+        }
+        notification_state
+            .sender
+            .emit(NotificationInput::Replace(Box::new((
+                init,
+                _state.config.clone(),
+                _state.monitor.clone(),
+                _state.icon_cache.clone(),
+            ))));
+    } else if let Some(pending) = _state.pending.iter_mut().find(|pending| pending.id == id) {
+        *pending = init;
+        // The absorbed notifications were identical to the previous content
+        _state.pending_counts.remove(&id);
+    } else {
+        // Closing takes a moment, so the new notification may still end up queued until then
+        _state.make_room_for_app(&init);
+        if _state.has_room(init.hints.urgency.unwrap_or_default()) {
+            launch_notification(app, state, _state, init);
+        } else {
+            log::info!("Notification {id} queued, as the screen is full");
+            _state.queue(init);
+        }
+    }
+}
+
+/// Show any queued notifications there is now room for
+fn show_pending(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    _state: &mut DaemonState,
+) {
+    while let Some(init) = _state.pending.pop_front() {
+        if !_state.has_room(init.hints.urgency.unwrap_or_default()) {
+            _state.pending.push_front(init);
+            break;
+        }
+        launch_notification(app, state, _state, init);
+    }
+}
+
+/// Show queued notifications even though the screen is full, either the next one or all of
+/// them depending on the config
+fn promote_pending(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    _state: &mut DaemonState,
+) {
+    let count = match _state.config.overflow_click {
+        OverflowClick::Promote => 1,
+        OverflowClick::ShowAll => _state.pending.len(),
+    };
+    for _ in 0..count {
+        let Some(init) = _state.pending.pop_front() else {
+            break;
+        };
+        log::info!("Notification {} shown ahead of its turn", init.id);
+        launch_notification(app, state, _state, init);
+    }
+
+    glib::idle_add_local_once(glib::clone!(
+        #[strong]
+        state,
+        move || {
+            state.borrow().recalculate_offsets();
+        }
+    ));
+}
+
+fn launch_notification(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    _state: &mut DaemonState,
+    init: NotificationInit,
+) {
+    let id = init.id;
+
+    if _state
+        .overflow_summary
+        .is_none_or(|(summary_id, _)| summary_id != id)
+    {
+        emit_notification_added(&_state.conn, &init);
+    }
+
+    let builder = ComponentBuilder::<Notification>::default();
+    let connector = builder.launch((
+        init.clone(),
+        _state.config.clone(),
+        _state.monitor.clone(),
+        _state.icon_cache.clone(),
+    ));
+
+    // The connection may have been replaced by the time the notification is closed
+    let mut controller = connector.connect_receiver(glib::clone!(
+        #[strong]
+        app,
+        #[strong]
+        state,
+        move |sender, message| match message {
+            NotificationOutput::Closed { id, reason } => {
+                log::info!("Notification {id} closed: {reason:?}");
+                let mut _state = state.borrow_mut();
+                let conn = _state.conn.clone();
+
+                // The keyboard moves on to the notification that takes the place of the closed one
+                let selected_index = _state
+                    .keyboard_selected
+                    .filter(|selected| *selected == id)
+                    .and_then(|_| {
+                        _state
+                            .keyboard_targets()
+                            .iter()
+                            .position(|target| *target == id)
+                    });
+
+                _state
+                    .notifications
+                    .retain(|notification| notification.id != id);
+                _state.portal.remove(id);
+                // Nobody else knows about the summary of dropped notifications
+                let overflow_summary = _state
+                    .overflow_summary
+                    .is_some_and(|(summary_id, _)| summary_id == id);
+                if !overflow_summary {
+                    emit_notification_removed(&conn, id, reason);
+                }
+                // Anything promoted from the queue is added after the removal
+                show_pending(&app, &state, &mut _state);
+
+                if let Some(index) = selected_index {
+                    let targets = _state.keyboard_targets();
+                    let next = targets.get(index).or(targets.last()).copied();
+                    _state.select(next);
+                }
+
+                if overflow_summary {
+                    _state.overflow_summary = None;
+                }
+
+                glib::idle_add_local_once(glib::clone!(
+                    #[strong]
+                    state,
+                    move || {
+                        state.borrow().recalculate_offsets();
+                    }
+                ));
+                if !overflow_summary {
+                    _state.record_closed(id, reason);
+                }
+                if _state.name_lost {
+                    if _state.notifications.is_empty() {
+                        app.quit();
+                    }
+                } else if !overflow_summary {
+                    emit_notification_closed(&conn, &_state.failed_emissions, id, reason);
+                }
+
+                // These need to be periodically cleared, and when all notifications have been closed it is
+                // an excellent time to do so
+                if _state.notifications.is_empty() {
+                    relm4::runtime_util::shutdown_all();
+                }
+            }
+            NotificationOutput::Snoozed { id } => {
+                let mut _state = state.borrow_mut();
+                let Some(index) = _state
+                    .notifications
+                    .iter()
+                    .position(|notification| notification.id == id)
+                else {
+                    return;
+                };
+                let notification = _state.notifications.remove(index);
+                let snooze_duration = _state.config.snooze_duration;
+                log::info!("Notification {id} snoozed for {snooze_duration} seconds");
+                // Added again once the snooze is over
+                emit_notification_removed(&_state.conn, id, NotificationCloseReason::Undefined);
+
+                let source_id = glib::timeout_add_local_once(
+                    Duration::from_secs(snooze_duration as u64),
+                    glib::clone!(
+                        #[strong]
+                        app,
+                        #[strong]
+                        state,
+                        move || {
+                            let mut _state = state.borrow_mut();
+                            if let Some(snoozed) = _state.snoozed.remove(&id) {
+                                log::info!("Notification {id} snooze is over");
+                                show_notification(&app, &state, &mut _state, snoozed.init);
+                            }
+                        }
+                    ),
+                );
+                _state.snoozed.insert(
+                    id,
+                    SnoozedNotification {
+                        init: notification.init,
+                        source_id,
+                    },
+                );
+                show_pending(&app, &state, &mut _state);
+
+                glib::idle_add_local_once(glib::clone!(
+                    #[strong]
+                    state,
+                    move || {
+                        state.borrow().recalculate_offsets();
+                    }
+                ));
+            }
+            NotificationOutput::ActionInvoked { id, action } => {
+                log::info!("Notification {id} action invoked: {action}");
+
+                // Does not work right now, and does some weird stuff
+                // let display = gdk::Display::default().unwrap();
+                // let ctx = display.app_launch_context();
+                // if let Some(token) =
+                //     ctx.startup_notify_id(Option::<&gio::AppInfo>::None, &[])
+                // {
+                //     log::info!("{token}");
+                //     conn.emit_signal(
+                //         None,
+                //         NOTIFICATIONS_PATH,
+                //         NOTIFICATIONS_IFACE,
+                //         "ActivationToken",
+                //         Some(&(id, token.to_string()).to_variant()),
+                //     )
+                //     .unwrap();
+                // }
+
+                let mut _state = state.borrow_mut();
+                let close = || {
+                    sender
+                        .send(notification::NotificationInput::Close(
+                            NotificationCloseReason::DismissedByUser,
+                        ))
+                        .unwrap();
+                };
+                if _state
+                    .overflow_summary
+                    .is_some_and(|(summary_id, _)| summary_id == id)
+                {
+                    close();
+                    // The dropped notifications are in the history
+                    if let Some(panel) = &_state.panel {
+                        panel.emit(PanelInput::Toggle);
+                    }
+                    return;
+                }
+                _state.record_action(id, &action);
+                // Emitted before closing, as NotificationClosed is only emitted once the
+                // notification has closed, and applications expect the action to come first
+                if !_state.name_lost {
+                    if let Some(parameters) = _state.portal.action_invoked(id, &action) {
+                        emit_signal_on(
+                            &_state.conn,
+                            portal::PATH,
+                            portal::IFACE,
+                            id,
+                            "ActionInvoked",
+                            parameters,
+                        );
+                    }
+                    emit_signal(
+                        &_state.conn,
+                        &_state.failed_emissions,
+                        id,
+                        "ActionInvoked",
+                        (id, action).to_variant(),
+                    );
+                }
+                // Resident notifications stay around until they are dismissed or closed by
+                // the application
+                let resident = _state
+                    .notifications
+                    .iter()
+                    .find(|notification| notification.id == id)
+                    .is_some_and(|notification| notification.init.hints.resident == Some(true));
+                if !resident {
+                    close();
+                }
+            }
+            NotificationOutput::KeyPressed { id, action } => {
+                state.borrow_mut().keyboard_action(id, action);
+            }
+        }
+    ));
+
+    let window = controller.widget();
+    if let Some(app) = app.downcast_ref::<gtk::Application>() {
+        app.add_window(window);
+    }
+    window.set_visible(true);
+
+    // Played once the notification is on the screen, not while it waits in the queue
+    let dnd = matches!(_state.level(), NotificationLevel::Dnd);
+    if let Some(sound) = _state.config.sound(&init, dnd) {
+        _state.sound_player.play(
+            sound,
+            init.hints.urgency.unwrap_or_default(),
+            Duration::from_millis(_state.config.sound_cooldown_ms),
+        );
+    }
+
+    // Duplicates received while the notification was queued
+    let count = _state.pending_counts.remove(&id).unwrap_or(1);
+    if count > 1 {
+        controller.sender().emit(NotificationInput::SetCount(count));
+    }
+
+    _state.notifications.push(NotificationState {
+        id,
+        sender: controller.sender().clone(),
+        window: window.clone(),
+        init,
+        count,
+        closing: false,
+    });
+
+    controller.detach_runtime();
+}
+
+pub fn notification_handler(
+    app: gio::Application,
+    state: Rc<RefCell<DaemonState>>,
+    conn: gio::DBusConnection,
+    method: NotificationMethod,
+    invocation: gio::DBusMethodInvocation,
+) {
+    // Applications may still have the unique name from before another daemon took over
+    if state.borrow().name_lost
+        && matches!(
+            method,
+            NotificationMethod::Notify(_) | NotificationMethod::CloseNotification(_)
+        )
+    {
+        invocation.return_error(
+            gio::DBusError::Failed,
+            "Notifications are handled by another daemon",
+        );
+        return;
+    }
+
+    match method {
+        NotificationMethod::GetCapabilities => {
+            let mut capabilities = vec![
+                "action-icons",
+                "actions",
+                "body",
+                "body-hyperlinks",
+                "body-markup",
+                "icon-static",
+                "x-canonical-append",
+            ];
+            // Notifications stay available in the history after they are gone from the screen
+            if state.borrow().config.history {
+                capabilities.push("persistence");
+            }
+            invocation.return_value(Some(&(capabilities,).to_variant()));
+        }
+        NotificationMethod::Notify(args) => {
+            let sender = invocation.sender().map(Into::into);
+            let id = notify(&app, &state, conn, args, sender).id;
+            invocation.return_value(Some(&(id,).to_variant()));
+        }
+        NotificationMethod::CloseNotification(close_notification_args) => {
+            close_notification(&mut state.borrow_mut(), &conn, close_notification_args.id);
+            invocation.return_value(None);
+        }
+        NotificationMethod::GetServerInformation => {
+            invocation.return_value(Some(
+                &("Yand", "Kirottu", env!("CARGO_PKG_VERSION"), "1.3").to_variant(),
+            ));
+        }
+    }
+}
+
+/// What became of a notification received by `notify`
+pub struct Notified {
+    /// The ID the application gets back
+    pub id: u32,
+    /// The notification it lives on as while shown, queued or snoozed, which is the one it was
+    /// absorbed into for duplicates. None if it was closed right away.
+    pub live: Option<u32>,
+}
+
+/// Show, queue or hold back a received notification depending on the config and do not
+/// disturb. `sender` is the unique bus name of the application, if known
+pub fn notify(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    conn: gio::DBusConnection,
+    args: NotifyArgs,
+    sender: Option<String>,
+) -> Notified {
+    let mut _state = state.borrow_mut();
+    let failed_emissions = _state.failed_emissions.clone();
+    // Appended notifications take the place of the one they are appended to
+    let appended_to = _state.append_target(&args);
+    let id = if let Some(id) = appended_to {
+        id
+    } else if args.replaces_id == 0 {
+        let id = _state.next_id;
+        _state.next_id += 1;
+        id
+    } else {
+        args.replaces_id
+    };
+    log::info!("Notification {id} received: {}", args.summary);
+    _state.stats.received(id, &args.app_name);
+
+    // Replacements are never absorbed, as the notification they replace is still around
+    let original = if args.replaces_id == 0 && appended_to.is_none() {
+        _state.duplicate_of(&args, id)
+    } else {
+        None
+    };
+    if let Some(original) = original {
+        log::info!("Notification {id} is identical to notification {original}, absorbing it");
+        _state.merge_into(original);
+        _state.record_closed(id, NotificationCloseReason::Expired);
+        glib::timeout_add_local_once(Duration::from_millis(100), move || {
+            emit_notification_closed(
+                &conn,
+                &failed_emissions,
+                id,
+                NotificationCloseReason::Expired,
+            );
+        });
+        return Notified {
+            id,
+            live: Some(original),
+        };
+    }
+
+    let mut init = args.into_notification_init(id, _state.config.max_image_bytes);
+    if let Some(previous) = appended_to.and_then(|id| {
+        _state
+            .notifications
+            .iter()
+            .find(|notification| notification.id == id)
+    }) {
+        log::info!("Notification appended to notification {id}");
+        // Each appended message stays on a line of its own
+        init.body = format!(
+            "{}{}{}",
+            previous.init.body,
+            format::LINE_SEPARATOR,
+            init.body
+        );
+    }
+    // Replacements go through here as well, so they are hidden just the same
+    let mut hidden_body = None;
+    if _state
+        .config
+        .hides_body(&init.app_name, init.hints.desktop_entry.as_deref())
+    {
+        let body = std::mem::replace(&mut init.body, _state.config.hidden_body.clone());
+        if _state.config.record_hidden_body {
+            hidden_body = Some(body);
+        }
+    }
+    // What the history, the notification log and hooks get to see
+    let with_hidden_body = hidden_body.map(|body| NotificationInit {
+        body,
+        ..init.clone()
+    });
+    let recorded_init = with_hidden_body.as_ref().unwrap_or(&init);
+
+    // Hooks run even when the notification itself is not shown
+    hooks::run(&_state.config.hooks, recorded_init);
+    if _state
+        .config
+        .hooks
+        .iter()
+        .any(|hook| hook.event() == HookEvent::Closed)
+    {
+        _state
+            .awaiting_close
+            .insert(id, (recorded_init.clone(), None));
+    }
+
+    let recorded = _state
+        .config
+        .recorded(&init.app_name, init.hints.desktop_entry.as_deref());
+    if !recorded {
+        log::info!(
+            "Notification {id} from {} is not recorded in the history",
+            init.app_name
+        );
+    }
+
+    if let Some(notification_log) = &_state.notification_log {
+        if recorded && (_state.config.log_transient || !init.hints.transient.unwrap_or_default()) {
+            notification_log.received(recorded_init);
+        }
+    }
+
+    if _state.config.history && recorded && !init.hints.transient.unwrap_or_default() {
+        let capacity = _state.config.history_size;
+        let entry = HistoryEntry {
+            sender,
+            ..HistoryEntry::from(recorded_init)
+        };
+        _state.history.push(entry, capacity);
+        _state.update_panel();
+    }
+
+    // Allowed applications come through do not disturb as if it was off
+    let level = match _state.level() {
+        NotificationLevel::Dnd if _state.config.allowed_during_dnd(&init) => {
+            log::info!(
+                "Notification {id} from {} is allowed during do not disturb",
+                init.app_name
+            );
+            NotificationLevel::Normal
+        }
+        level => level,
+    };
+    // Do not disturb goes first, so only notifications that would be shown count as
+    // suppressed
+    let below_minimum = init.hints.urgency.unwrap_or_default() < _state.min_display_urgency();
+    let live = match level {
+        NotificationLevel::Normal if below_minimum => {
+            log::info!(
+                "Notification {id} is less urgent than {}, not showing it",
+                _state.min_display_urgency()
+            );
+            _state.suppressed += 1;
+            emit_properties_changed(&conn, &[("Suppressed", _state.suppressed.to_variant())]);
+            _state.record_closed(id, NotificationCloseReason::Undefined);
+            glib::timeout_add_local_once(Duration::from_millis(100), move || {
+                emit_notification_closed(
+                    &conn,
+                    &failed_emissions,
+                    id,
+                    NotificationCloseReason::Undefined,
+                );
+            });
+            None
+        }
+        NotificationLevel::Normal => display(app, state, &mut _state, init),
+        NotificationLevel::Dnd => {
+            if let Some(held) = _state.held.iter_mut().find(|held| held.id == id) {
+                // Replacing a held notification keeps it held
+                *held = init;
+                Some(id)
+            } else if _state.is_open(id) {
+                // Replacements of notifications that are already around are not held back
+                display(app, state, &mut _state, init)
+            } else if below_minimum {
+                // Would not be shown once do not disturb is over either
+                _state.record_closed(id, NotificationCloseReason::Undefined);
+                glib::timeout_add_local_once(Duration::from_millis(100), move || {
+                    emit_notification_closed(
+                        &conn,
+                        &failed_emissions,
+                        id,
+                        NotificationCloseReason::Undefined,
+                    );
+                });
+                None
+            } else {
+                log::info!("Notification {id} held back until do not disturb is over");
+                _state.held.push(init);
+                Some(id)
+            }
+        }
+    };
+    Notified { id, live }
+}
+
+/// Show a notification on the screen, or through the headless stand-in, returning its ID if
+/// it is still around afterwards
+fn display(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    _state: &mut DaemonState,
+    init: NotificationInit,
+) -> Option<u32> {
+    let id = init.id;
+    if _state.headless.is_none() {
+        show_notification(app, state, _state, init);
+        // Dropped if the queue is full
+        return _state.is_open(id).then_some(id);
+    }
+
+    let on_expired = glib::clone!(
+        #[strong]
+        state,
+        move || {
+            let mut _state = state.borrow_mut();
+            if let Some(headless) = &mut _state.headless {
+                headless.close(id, NotificationCloseReason::Expired);
+            }
+            _state.portal.remove(id);
+            _state.record_closed(id, NotificationCloseReason::Expired);
+            emit_notification_closed(
+                &_state.conn,
+                &_state.failed_emissions,
+                id,
+                NotificationCloseReason::Expired,
+            );
+        }
+    );
+    let config = _state.config.clone();
+    if let Some(headless) = &mut _state.headless {
+        headless.show(init, &config, on_expired);
+    }
+    Some(id)
+}
+
+/// Show the notifications held back during do not disturb, once it is over
+pub fn release_held(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    _state: &mut DaemonState,
+) {
+    if matches!(_state.level(), NotificationLevel::Dnd) || _state.held.is_empty() {
+        return;
+    }
+    let held = std::mem::take(&mut _state.held);
+    log::info!(
+        "Do not disturb is over, showing {} held back notifications",
+        held.len()
+    );
+    for init in held {
+        display(app, state, _state, init);
+    }
+}
+
+/// Pass on what the user did with a notification of the renderer of an embedding program
+fn renderer_message(_state: &mut DaemonState, message: RendererMessage) {
+    match message {
+        RendererMessage::Dismissed(id) => {
+            let closed = _state.headless.as_mut().is_some_and(|headless| {
+                headless.close(id, NotificationCloseReason::DismissedByUser)
+            });
+            if closed {
+                _state.portal.remove(id);
+                _state.record_closed(id, NotificationCloseReason::DismissedByUser);
+                emit_notification_closed(
+                    &_state.conn,
+                    &_state.failed_emissions,
+                    id,
+                    NotificationCloseReason::DismissedByUser,
+                );
+            }
+        }
+        RendererMessage::ActionInvoked { id, action } => {
+            log::info!("Action {action} of notification {id} invoked by the renderer");
+            _state.record_action(id, &action);
+            if _state.name_lost {
+                return;
+            }
+            if let Some(parameters) = _state.portal.action_invoked(id, &action) {
+                emit_signal_on(
+                    &_state.conn,
+                    portal::PATH,
+                    portal::IFACE,
+                    id,
+                    "ActionInvoked",
+                    parameters,
+                );
+            }
+            emit_signal(
+                &_state.conn,
+                &_state.failed_emissions,
+                id,
+                "ActionInvoked",
+                (id, action).to_variant(),
+            );
+        }
+    }
+}
+
+/// Close a notification at the request of the application that sent it
+pub fn close_notification(_state: &mut DaemonState, conn: &gio::DBusConnection, id: u32) {
+    if let Some(headless) = &mut _state.headless {
+        if headless.close(id, NotificationCloseReason::DismissedByApp) {
+            _state.portal.remove(id);
+            _state.record_closed(id, NotificationCloseReason::DismissedByApp);
+            emit_notification_closed(
+                conn,
+                &_state.failed_emissions,
+                id,
+                NotificationCloseReason::DismissedByApp,
+            );
+        }
+    } else if let Some(notification) = _state.notifications.iter().find(|n| n.id == id) {
+        notification.sender.emit(NotificationInput::Close(
+            NotificationCloseReason::DismissedByApp,
+        ));
+    } else if let Some(snoozed) = _state.snoozed.remove(&id) {
+        snoozed.source_id.remove();
+        _state.portal.remove(id);
+        _state.record_closed(id, NotificationCloseReason::DismissedByApp);
+        emit_notification_closed(
+            conn,
+            &_state.failed_emissions,
+            id,
+            NotificationCloseReason::DismissedByApp,
+        );
+    } else if let Some(index) = _state.pending.iter().position(|init| init.id == id) {
+        _state.pending.remove(index);
+        _state.recalculate_offsets();
+        _state.portal.remove(id);
+        _state.record_closed(id, NotificationCloseReason::DismissedByApp);
+        emit_notification_closed(
+            conn,
+            &_state.failed_emissions,
+            id,
+            NotificationCloseReason::DismissedByApp,
+        );
+    } else if let Some(index) = _state.held.iter().position(|init| init.id == id) {
+        _state.held.remove(index);
+        _state.portal.remove(id);
+        _state.record_closed(id, NotificationCloseReason::DismissedByApp);
+        emit_notification_closed(
+            conn,
+            &_state.failed_emissions,
+            id,
+            NotificationCloseReason::DismissedByApp,
+        );
+    }
+}
@@ -0,0 +1,882 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use gtk4::{gio, glib, prelude::*};
+use log::error;
+
+use crate::{
+    app_matcher::AppMatcher,
+    config::OnNameLost,
+    daemon::{DaemonState, NotificationLevel, notification_handler, release_held},
+    hints::Hints,
+    history::HistoryRecord,
+    notification::{self, NotificationCloseReason, NotificationInit, Urgency},
+    output,
+    panel::PanelInput,
+    portal, systemd,
+};
+
+pub const INTERFACE_XML: &str = r#"
+<node>
+    <interface name="org.freedesktop.Notifications">
+        <method name="GetCapabilities">
+            <arg type="as" name="capabilities" direction="out"/>
+        </method>
+        <method name="Notify">
+            <arg type="s" name="app_name" direction="in"/> 
+            <arg type="u" name="replaces_id" direction="in"/>
+            <arg type="s" name="app_icon" direction="in"/>
+            <arg type="s" name="summary" direction="in"/>
+            <arg type="s" name="body" direction="in"/>
+            <arg type="as" name="actions" direction="in"/>
+            <arg type="a{sv}" name="hints" direction="in"/>
+            <arg type="i" name="expire_timeout" direction="in"/>
+            <arg type="u" name="id" direction="out"/>
+        </method>
+        <method name="CloseNotification">
+            <arg type="u" name="id" direction="in"/>
+        </method>
+        <method name="GetServerInformation">
+            <arg type="s" name="name" direction="out"/>
+            <arg type="s" name="vendor" direction="out"/>
+            <arg type="s" name="version" direction="out"/>
+            <arg type="s" name="spec_version" direction="out"/>
+        </method>
+
+        <signal name="NotificationClosed">
+            <arg type="u" name="id"/>
+            <arg type="u" name="reason"/>
+        </signal>
+        <signal name="ActionInvoked">
+            <arg type="u" name="id"/>
+            <arg type="s" name="action_key"/>
+        </signal>
+        <signal name="ActivationToken">
+            <arg type="u" name="id"/>
+            <arg type="s" name="activation_token"/>
+        </signal>
+    </interface>
+    <interface name="com.kirottu.Yand">
+        <method name="Reload">
+            <arg type="as" name="changes" direction="out"/>
+            <arg type="as" name="config_errors" direction="out"/>
+            <arg type="as" name="style_errors" direction="out"/>
+        </method>
+        <method name="SetOffset">
+            <arg type="i" name="offset" direction="in"/>
+        </method>
+        <method name="SetOption">
+            <arg type="s" name="key" direction="in"/>
+            <arg type="s" name="value" direction="in"/>
+            <arg type="as" name="changes" direction="out"/>
+            <arg type="as" name="config_errors" direction="out"/>
+            <arg type="as" name="style_errors" direction="out"/>
+        </method>
+        <method name="ClearOptions">
+            <arg type="as" name="changes" direction="out"/>
+            <arg type="as" name="config_errors" direction="out"/>
+            <arg type="as" name="style_errors" direction="out"/>
+        </method>
+        <method name="TogglePanel"/>
+        <method name="KeyboardMode"/>
+        <method name="ListNotifications">
+            <arg type="b" name="snoozed" direction="in"/>
+            <arg type="a(uss)" name="notifications" direction="out"/>
+        </method>
+        <method name="CloseApp">
+            <arg type="s" name="app_name" direction="in"/>
+            <arg type="b" name="regex" direction="in"/>
+            <arg type="u" name="closed" direction="out"/>
+        </method>
+        <method name="GetHistory">
+            <arg type="a(usssssxxss)" name="history" direction="out"/>
+        </method>
+        <method name="GetStats">
+            <arg type="a{s(uuuu)}" name="apps" direction="out"/>
+            <arg type="t" name="uptime" direction="out"/>
+        </method>
+        <method name="ResetStats"/>
+        <method name="GetConfig">
+            <arg type="s" name="config" direction="out"/>
+            <arg type="a(ss)" name="options" direction="out"/>
+        </method>
+        <method name="DndStatus">
+            <arg type="b" name="enabled" direction="out"/>
+            <arg type="s" name="source" direction="out"/>
+            <arg type="u" name="queued" direction="out"/>
+        </method>
+        <method name="SetMinUrgency">
+            <arg type="s" name="urgency" direction="in"/>
+        </method>
+        <method name="MinUrgencyStatus">
+            <arg type="s" name="urgency" direction="out"/>
+            <arg type="u" name="suppressed" direction="out"/>
+        </method>
+        <signal name="NotificationAdded">
+            <arg type="u" name="id"/>
+            <arg type="s" name="app_name"/>
+            <arg type="s" name="summary"/>
+            <arg type="s" name="body"/>
+            <arg type="s" name="urgency"/>
+            <arg type="s" name="icon"/>
+        </signal>
+        <signal name="NotificationRemoved">
+            <arg type="u" name="id"/>
+            <arg type="u" name="reason"/>
+        </signal>
+        <property type="s" name="NotificationLevel" access="readwrite"/>
+        <property type="u" name="Suppressed" access="read"/>
+    </interface>
+</node>
+"#;
+
+pub const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+pub const NOTIFICATIONS_IFACE: &str = "org.freedesktop.Notifications";
+pub const CONTROL_PATH: &str = "/com/kirottu/Yand";
+pub const CONTROL_IFACE: &str = "com.kirottu.Yand";
+
+#[derive(Debug, glib::Variant)]
+pub struct NotifyArgs {
+    pub app_name: String,
+    pub replaces_id: u32,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    pub actions: Vec<String>,
+    pub hints: HashMap<String, glib::Variant>,
+    pub expire_timeout: i32,
+}
+
+impl NotifyArgs {
+    pub fn into_notification_init(self, id: u32, max_image_bytes: usize) -> NotificationInit {
+        let actions = self
+            .actions
+            .chunks_exact(2)
+            .map(|chunk| (chunk[0].clone(), chunk[1].clone()))
+            .collect();
+
+        let (hints, recognized) = Hints::from_map(&self.hints, id, max_image_bytes);
+        log::debug!("Notification {id} has the hints {}", recognized.join(", "));
+
+        // Hooks get to see the hints that are not supported here as well
+        let mut raw_hints = self.hints;
+        for key in ["image-data", "image_data", "icon_data"] {
+            raw_hints.remove(key);
+        }
+
+        NotificationInit {
+            id,
+            app_name: self.app_name,
+            app_icon: self.app_icon,
+            summary: self.summary,
+            body: self.body,
+            actions,
+            expire_timeout: self.expire_timeout,
+            hints,
+            raw_hints,
+        }
+    }
+}
+
+#[derive(Debug, glib::Variant)]
+pub struct CloseNotificationArgs {
+    pub id: u32,
+}
+
+#[derive(Debug)]
+pub enum NotificationMethod {
+    GetCapabilities,
+    Notify(NotifyArgs),
+    CloseNotification(CloseNotificationArgs),
+    GetServerInformation,
+}
+
+impl DBusMethodCall for NotificationMethod {
+    fn parse_call(
+        _obj_path: &str,
+        _interface: Option<&str>,
+        method: &str,
+        params: glib::Variant,
+    ) -> Result<Self, glib::Error> {
+        match method {
+            "GetCapabilities" => Ok(Some(Self::GetCapabilities)),
+            "Notify" => Ok(params.get::<NotifyArgs>().map(Self::Notify)),
+            "CloseNotification" => Ok(params
+                .get::<CloseNotificationArgs>()
+                .map(Self::CloseNotification)),
+            "GetServerInformation" => Ok(Some(Self::GetServerInformation)),
+            _ => Err(glib::Error::new(
+                gio::DBusError::UnknownMethod,
+                "No such method",
+            )),
+        }
+        .and_then(|p| {
+            p.ok_or_else(|| glib::Error::new(gio::DBusError::InvalidArgs, "Invalid parameters"))
+        })
+    }
+}
+
+#[derive(Debug, glib::Variant)]
+struct SetOffsetArgs {
+    offset: i32,
+}
+
+enum ControlMethod {
+    Reload,
+    SetOffset(SetOffsetArgs),
+    SetOption(SetOptionArgs),
+    ClearOptions,
+    TogglePanel,
+    KeyboardMode,
+    ListNotifications(ListNotificationsArgs),
+    CloseApp(CloseAppArgs),
+    GetHistory,
+    GetStats,
+    ResetStats,
+    GetConfig,
+    DndStatus,
+    SetMinUrgency(SetMinUrgencyArgs),
+    MinUrgencyStatus,
+}
+
+#[derive(Debug, glib::Variant)]
+struct SetMinUrgencyArgs {
+    urgency: String,
+}
+
+#[derive(Debug, glib::Variant)]
+struct SetOptionArgs {
+    key: String,
+    value: String,
+}
+
+/// How the config changed in a reload, and what went wrong
+#[derive(Debug, glib::Variant)]
+pub struct ReloadOutcome {
+    pub changes: Vec<String>,
+    /// Why the config could not be read, in which case the previous one is kept, and the
+    /// skipped `config.d` files
+    pub config_errors: Vec<String>,
+    pub style_errors: Vec<String>,
+}
+
+impl From<ReloadOutcome> for output::Reload {
+    fn from(outcome: ReloadOutcome) -> Self {
+        Self {
+            changes: outcome.changes,
+            config_errors: outcome.config_errors,
+            style_errors: outcome.style_errors,
+        }
+    }
+}
+
+#[derive(Debug, glib::Variant)]
+struct CloseAppArgs {
+    app_name: String,
+    regex: bool,
+}
+
+#[derive(Debug, glib::Variant)]
+struct ListNotificationsArgs {
+    snoozed: bool,
+}
+
+impl DBusMethodCall for ControlMethod {
+    fn parse_call(
+        _obj_path: &str,
+        _interface: Option<&str>,
+        method: &str,
+        params: glib::Variant,
+    ) -> Result<Self, glib::Error> {
+        match method {
+            "Reload" => Ok(Some(Self::Reload)),
+            "SetOffset" => Ok(params.get::<SetOffsetArgs>().map(Self::SetOffset)),
+            "SetOption" => Ok(params.get::<SetOptionArgs>().map(Self::SetOption)),
+            "ClearOptions" => Ok(Some(Self::ClearOptions)),
+            "TogglePanel" => Ok(Some(Self::TogglePanel)),
+            "KeyboardMode" => Ok(Some(Self::KeyboardMode)),
+            "ListNotifications" => Ok(params
+                .get::<ListNotificationsArgs>()
+                .map(Self::ListNotifications)),
+            "CloseApp" => Ok(params.get::<CloseAppArgs>().map(Self::CloseApp)),
+            "GetHistory" => Ok(Some(Self::GetHistory)),
+            "GetStats" => Ok(Some(Self::GetStats)),
+            "ResetStats" => Ok(Some(Self::ResetStats)),
+            "GetConfig" => Ok(Some(Self::GetConfig)),
+            "DndStatus" => Ok(Some(Self::DndStatus)),
+            "SetMinUrgency" => Ok(params.get::<SetMinUrgencyArgs>().map(Self::SetMinUrgency)),
+            "MinUrgencyStatus" => Ok(Some(Self::MinUrgencyStatus)),
+            _ => Err(glib::Error::new(
+                gio::DBusError::UnknownMethod,
+                "No such method",
+            )),
+        }
+        .and_then(|p| {
+            p.ok_or_else(|| glib::Error::new(gio::DBusError::InvalidArgs, "Invalid parameters"))
+        })
+    }
+}
+
+/// How many emissions may fail in a row before the connection is treated as broken
+const MAX_FAILED_EMISSIONS: u32 = 5;
+
+/// Emit a signal about notification `id` on the Notifications interface. A failure is only
+/// logged, as it can be as harmless as the application having gone away, unless emissions keep
+/// failing, in which case the connection is closed to go through reconnecting.
+/// `failed_emissions` counts the failures in a row on `conn`.
+pub fn emit_signal(
+    conn: &gio::DBusConnection,
+    failed_emissions: &Cell<u32>,
+    id: u32,
+    signal: &str,
+    parameters: glib::Variant,
+) {
+    match conn.emit_signal(
+        None,
+        NOTIFICATIONS_PATH,
+        NOTIFICATIONS_IFACE,
+        signal,
+        Some(&parameters),
+    ) {
+        Ok(()) => failed_emissions.set(0),
+        Err(why) => {
+            error!("Failed to emit {signal} for notification {id}: {why}");
+            let failed = failed_emissions.get() + 1;
+            failed_emissions.set(failed);
+            // A connection that is already closed is being taken care of by watch_connection
+            if failed >= MAX_FAILED_EMISSIONS && !conn.is_closed() {
+                error!("Failed to emit {failed} signals in a row, reconnecting to D-Bus");
+                failed_emissions.set(0);
+                conn.close(None::<&gio::Cancellable>, |result| {
+                    if let Err(why) = result {
+                        error!("Failed to close the D-Bus connection: {why}");
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Emit a signal about notification `id` on another interface. Failures are only logged, as
+/// the other interfaces say nothing about the health of the connection.
+pub fn emit_signal_on(
+    conn: &gio::DBusConnection,
+    path: &str,
+    interface: &str,
+    id: u32,
+    signal: &str,
+    parameters: glib::Variant,
+) {
+    if let Err(why) = conn.emit_signal(None, path, interface, signal, Some(&parameters)) {
+        error!("Failed to emit {signal} for notification {id}: {why}");
+    }
+}
+
+/// Let clients watching the properties of the control interface know that they changed
+pub fn emit_properties_changed(conn: &gio::DBusConnection, changed: &[(&str, glib::Variant)]) {
+    let changed = changed
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect::<HashMap<_, _>>();
+    if let Err(why) = conn.emit_signal(
+        None,
+        CONTROL_PATH,
+        "org.freedesktop.DBus.Properties",
+        "PropertiesChanged",
+        Some(&(CONTROL_IFACE, changed, Vec::<String>::new()).to_variant()),
+    ) {
+        error!("Failed to emit PropertiesChanged: {why}");
+    }
+}
+
+/// Let other notification centers know that a notification is now on the screen, with the
+/// image path or icon instead of any raw image data
+pub fn emit_notification_added(conn: &gio::DBusConnection, init: &NotificationInit) {
+    let icon = init.hints.image_path.as_deref().unwrap_or(&init.app_icon);
+    emit_signal_on(
+        conn,
+        CONTROL_PATH,
+        CONTROL_IFACE,
+        init.id,
+        "NotificationAdded",
+        (
+            init.id,
+            &init.app_name,
+            &init.summary,
+            &init.body,
+            init.hints.urgency.unwrap_or_default().as_str(),
+            icon,
+        )
+            .to_variant(),
+    );
+}
+
+/// Let other notification centers know that a notification left the screen
+pub fn emit_notification_removed(
+    conn: &gio::DBusConnection,
+    id: u32,
+    reason: NotificationCloseReason,
+) {
+    emit_signal_on(
+        conn,
+        CONTROL_PATH,
+        CONTROL_IFACE,
+        id,
+        "NotificationRemoved",
+        (id, u32::from(reason)).to_variant(),
+    );
+}
+
+/// Let an application know that one of its notifications was closed
+pub fn emit_notification_closed(
+    conn: &gio::DBusConnection,
+    failed_emissions: &Cell<u32>,
+    id: u32,
+    reason: NotificationCloseReason,
+) {
+    emit_signal(
+        conn,
+        failed_emissions,
+        id,
+        "NotificationClosed",
+        (id, u32::from(reason)).to_variant(),
+    );
+}
+
+/// Invoke the default action of a notification, as long as the application that sent it is
+/// still around to receive it
+pub fn activate_if_connected(
+    conn: &gio::DBusConnection,
+    failed_emissions: Rc<Cell<u32>>,
+    id: u32,
+    sender: String,
+) {
+    conn.call(
+        Some("org.freedesktop.DBus"),
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "NameHasOwner",
+        Some(&(sender,).to_variant()),
+        Some(glib::VariantTy::new("(b)").unwrap()),
+        gio::DBusCallFlags::NONE,
+        -1,
+        Option::<&gio::Cancellable>::None,
+        glib::clone!(
+            #[strong]
+            conn,
+            move |result| match result.map(|reply| reply.get::<(bool,)>()) {
+                Ok(Some((true,))) => {
+                    log::info!("Notification {id} activated from the panel");
+                    emit_signal(
+                        &conn,
+                        &failed_emissions,
+                        id,
+                        "ActionInvoked",
+                        (id, notification::DEFAULT_ACTION).to_variant(),
+                    );
+                }
+                Ok(_) => log::info!("The application of notification {id} is no longer running"),
+                Err(why) =>
+                    error!("Failed to check whether notification {id} can be activated: {why}"),
+            }
+        ),
+    );
+}
+
+/// Serve the D-Bus interfaces on the connection, which must own the notification bus name
+pub fn serve(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    conn: &gio::DBusConnection,
+) -> Result<(), glib::Error> {
+    let node_info = gio::DBusNodeInfo::for_xml(INTERFACE_XML).unwrap();
+    let notification_iface = node_info.lookup_interface(NOTIFICATIONS_IFACE).unwrap();
+    let control_iface = node_info.lookup_interface(CONTROL_IFACE).unwrap();
+
+    conn.register_object(NOTIFICATIONS_PATH, &notification_iface)
+        .typed_method_call::<NotificationMethod>()
+        .invoke(glib::clone!(
+            #[weak_allow_none]
+            app,
+            #[strong]
+            state,
+            move |conn, _sender, method, invocation| {
+                let app = app.unwrap();
+                notification_handler(app, state.clone(), conn, method, invocation);
+            }
+        ))
+        .build()?;
+
+    // Only read at startup, as xdg-desktop-portal has to be told to use Yand anyway
+    if state.borrow().config.portal {
+        portal::serve(app, state, conn)?;
+    }
+
+    conn.register_object("/com/kirottu/Yand", &control_iface)
+        .property(glib::clone!(
+            #[strong]
+            state,
+            move |_conn, _sender, _path, _interface, name| {
+                match name {
+                    // Including do not disturb during screencasts
+                    "NotificationLevel" => state.borrow().level().to_variant(),
+                    "Suppressed" => state.borrow().suppressed.to_variant(),
+                    _ => ().to_variant(),
+                }
+            }
+        ))
+        .set_property(glib::clone!(
+            #[weak_allow_none]
+            app,
+            #[strong]
+            state,
+            move |_conn, _sender, _path, _interface, name, val| {
+                match name {
+                    "NotificationLevel" => {
+                        if let Some(level) = NotificationLevel::from_variant(&val) {
+                            let mut _state = state.borrow_mut();
+                            _state.notification_level = level;
+                            if let Some(app) = app {
+                                release_held(&app, &state, &mut _state);
+                            }
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    _ => false,
+                }
+            }
+        ))
+        .typed_method_call::<ControlMethod>()
+        .invoke(glib::clone!(
+            #[strong]
+            state,
+            move |conn, _sender, method, invocation| {
+                match method {
+                    ControlMethod::Reload => {
+                        systemd::reloading();
+                        let outcome = state.borrow_mut().reload();
+                        systemd::notify("READY=1");
+                        invocation.return_value(Some(&outcome.to_variant()));
+                    }
+                    ControlMethod::SetOption(args) => {
+                        let option = match format!("{}={}", args.key, args.value).parse() {
+                            Ok(option) => option,
+                            Err(why) => {
+                                invocation.return_error(gio::DBusError::InvalidArgs, &why);
+                                return;
+                            }
+                        };
+                        let outcome = state.borrow_mut().set_option(option);
+                        invocation.return_value(Some(&outcome.to_variant()));
+                    }
+                    ControlMethod::ClearOptions => {
+                        let outcome = state.borrow_mut().reload();
+                        invocation.return_value(Some(&outcome.to_variant()));
+                    }
+                    ControlMethod::SetOffset(args) => {
+                        state.borrow_mut().offset = args.offset;
+                        state.borrow().recalculate_offsets();
+                        invocation.return_value(None);
+                    }
+                    ControlMethod::TogglePanel => match &state.borrow().panel {
+                        Some(panel) => {
+                            panel.emit(PanelInput::Toggle);
+                            invocation.return_value(None);
+                        }
+                        None => invocation.return_error(
+                            gio::DBusError::NotSupported,
+                            "The panel is not available in headless mode",
+                        ),
+                    },
+                    ControlMethod::KeyboardMode => {
+                        let mut _state = state.borrow_mut();
+                        if _state.headless.is_some() {
+                            invocation.return_error(
+                                gio::DBusError::NotSupported,
+                                "Keyboard mode is not available in headless mode",
+                            );
+                            return;
+                        }
+                        let first = _state.keyboard_targets().first().copied();
+                        _state.select(first);
+                        invocation.return_value(None);
+                    }
+                    ControlMethod::ListNotifications(args) => {
+                        let notifications = state.borrow().list(args.snoozed);
+                        invocation.return_value(Some(&(notifications,).to_variant()));
+                    }
+                    ControlMethod::CloseApp(args) => {
+                        let matcher = if args.regex {
+                            match AppMatcher::regex(&args.app_name) {
+                                Ok(matcher) => matcher,
+                                Err(why) => {
+                                    invocation.return_error(
+                                        gio::DBusError::InvalidArgs,
+                                        &why.to_string(),
+                                    );
+                                    return;
+                                }
+                            }
+                        } else {
+                            AppMatcher::name(&args.app_name)
+                        };
+
+                        let mut _state = state.borrow_mut();
+                        if _state.headless.is_some() {
+                            invocation.return_error(
+                                gio::DBusError::NotSupported,
+                                "Closing by application is not available in headless mode",
+                            );
+                            return;
+                        }
+                        let closed = _state.close_app(&conn, |app_name| matcher.matches(app_name));
+                        invocation.return_value(Some(&(closed,).to_variant()));
+                    }
+                    ControlMethod::GetHistory => {
+                        let history = state
+                            .borrow()
+                            .history
+                            .entries()
+                            .map(HistoryRecord::from)
+                            .collect::<Vec<_>>();
+                        invocation.return_value(Some(&(history,).to_variant()));
+                    }
+                    ControlMethod::GetStats => {
+                        let _state = state.borrow();
+                        invocation.return_value(Some(
+                            &(_state.stats.apps(), _state.stats.uptime()).to_variant(),
+                        ));
+                    }
+                    ControlMethod::ResetStats => {
+                        state.borrow_mut().stats.reset();
+                        log::info!("Stats reset");
+                        invocation.return_value(None);
+                    }
+                    ControlMethod::GetConfig => {
+                        let _state = state.borrow();
+                        let config = toml::to_string(&_state.file_config).unwrap_or_default();
+                        let options = _state
+                            .config_overlay
+                            .iter()
+                            .map(|option| (option.key().to_string(), "command-line".to_string()))
+                            .chain(
+                                _state.runtime_options.iter().map(|option| {
+                                    (option.key().to_string(), "runtime".to_string())
+                                }),
+                            )
+                            .collect::<Vec<_>>();
+                        invocation.return_value(Some(&(config, options).to_variant()));
+                    }
+                    ControlMethod::DndStatus => {
+                        let status = state.borrow().dnd_status();
+                        invocation.return_value(Some(&status.to_variant()));
+                    }
+                    ControlMethod::SetMinUrgency(args) => {
+                        let Ok(urgency) = Urgency::from_str(&args.urgency, true) else {
+                            invocation.return_error(
+                                gio::DBusError::InvalidArgs,
+                                "Expected low, normal or critical",
+                            );
+                            return;
+                        };
+                        let mut _state = state.borrow_mut();
+                        log::info!("Minimum urgency set to {urgency}");
+                        _state.min_urgency_override = Some(urgency);
+                        // Counting starts over with every new threshold
+                        _state.suppressed = 0;
+                        emit_properties_changed(
+                            &conn,
+                            &[("Suppressed", _state.suppressed.to_variant())],
+                        );
+                        invocation.return_value(None);
+                    }
+                    ControlMethod::MinUrgencyStatus => {
+                        let _state = state.borrow();
+                        let status = (_state.min_display_urgency().to_string(), _state.suppressed);
+                        invocation.return_value(Some(&status.to_variant()));
+                    }
+                }
+            }
+        ))
+        .build()?;
+
+    // Another daemon may take over the bus name, which leaves the connection open
+    let name_subscription = conn.subscribe_to_signal(
+        Some("org.freedesktop.DBus"),
+        Some("org.freedesktop.DBus"),
+        Some("NameOwnerChanged"),
+        Some("/org/freedesktop/DBus"),
+        Some(NOTIFICATIONS_IFACE),
+        gio::DBusSignalFlags::NONE,
+        glib::clone!(
+            #[weak]
+            app,
+            #[strong]
+            state,
+            move |signal| {
+                let Some((_, old_owner, new_owner)) =
+                    signal.parameters.get::<(String, String, String)>()
+                else {
+                    return;
+                };
+                if signal.connection.unique_name().as_deref() == Some(old_owner.as_str()) {
+                    name_lost(&app, &state, &new_owner);
+                }
+            }
+        ),
+    );
+    state.borrow_mut().signal_subscriptions = vec![name_subscription];
+
+    Ok(())
+}
+
+/// Reconnect once the connection is lost, so that notifications on the screen stay around
+/// when the bus restarts
+pub fn watch_connection(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    conn: &gio::DBusConnection,
+) {
+    conn.connect_closed(glib::clone!(
+        #[weak]
+        app,
+        #[strong]
+        state,
+        move |_, _, why| {
+            // Nothing to reconnect for, as the bus name belongs to someone else
+            if state.borrow().name_lost {
+                app.quit();
+                return;
+            }
+            match why {
+                Some(why) => error!("Lost the D-Bus connection: {why}"),
+                None => error!("Lost the D-Bus connection"),
+            }
+            reconnect(&app, &state, 0);
+        }
+    ));
+}
+
+fn name_lost(app: &gio::Application, state: &Rc<RefCell<DaemonState>>, new_owner: &str) {
+    let mut _state = state.borrow_mut();
+    if _state.name_lost {
+        return;
+    }
+    _state.name_lost = true;
+    if new_owner.is_empty() {
+        log::warn!("Lost the bus name {NOTIFICATIONS_IFACE}");
+    } else {
+        log::warn!("The bus name {NOTIFICATIONS_IFACE} was taken over by {new_owner}");
+    }
+
+    match _state.config.on_name_lost {
+        OnNameLost::Exit => {
+            drop(_state);
+            app.quit();
+        }
+        OnNameLost::Linger => {
+            log::info!("Keeping the notifications on the screen until they are closed");
+            // Nothing is going to be shown from the queue anymore
+            let mut dropped = _state
+                .pending
+                .drain(..)
+                .map(|init| init.id)
+                .collect::<Vec<_>>();
+            dropped.extend(_state.held.drain(..).map(|init| init.id));
+            for (id, snoozed) in _state.snoozed.drain() {
+                snoozed.source_id.remove();
+                dropped.push(id);
+            }
+            for id in dropped {
+                _state.record_closed(id, NotificationCloseReason::Undefined);
+            }
+            if _state.notifications.is_empty() {
+                drop(_state);
+                app.quit();
+            }
+        }
+    }
+}
+
+fn reconnect(app: &gio::Application, state: &Rc<RefCell<DaemonState>>, attempt: u32) {
+    let attempts = state.borrow().config.reconnect_attempts;
+    if attempt >= attempts {
+        error!("Failed to reconnect to D-Bus {attempts} times, giving up");
+        app.quit();
+        return;
+    }
+
+    // 1, 2, 4, 8... seconds, up to a minute
+    let delay = Duration::from_secs((1 << attempt.min(6)).min(60));
+    glib::timeout_add_local_once(
+        delay,
+        glib::clone!(
+            #[weak]
+            app,
+            #[strong]
+            state,
+            move || match connect(&app, &state) {
+                Ok(conn) => {
+                    log::info!("Reconnected to D-Bus");
+                    watch_connection(&app, &state, &conn);
+                    let mut _state = state.borrow_mut();
+                    _state.conn = conn;
+                    _state.failed_emissions = Rc::default();
+                }
+                Err(why) => {
+                    log::warn!("Failed to reconnect to D-Bus: {why}");
+                    reconnect(&app, &state, attempt + 1);
+                }
+            }
+        ),
+    );
+}
+
+/// Open a new connection to the session bus and serve the D-Bus interfaces on it
+fn connect(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+) -> Result<gio::DBusConnection, glib::Error> {
+    // The shared connection of the application can't be opened again
+    let address = gio::dbus_address_get_for_bus_sync(
+        gio::BusType::Session,
+        Option::<&gio::Cancellable>::None,
+    )?;
+    let conn = gio::DBusConnection::for_address_sync(
+        &address,
+        gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+            | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+        None,
+        Option::<&gio::Cancellable>::None,
+    )?;
+
+    let reply = conn.call_sync(
+        Some("org.freedesktop.DBus"),
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "RequestName",
+        // DBUS_NAME_FLAG_ALLOW_REPLACEMENT | DBUS_NAME_FLAG_DO_NOT_QUEUE
+        Some(&(NOTIFICATIONS_IFACE, 5u32).to_variant()),
+        Some(glib::VariantTy::new("(u)").unwrap()),
+        gio::DBusCallFlags::NONE,
+        -1,
+        Option::<&gio::Cancellable>::None,
+    )?;
+    // DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER
+    if reply.get::<(u32,)>() != Some((1,)) {
+        return Err(glib::Error::new(
+            gio::IOErrorEnum::Exists,
+            &format!("{NOTIFICATIONS_IFACE} is owned by someone else"),
+        ));
+    }
+
+    serve(app, state, &conn)?;
+    Ok(conn)
+}
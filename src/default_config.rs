@@ -0,0 +1,168 @@
+use crate::config::Config;
+
+/// The commented config the default config is generated from, describing every option and
+/// giving examples for the ones that are unset by default
+pub const TEMPLATE: &str = include_str!("../res/config.toml");
+
+/// Every option at its default value, commented out.
+///
+/// The values come from `Config::default()`, and the template only adds the descriptions, so
+/// the printed defaults can't drift from the real ones. Options without a line in the template
+/// are added at the end of their section.
+pub fn generate() -> String {
+    let defaults =
+        toml::Table::try_from(Config::default()).expect("the default config can be serialized");
+    let empty = toml::Table::new();
+
+    let mut lines = Vec::new();
+    let mut section = &defaults;
+    let mut listed = Vec::new();
+    // Right after the last option of the section
+    let mut end_of_section = 0;
+    for line in TEMPLATE.lines() {
+        if let Some(name) = section_name(line) {
+            lines.splice(end_of_section..end_of_section, unlisted(section, &listed));
+            listed.clear();
+            section = match defaults.get(name) {
+                Some(toml::Value::Table(table)) => table,
+                _ => &empty,
+            };
+            lines.push(line.to_string());
+            end_of_section = lines.len();
+            continue;
+        }
+        match option_key(line) {
+            Some(key) => {
+                listed.push(key);
+                // Options unset by default keep the example from the template
+                match section.get(key) {
+                    Some(value) => lines.push(format!("#{key} = {value}")),
+                    None => lines.push(line.to_string()),
+                }
+                end_of_section = lines.len();
+            }
+            None => lines.push(line.to_string()),
+        }
+    }
+    lines.splice(end_of_section..end_of_section, unlisted(section, &listed));
+
+    lines.join("\n") + "\n"
+}
+
+/// The name of a commented out `#[section]` header
+fn section_name(line: &str) -> Option<&str> {
+    line.strip_prefix("#[")?.strip_suffix(']')
+}
+
+/// The key of a commented out `#key = value` line
+fn option_key(line: &str) -> Option<&str> {
+    let (key, _) = line.strip_prefix('#')?.split_once(" = ")?;
+    key.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        .then_some(key)
+}
+
+/// The options of the section that have no line in the template. Tables are left to their own
+/// section.
+fn unlisted(section: &toml::Table, listed: &[&str]) -> Vec<String> {
+    section
+        .iter()
+        .filter(|(key, value)| !listed.contains(&key.as_str()) && !value.is_table())
+        .map(|(key, value)| format!("#{key} = {value}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Configs have no PartialEq, as compiled regexes can't be compared, but their debug
+    /// output shows every value
+    fn assert_round_trips(config: &Config) {
+        let printed = toml::to_string(config).unwrap();
+        let parsed = toml::from_str::<Config>(&printed).unwrap();
+        assert_eq!(format!("{parsed:?}"), format!("{config:?}"), "{printed}");
+    }
+
+    #[test]
+    fn default_config_round_trips() {
+        assert_round_trips(&Config::default());
+    }
+
+    #[test]
+    fn config_with_sections_round_trips() {
+        let config = toml::from_str::<Config>(
+            r#"
+            output = ["DP-3", 1]
+            layer_critical = "Overlay"
+            font = "Inter 11"
+            history_ignore = ["FreeOTP", { regex = "^bitwarden" }]
+            timeout_scale = { low = 0.5, normal = 1.0, critical = 0.0 }
+
+            [keys]
+            dismiss = "<Control>x"
+
+            [sounds]
+            critical = "dialog-warning"
+
+            [critical]
+            position = "bottom-center"
+
+            [[app_override]]
+            app_name = "Discord"
+            max_lines = 0
+            silent = true
+
+            [[output_override]]
+            output = "HDMI-A-1"
+            max_visible = 2
+
+            [[hook]]
+            event = "closed"
+            summary = "^Build"
+            command = "true"
+            "#,
+        )
+        .unwrap();
+        assert_round_trips(&config);
+    }
+
+    #[test]
+    fn generated_config_lists_every_option() {
+        let generated = generate();
+        let defaults = toml::Table::try_from(Config::default()).unwrap();
+        for (key, value) in &defaults {
+            // Tables are either sections of their own or inline tables
+            match value.as_table() {
+                Some(section) if generated.contains(&format!("#[{key}]\n")) => {
+                    for key in section.keys() {
+                        assert!(generated.contains(&format!("#{key} = ")), "{key}");
+                    }
+                }
+                _ => assert!(generated.contains(&format!("#{key} = {value}\n")), "{key}"),
+            }
+        }
+    }
+
+    #[test]
+    fn template_describes_every_option() {
+        let defaults = toml::Table::try_from(Config::default()).unwrap();
+        for key in defaults.keys() {
+            assert!(
+                TEMPLATE.contains(&format!("#{key} = ")) || TEMPLATE.contains(&format!("#[{key}]")),
+                "{key} is missing from res/config.toml"
+            );
+        }
+    }
+
+    #[test]
+    fn generated_config_parses() {
+        let uncommented = generate()
+            .lines()
+            .filter(|line| option_key(line).is_some() || section_name(line).is_some())
+            .map(|line| &line[1..])
+            .collect::<Vec<_>>()
+            .join("\n");
+        toml::from_str::<Config>(&uncommented).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+use gtk::{glib::translate::IntoGlib, pango, prelude::*};
+use gtk4 as gtk;
+use log::warn;
+
+use crate::config::Config;
+
+/// Generate CSS for the font related config options. Invalid fonts are logged and left out.
+pub fn css(config: &Config) -> String {
+    let families = gtk::Label::new(None)
+        .pango_context()
+        .list_families()
+        .iter()
+        .map(|family| family.name().to_lowercase())
+        .collect::<Vec<_>>();
+
+    let mut css = String::new();
+
+    for (selector, font) in [
+        (".notification", &config.font),
+        (".notification .summary", &config.summary_font),
+        (".notification .body", &config.body_font),
+    ] {
+        let declarations = match font {
+            Some(font) => declarations(font, config.font_scale, &families),
+            // Without an explicit size the scale applies to whatever the style uses
+            None if selector == ".notification" && config.font_scale != 1.0 => {
+                vec![format!("font-size: {}%;", config.font_scale * 100.0)]
+            }
+            None => continue,
+        };
+
+        if !declarations.is_empty() {
+            css.push_str(&format!("{selector} {{ {} }}\n", declarations.join(" ")));
+        }
+    }
+
+    css
+}
+
+fn declarations(font: &str, scale: f64, families: &[String]) -> Vec<String> {
+    let description = pango::FontDescription::from_string(font);
+    let fields = description.set_fields();
+    let mut declarations = Vec::new();
+
+    if let Some(family) = description.family() {
+        // Pango would otherwise silently fall back to some other font
+        let (installed, missing): (Vec<_>, Vec<_>) = family
+            .split(',')
+            .map(str::trim)
+            .filter(|family| !family.is_empty())
+            .partition(|family| families.contains(&family.to_lowercase()));
+
+        for family in missing {
+            warn!("Font family {family:?} of font {font:?} is not installed, ignoring it");
+        }
+        if !installed.is_empty() {
+            let installed = installed
+                .iter()
+                .map(|family| format!("\"{family}\""))
+                .collect::<Vec<_>>();
+            declarations.push(format!("font-family: {};", installed.join(", ")));
+        }
+    }
+
+    if fields.contains(pango::FontMask::SIZE) {
+        let size = description.size() as f64 / pango::SCALE as f64 * scale;
+        let unit = if description.is_size_absolute() {
+            "px"
+        } else {
+            "pt"
+        };
+        declarations.push(format!("font-size: {size}{unit};"));
+    } else if scale != 1.0 {
+        declarations.push(format!("font-size: {}%;", scale * 100.0));
+    }
+
+    if fields.contains(pango::FontMask::WEIGHT) {
+        declarations.push(format!(
+            "font-weight: {};",
+            description.weight().into_glib()
+        ));
+    }
+
+    if fields.contains(pango::FontMask::STYLE) {
+        let style = match description.style() {
+            pango::Style::Italic => "italic",
+            pango::Style::Oblique => "oblique",
+            _ => "normal",
+        };
+        declarations.push(format!("font-style: {style};"));
+    }
+
+    if declarations.is_empty() {
+        warn!("Font {font:?} could not be used, ignoring it");
+    }
+
+    declarations
+}
@@ -0,0 +1,248 @@
+use std::sync::LazyLock;
+
+use gtk4::{glib, pango};
+use regex::Regex;
+
+use crate::{config::Config, notification::NotificationInit};
+
+/// A markup tag, opening or closing
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+/// Conservative on purpose, anything looking like a web address with a scheme
+static URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"https?://[^\s<>"']+"#).unwrap());
+
+/// Unicode's line separator, which Pango breaks lines at like at a newline, but which is kept in
+/// the body when its newlines are turned into spaces
+pub const LINE_SEPARATOR: char = '\u{2028}';
+
+/// Expand a format string for the summary or body of a notification.
+///
+/// Supported placeholders are `%a` (app name), `%s` (summary), `%b` (body), `%i` (icon name)
+/// and `%u` (urgency), with `%%` producing a literal `%`. Text between `%(` and `%)` is left
+/// out if any placeholder inside it expands to nothing, which allows for separators that only
+/// show up when needed. The result is trimmed of surrounding whitespace.
+pub fn expand(template: &str, init: &NotificationInit) -> String {
+    let mut output = String::new();
+    // The text of the currently open optional group, and whether a placeholder in it was empty
+    let mut group: Option<(String, bool)> = None;
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        let text = if c == '%' {
+            match chars.next() {
+                Some('(') => {
+                    close_group(&mut output, group.replace((String::new(), false)));
+                    continue;
+                }
+                Some(')') => {
+                    close_group(&mut output, group.take());
+                    continue;
+                }
+                Some('%') | None => "%".to_string(),
+                Some(placeholder) => match value(placeholder, init) {
+                    Some(value) => {
+                        if let Some((_, empty)) = &mut group {
+                            *empty |= value.is_empty();
+                        }
+                        value
+                    }
+                    // Unknown placeholders are kept as is
+                    None => format!("%{placeholder}"),
+                },
+            }
+        } else {
+            c.to_string()
+        };
+
+        match &mut group {
+            Some((group_text, _)) => group_text.push_str(&text),
+            None => output.push_str(&text),
+        }
+    }
+
+    // An unterminated group is treated as if it was closed at the end
+    close_group(&mut output, group);
+
+    output.trim().to_string()
+}
+
+/// The body as shown on the notification, with the body format applied
+pub fn body(config: &Config, init: &NotificationInit) -> String {
+    // Remove all newlines to make sure GTK can properly truncate the label
+    // TODO: Configurable, figure out a better way to do this
+    let body = expand(&config.body_format, init).replace('\n', " ");
+    if config.linkify { linkify(&body) } else { body }
+}
+
+/// The text without markup, for places where it can't be displayed
+pub fn strip_markup(text: &str) -> String {
+    pango::parse_markup(text, '\0')
+        .map(|(_, text, _)| text.to_string())
+        .unwrap_or_else(|_| text.to_string())
+}
+
+/// Wrap the bare http and https URLs in the markup in links, leaving the ones already in a link
+/// alone.
+///
+/// Punctuation ending a sentence and closing brackets without an opening one in the URL are
+/// not counted as part of it.
+fn linkify(markup: &str) -> String {
+    let mut output = String::new();
+    // How many links the text is inside of
+    let mut depth = 0usize;
+    let mut last = 0;
+
+    for tag in TAG.find_iter(markup) {
+        linkify_text(&mut output, &markup[last..tag.start()], depth > 0);
+        let name = tag.as_str()[1..].trim_start();
+        if name.starts_with("a ") || name.starts_with("a>") {
+            depth += 1;
+        } else if name.starts_with("/a") {
+            depth = depth.saturating_sub(1);
+        }
+        output.push_str(tag.as_str());
+        last = tag.end();
+    }
+    linkify_text(&mut output, &markup[last..], depth > 0);
+
+    output
+}
+
+fn linkify_text(output: &mut String, text: &str, in_link: bool) {
+    if in_link {
+        output.push_str(text);
+        return;
+    }
+
+    let mut last = 0;
+    for found in URL.find_iter(text) {
+        // Only the end is trimmed, so the URL still starts where it was found
+        let url = trim_url(found.as_str());
+        output.push_str(&text[last..found.start()]);
+        // The text is already escaped, which is what the attribute needs too
+        output.push_str(&format!("<a href=\"{url}\">{url}</a>"));
+        last = found.start() + url.len();
+    }
+    output.push_str(&text[last..]);
+}
+
+/// The URL without what most likely follows it in the text
+fn trim_url(mut url: &str) -> &str {
+    // Escaped characters that can't be part of a URL
+    if let Some(end) = ["&lt;", "&gt;", "&quot;", "&apos;"]
+        .iter()
+        .filter_map(|entity| url.find(entity))
+        .min()
+    {
+        url = &url[..end];
+    }
+
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ':', ';', '!', '?']);
+        let trimmed = match trimmed.chars().last() {
+            Some(close @ (')' | ']' | '}')) => {
+                let open = match close {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                // Unbalanced brackets belong to the text around the URL
+                if trimmed.matches(open).count() < trimmed.matches(close).count() {
+                    &trimmed[..trimmed.len() - 1]
+                } else {
+                    trimmed
+                }
+            }
+            _ => trimmed,
+        };
+        if trimmed == url {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+fn close_group(output: &mut String, group: Option<(String, bool)>) {
+    if let Some((text, false)) = group {
+        output.push_str(&text);
+    }
+}
+
+fn value(placeholder: char, init: &NotificationInit) -> Option<String> {
+    // Summary and body may contain markup, the rest is escaped to be displayed as is
+    match placeholder {
+        'a' => Some(glib::markup_escape_text(&init.app_name).to_string()),
+        's' => Some(init.summary.clone()),
+        'b' => Some(init.body.clone()),
+        'i' => Some(glib::markup_escape_text(&init.app_icon).to_string()),
+        'u' => Some(init.hints.urgency.unwrap_or_default().to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_separators_survive_the_body_format() {
+        let init = NotificationInit {
+            body: format!("first\nmessage{LINE_SEPARATOR}second"),
+            ..Default::default()
+        };
+        assert_eq!(
+            body(&Config::default(), &init),
+            format!("first message{LINE_SEPARATOR}second")
+        );
+    }
+
+    #[test]
+    fn bare_urls_are_linked() {
+        assert_eq!(
+            linkify("see https://example.com/a?b=c&amp;d=e now"),
+            "see <a href=\"https://example.com/a?b=c&amp;d=e\">https://example.com/a?b=c&amp;d=e</a> now"
+        );
+        assert_eq!(
+            linkify("http://a.org and https://b.org"),
+            "<a href=\"http://a.org\">http://a.org</a> and <a href=\"https://b.org\">https://b.org</a>"
+        );
+    }
+
+    #[test]
+    fn links_are_not_wrapped_twice() {
+        let markup = "<a href=\"https://example.com\">https://example.com</a>";
+        assert_eq!(linkify(markup), markup);
+        let markup = "<b><a href=\"https://a.org\">the <i>https://a.org</i> site</a></b>";
+        assert_eq!(linkify(markup), markup);
+        assert_eq!(
+            linkify("<a href=\"https://a.org\">a</a> https://b.org"),
+            "<a href=\"https://a.org\">a</a> <a href=\"https://b.org\">https://b.org</a>"
+        );
+    }
+
+    #[test]
+    fn trailing_punctuation_is_left_out() {
+        for (text, url) in [
+            ("https://example.com.", "https://example.com"),
+            ("https://example.com/path?!", "https://example.com/path"),
+            ("https://example.com/a.b,", "https://example.com/a.b"),
+            ("(https://example.com)", "https://example.com"),
+            (
+                "https://en.wikipedia.org/wiki/Rust_(language))",
+                "https://en.wikipedia.org/wiki/Rust_(language)",
+            ),
+            ("https://example.com&gt;", "https://example.com"),
+        ] {
+            let linked = linkify(text);
+            assert!(
+                linked.contains(&format!("<a href=\"{url}\">{url}</a>")),
+                "{text} became {linked}"
+            );
+            // Nothing is lost around the link
+            assert_eq!(strip_tags(&linked), text);
+        }
+    }
+
+    fn strip_tags(markup: &str) -> String {
+        TAG.replace_all(markup, "").into_owned()
+    }
+}
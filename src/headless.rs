@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use gtk4::glib;
+use relm4::Sender;
+use serde_json::json;
+
+use crate::{
+    config::Config,
+    notification::{NotificationCloseReason, NotificationInit},
+};
+
+/// What the daemon tells the renderer of a program embedding it with `run_dbus_server`
+#[derive(Debug)]
+pub enum ServerMessage {
+    /// Show the notification, or update it if its ID is already shown
+    Notify(NotificationInit),
+    /// The notification is gone, whether the renderer asked for it or not
+    Closed {
+        id: u32,
+        reason: NotificationCloseReason,
+    },
+}
+
+/// What the renderer of a program embedding the daemon tells it
+#[derive(Debug)]
+pub enum RendererMessage {
+    /// The user dismissed the notification
+    Dismissed(u32),
+    /// The user invoked an action of the notification. Closing the notification afterwards is
+    /// up to the renderer, as resident notifications stay around.
+    ActionInvoked { id: u32, action: String },
+}
+
+/// Stand-in for the notification windows that prints notifications to stdout as JSON lines,
+/// making it possible to run without a compositor, or hands them to the renderer of an
+/// embedding program
+#[derive(Default)]
+pub struct Headless {
+    /// Shown notifications and the timeouts simulating their expiry
+    notifications: HashMap<u32, Option<glib::SourceId>>,
+    /// Gets the notifications instead of stdout
+    renderer: Option<Sender<ServerMessage>>,
+}
+
+impl Headless {
+    pub fn with_renderer(renderer: Sender<ServerMessage>) -> Self {
+        Self {
+            notifications: HashMap::new(),
+            renderer: Some(renderer),
+        }
+    }
+
+    /// Print the notification and start its expiry timeout, which calls `on_expired` once done
+    pub fn show(
+        &mut self,
+        mut init: NotificationInit,
+        config: &Config,
+        on_expired: impl FnOnce() + 'static,
+    ) {
+        // Nothing is played without a display, but the sound shows what would be
+        let silent = config.silent(&init.app_name, init.hints.desktop_entry.as_deref());
+        let sound = config.sound(&init, false).map(str::to_string);
+        let (config, overrides) = config
+            .clone()
+            .overridden(&init.app_name, init.hints.desktop_entry.as_deref());
+        let default_action = init.default_action();
+        let timeout = init.timeout(&config, &overrides);
+
+        let id = init.id;
+        match &self.renderer {
+            // A renderer that went away leaves the daemon running without one
+            Some(renderer) => {
+                let _ = renderer.send(ServerMessage::Notify(init));
+            }
+            None => {
+                println!(
+                    "{}",
+                    json!({
+                        "event": "notify",
+                        "id": init.id,
+                        "app_name": init.app_name,
+                        "app_icon": init.app_icon,
+                        "summary": init.summary,
+                        "body": init.body,
+                        "actions": init
+                            .actions
+                            .iter()
+                            .map(|(key, label)| json!({ "key": key, "label": label }))
+                            .collect::<Vec<_>>(),
+                        "default_action": default_action,
+                        "urgency": init.hints.urgency.unwrap_or_default().to_string(),
+                        "timeout": timeout.map(|timeout| timeout.as_secs_f64()),
+                        "silent": silent,
+                        "sound": sound,
+                    })
+                );
+            }
+        }
+
+        // Replacing a notification restarts the timeout
+        if let Some(Some(source_id)) = self.notifications.remove(&id) {
+            source_id.remove();
+        }
+
+        let source_id = timeout.map(|timeout| glib::timeout_add_local_once(timeout, on_expired));
+        self.notifications.insert(id, source_id);
+    }
+
+    /// Print the closing of a notification. Returns false if no such notification was shown.
+    pub fn close(&mut self, id: u32, reason: NotificationCloseReason) -> bool {
+        let Some(source_id) = self.notifications.remove(&id) else {
+            return false;
+        };
+
+        match (source_id, &reason) {
+            // The timeout source is already gone if it is what expired the notification
+            (Some(_), NotificationCloseReason::Expired) | (None, _) => (),
+            (Some(source_id), _) => source_id.remove(),
+        }
+
+        match &self.renderer {
+            Some(renderer) => {
+                let _ = renderer.send(ServerMessage::Closed { id, reason });
+            }
+            None => println!(
+                "{}",
+                json!({
+                    "event": "closed",
+                    "id": id,
+                    "reason": reason.to_string(),
+                })
+            ),
+        }
+
+        true
+    }
+
+    /// Whether the notification is shown
+    pub fn contains(&self, id: u32) -> bool {
+        self.notifications.contains_key(&id)
+    }
+
+    pub fn ids(&self) -> Vec<u32> {
+        self.notifications.keys().copied().collect()
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use gtk4::glib;
+
+use crate::notification::{ImageData, Urgency};
+
+/// Hints that are understood without being parsed into `Hints`
+const HANDLED_ELSEWHERE: [&str; 1] = ["x-canonical-append"];
+
+/// The supported hints of a notification
+#[derive(Debug, Default, Clone)]
+pub struct Hints {
+    pub action_icons: Option<bool>,
+    pub image_data: Option<ImageData>,
+    pub image_path: Option<String>,
+    pub resident: Option<bool>,
+    pub transient: Option<bool>,
+    pub suppress_sound: Option<bool>,
+    /// Progress in percent, negative for progress of unknown length
+    pub value: Option<i32>,
+    pub urgency: Option<Urgency>,
+    pub category: Option<String>,
+    pub desktop_entry: Option<String>,
+    /// Extra CSS classes of the root, from the `x-yand-css-class` hint
+    pub css_classes: Vec<String>,
+}
+
+impl Hints {
+    /// Parse the supported hints of notification `id`, returning them along with the names of
+    /// the hints that were recognized.
+    ///
+    /// Unrecognized hints are logged at debug level, and hints of the wrong type or out of range
+    /// are ignored.
+    pub fn from_map(
+        hints: &HashMap<String, glib::Variant>,
+        id: u32,
+        max_image_bytes: usize,
+    ) -> (Self, Vec<String>) {
+        let mut parsed = Self::default();
+        let mut recognized = Vec::new();
+
+        for (key, value) in hints {
+            match key.as_str() {
+                "action-icons" => parsed.action_icons = value.get(),
+                "image-data" => parsed.image_data = image_data(value, id, max_image_bytes),
+                "image-path" => parsed.image_path = value.get(),
+                "resident" => parsed.resident = value.get(),
+                "transient" => parsed.transient = value.get(),
+                "suppress-sound" => parsed.suppress_sound = value.get(),
+                "value" => parsed.value = value.get(),
+                "category" => parsed.category = value.get(),
+                "desktop-entry" => parsed.desktop_entry = value.get(),
+                "x-yand-css-class" => {
+                    if let Some(classes) = value.str() {
+                        parsed.css_classes = sanitize_css_classes(classes);
+                    }
+                }
+                // Out of range urgencies are ignored like values of the wrong type
+                "urgency" => {
+                    parsed.urgency = value
+                        .get::<u8>()
+                        .and_then(|urgency| Urgency::try_from(urgency).ok())
+                }
+                key if HANDLED_ELSEWHERE.contains(&key) => (),
+                _ => {
+                    log::debug!("Notification {id} has the unsupported hint {key}");
+                    continue;
+                }
+            }
+            recognized.push(key.clone());
+        }
+
+        (parsed, recognized)
+    }
+
+    /// Whether the `x-canonical-append` hint asks for the notification to be appended to the
+    /// previous one, given either as a boolean or as a string
+    pub fn append(hints: &HashMap<String, glib::Variant>) -> bool {
+        hints.get("x-canonical-append").is_some_and(|value| {
+            value.get::<bool>().unwrap_or_else(|| {
+                value
+                    .str()
+                    .is_some_and(|str| matches!(str, "true" | "allowed"))
+            })
+        })
+    }
+}
+
+/// Raw image data, unless it would take up more than `max_image_bytes`
+fn image_data(value: &glib::Variant, id: u32, max_image_bytes: usize) -> Option<ImageData> {
+    let (width, height, rowstride, has_alpha, _bits_per_sample, channels, data) =
+        value.get::<(i32, i32, i32, bool, i32, i32, Vec<u8>)>()?;
+
+    // The claimed dimensions are checked as well, as they decide how much
+    // memory the texture takes up regardless of what was actually sent
+    let channels = channels.max(if has_alpha { 4 } else { 3 });
+    let size = [width, height, channels]
+        .into_iter()
+        .try_fold(1usize, |size, dimension| {
+            size.checked_mul(usize::try_from(dimension).ok()?)
+        })
+        .map(|size| size.max(data.len()));
+
+    if !size.is_some_and(|size| size <= max_image_bytes) {
+        log::warn!(
+            "Notification {id} image data of {width}x{height} ({} bytes) exceeds max_image_bytes, ignoring it",
+            data.len()
+        );
+        return None;
+    }
+
+    Some(ImageData::new(width, height, rowstride, has_alpha, data))
+}
+
+/// Split space separated CSS classes, keeping only alphanumerics and dashes of each
+fn sanitize_css_classes(classes: &str) -> Vec<String> {
+    classes
+        .split_whitespace()
+        .map(|class| {
+            class
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .collect::<String>()
+        })
+        .filter(|class| !class.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use gtk4::prelude::*;
+
+    use super::*;
+
+    const MAX_IMAGE_BYTES: usize = 1024;
+
+    fn parse(hints: &[(&str, glib::Variant)]) -> (Hints, Vec<String>) {
+        let hints = hints
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect();
+        Hints::from_map(&hints, 1, MAX_IMAGE_BYTES)
+    }
+
+    fn image(width: i32, height: i32) -> glib::Variant {
+        let data = vec![0u8; (width * height * 4) as usize];
+        (width, height, width * 4, true, 8, 4, data).to_variant()
+    }
+
+    #[test]
+    fn missing_hints_are_unset() {
+        let (hints, recognized) = parse(&[]);
+        assert!(recognized.is_empty());
+        assert_eq!(hints.urgency, None);
+        assert_eq!(hints.resident, None);
+        assert_eq!(hints.value, None);
+        assert!(hints.image_data.is_none());
+        assert!(hints.css_classes.is_empty());
+    }
+
+    #[test]
+    fn supported_hints_are_parsed() {
+        let (hints, recognized) = parse(&[
+            ("urgency", 2u8.to_variant()),
+            ("resident", true.to_variant()),
+            ("value", 40i32.to_variant()),
+            ("desktop-entry", "firefox".to_variant()),
+            ("x-yand-css-class", "work  <b>urgent</b>".to_variant()),
+            ("image-data", image(4, 4)),
+            ("x-unknown", "?".to_variant()),
+        ]);
+        assert_eq!(hints.urgency, Some(Urgency::Critical));
+        assert_eq!(hints.resident, Some(true));
+        assert_eq!(hints.value, Some(40));
+        assert_eq!(hints.desktop_entry.as_deref(), Some("firefox"));
+        assert_eq!(hints.css_classes, ["work", "burgentb"]);
+        assert!(hints.image_data.is_some());
+        assert_eq!(recognized.len(), 6);
+        assert!(!recognized.contains(&"x-unknown".to_string()));
+    }
+
+    #[test]
+    fn hints_of_the_wrong_type_are_ignored() {
+        let (hints, _) = parse(&[
+            ("urgency", "2".to_variant()),
+            ("resident", 1u32.to_variant()),
+            ("transient", "true".to_variant()),
+            ("value", 40u8.to_variant()),
+            ("image-path", false.to_variant()),
+            ("category", 3i32.to_variant()),
+            ("image-data", "image.png".to_variant()),
+        ]);
+        assert_eq!(hints.urgency, None);
+        assert_eq!(hints.resident, None);
+        assert_eq!(hints.transient, None);
+        assert_eq!(hints.value, None);
+        assert_eq!(hints.image_path, None);
+        assert_eq!(hints.category, None);
+        assert!(hints.image_data.is_none());
+    }
+
+    #[test]
+    fn urgencies_out_of_range_are_ignored() {
+        for (byte, urgency) in [
+            (0u8, Some(Urgency::Low)),
+            (1, Some(Urgency::Normal)),
+            (2, Some(Urgency::Critical)),
+            (3, None),
+            (255, None),
+        ] {
+            let (hints, _) = parse(&[("urgency", byte.to_variant())]);
+            assert_eq!(hints.urgency, urgency, "{byte}");
+        }
+    }
+
+    #[test]
+    fn images_over_the_limit_are_dropped() {
+        let (hints, _) = parse(&[("image-data", image(64, 64))]);
+        assert!(hints.image_data.is_none());
+        // What the dimensions claim counts, not only what was sent
+        let lying = (4096, 4096, 16384, true, 8, 4, vec![0u8; 16]).to_variant();
+        let (hints, _) = parse(&[("image-data", lying)]);
+        assert!(hints.image_data.is_none());
+    }
+
+    #[test]
+    fn append_takes_booleans_and_strings() {
+        for (value, append) in [
+            (true.to_variant(), true),
+            (false.to_variant(), false),
+            ("allowed".to_variant(), true),
+            ("true".to_variant(), true),
+            ("no".to_variant(), false),
+            (1u8.to_variant(), false),
+        ] {
+            let hints = HashMap::from([("x-canonical-append".to_string(), value.clone())]);
+            assert_eq!(Hints::append(&hints), append, "{value}");
+        }
+        assert!(!Hints::append(&HashMap::new()));
+    }
+}
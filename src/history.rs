@@ -0,0 +1,179 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use gtk4::glib;
+use serde_json::json;
+
+use crate::notification::{DEFAULT_ACTION, NotificationCloseReason, NotificationInit, Urgency};
+
+/// A notification as it was received, kept around after it has been closed
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub id: u32,
+    pub app_name: String,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    pub urgency: Urgency,
+    pub category: Option<String>,
+    pub desktop_entry: Option<String>,
+    pub received: SystemTime,
+    /// When and why the notification was closed, if it was
+    pub closed: Option<(SystemTime, NotificationCloseReason)>,
+    /// The action the user invoked, if any
+    pub action: Option<String>,
+    /// Whether the notification can be activated
+    pub default_action: bool,
+    /// The unique bus name of the application that sent the notification
+    pub sender: Option<String>,
+}
+
+impl From<&NotificationInit> for HistoryEntry {
+    fn from(init: &NotificationInit) -> Self {
+        Self {
+            id: init.id,
+            app_name: init.app_name.clone(),
+            app_icon: init.app_icon.clone(),
+            summary: init.summary.clone(),
+            body: init.body.clone(),
+            urgency: init.hints.urgency.unwrap_or_default(),
+            category: init.hints.category.clone(),
+            desktop_entry: init.hints.desktop_entry.clone(),
+            received: SystemTime::now(),
+            closed: None,
+            action: None,
+            default_action: init.actions.iter().any(|(key, _)| key == DEFAULT_ACTION),
+            sender: None,
+        }
+    }
+}
+
+/// The most recent notifications, oldest first
+#[derive(Debug, Default)]
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl History {
+    /// Record a notification, taking the place of the notification it replaces
+    pub fn push(&mut self, entry: HistoryEntry, capacity: usize) {
+        self.entries.retain(|existing| existing.id != entry.id);
+        self.entries.push_back(entry);
+        self.truncate(capacity);
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut HistoryEntry> {
+        self.entries.iter_mut().find(|entry| entry.id == id)
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<HistoryEntry> {
+        let index = self.entries.iter().position(|entry| entry.id == id)?;
+        self.entries.remove(index)
+    }
+
+    pub fn retain(&mut self, keep: impl FnMut(&HistoryEntry) -> bool) {
+        self.entries.retain(keep);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Drop the oldest entries until at most `capacity` remain
+    pub fn truncate(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+}
+
+/// A history entry as sent over D-Bus, with empty strings and zero timestamps for missing values
+#[derive(Debug, glib::Variant)]
+pub struct HistoryRecord {
+    id: u32,
+    app_name: String,
+    summary: String,
+    body: String,
+    urgency: String,
+    category: String,
+    /// Microseconds since the Unix epoch
+    received: i64,
+    closed: i64,
+    close_reason: String,
+    action: String,
+}
+
+impl From<&HistoryEntry> for HistoryRecord {
+    fn from(entry: &HistoryEntry) -> Self {
+        Self {
+            id: entry.id,
+            app_name: entry.app_name.clone(),
+            summary: entry.summary.clone(),
+            body: entry.body.clone(),
+            urgency: entry.urgency.to_string(),
+            category: entry.category.clone().unwrap_or_default(),
+            received: unix_micros(entry.received),
+            closed: entry.closed.map(|(time, _)| unix_micros(time)).unwrap_or(0),
+            close_reason: entry
+                .closed
+                .map(|(_, reason)| reason.to_string())
+                .unwrap_or_default(),
+            action: entry.action.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Write the records as a JSON array, one record at a time.
+///
+/// Missing values are `null` and timestamps are in RFC 3339.
+pub fn export(records: &[HistoryRecord], mut writer: impl Write) -> io::Result<()> {
+    writer.write_all(b"[")?;
+    for (i, record) in records.iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        writer.write_all(b"\n  ")?;
+        let value = json!({
+            "id": record.id,
+            "app_name": record.app_name,
+            "summary": record.summary,
+            "body": record.body,
+            "urgency": record.urgency,
+            "category": non_empty(&record.category),
+            "received": rfc3339(record.received),
+            "closed": rfc3339(record.closed),
+            "close_reason": non_empty(&record.close_reason),
+            "action": non_empty(&record.action),
+        });
+        serde_json::to_writer(&mut writer, &value)?;
+    }
+    writer.write_all(b"\n]\n")?;
+    writer.flush()
+}
+
+fn unix_micros(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+fn rfc3339(micros: i64) -> Option<String> {
+    if micros == 0 {
+        return None;
+    }
+    glib::DateTime::from_unix_utc(micros / 1_000_000)
+        .and_then(|time| time.format("%Y-%m-%dT%H:%M:%SZ"))
+        .map(String::from)
+        .ok()
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    (!value.is_empty()).then_some(value)
+}
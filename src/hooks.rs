@@ -0,0 +1,253 @@
+use std::{ffi::OsStr, time::Duration};
+
+use gtk4::{gio, glib, prelude::*};
+use log::{error, warn};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Value, json};
+
+use crate::notification::{NotificationCloseReason, NotificationInit, Urgency};
+
+/// When a hook is run
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HookEvent {
+    #[default]
+    Received,
+    Closed,
+}
+
+/// A command run for every notification matching all of the given matchers
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Hook {
+    #[serde(default)]
+    event: HookEvent,
+    app_name: Option<String>,
+    urgency: Option<Urgency>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_regex",
+        serialize_with = "serialize_regex"
+    )]
+    summary: Option<Regex>,
+    /// Run with `sh -c`
+    command: String,
+    /// Seconds after which the command is killed
+    timeout: Option<u32>,
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Regex>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|regex| Regex::new(&regex).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+fn serialize_regex<S: Serializer>(regex: &Option<Regex>, serializer: S) -> Result<S::Ok, S::Error> {
+    regex.as_ref().map(Regex::as_str).serialize(serializer)
+}
+
+impl Hook {
+    fn matches(&self, init: &NotificationInit) -> bool {
+        self.app_name
+            .as_ref()
+            .is_none_or(|app_name| *app_name == init.app_name)
+            && self
+                .urgency
+                .is_none_or(|urgency| urgency == init.hints.urgency.unwrap_or_default())
+            && self
+                .summary
+                .as_ref()
+                .is_none_or(|summary| summary.is_match(&init.summary))
+    }
+
+    pub fn event(&self) -> HookEvent {
+        self.event
+    }
+
+    fn spawn(&self, init: &NotificationInit, env: &[(&str, &str)]) -> Result<(), glib::Error> {
+        let launcher = gio::SubprocessLauncher::new(gio::SubprocessFlags::STDIN_PIPE);
+        for (variable, value) in env {
+            launcher.setenv(variable, value, true);
+        }
+        launcher.setenv("YAND_ID", init.id.to_string(), true);
+        launcher.setenv("YAND_APP_NAME", &init.app_name, true);
+        launcher.setenv("YAND_SUMMARY", &init.summary, true);
+        launcher.setenv("YAND_BODY", &init.body, true);
+        launcher.setenv(
+            "YAND_URGENCY",
+            init.hints.urgency.unwrap_or_default().to_string(),
+            true,
+        );
+
+        let process = launcher.spawn(&[
+            OsStr::new("sh"),
+            OsStr::new("-c"),
+            OsStr::new(&self.command),
+        ])?;
+
+        if let Some(timeout) = self.timeout {
+            glib::timeout_add_local_once(
+                Duration::from_secs(timeout as u64),
+                glib::clone!(
+                    #[weak]
+                    process,
+                    #[strong(rename_to = command)]
+                    self.command,
+                    move || {
+                        // The identifier is gone once the process has been reaped, much like
+                        // try_wait
+                        if process.identifier().is_none() {
+                            return;
+                        }
+                        warn!("Hook {command:?} timed out, killing it");
+                        process.force_exit();
+                    }
+                ),
+            );
+        }
+
+        // Written in the background, as the command may never read it
+        if let Some(stdin) = process.stdin_pipe() {
+            let mut input = to_json(init).to_string();
+            input.push('\n');
+            stdin.write_all_async(
+                input,
+                glib::Priority::DEFAULT,
+                gio::Cancellable::NONE,
+                glib::clone!(
+                    #[strong]
+                    stdin,
+                    move |result| {
+                        // Commands that exit without reading their input are fine
+                        if let Err((_, why)) = result {
+                            if !why.matches(gio::IOErrorEnum::BrokenPipe) {
+                                warn!("Failed to write to hook: {why}");
+                            }
+                        }
+                        stdin.close_async(glib::Priority::DEFAULT, gio::Cancellable::NONE, |_| ());
+                    }
+                ),
+            );
+        }
+
+        let command = self.command.clone();
+        process.wait_check_async(gio::Cancellable::NONE, move |result| {
+            if let Err(why) = result {
+                warn!("Hook {command:?} failed: {why}");
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// The notification as passed to hooks on stdin
+fn to_json(init: &NotificationInit) -> Value {
+    json!({
+        "id": init.id,
+        "app_name": init.app_name,
+        "app_icon": init.app_icon,
+        "summary": init.summary,
+        "body": init.body,
+        "actions": init
+            .actions
+            .iter()
+            .map(|(key, label)| json!({ "key": key, "label": label }))
+            .collect::<Vec<_>>(),
+        "expire_timeout": init.expire_timeout,
+        "urgency": init.hints.urgency.unwrap_or_default().to_string(),
+        "hints": init
+            .raw_hints
+            .iter()
+            .map(|(key, value)| (key.clone(), variant_to_json(value)))
+            .collect::<serde_json::Map<_, _>>(),
+    })
+}
+
+/// Convert a hint to JSON, with dictionaries as objects
+fn variant_to_json(variant: &glib::Variant) -> Value {
+    match variant.classify() {
+        glib::VariantClass::Boolean => variant.get::<bool>().into(),
+        glib::VariantClass::Byte => variant.get::<u8>().into(),
+        glib::VariantClass::Int16 => variant.get::<i16>().into(),
+        glib::VariantClass::Uint16 => variant.get::<u16>().into(),
+        glib::VariantClass::Int32 => variant.get::<i32>().into(),
+        glib::VariantClass::Uint32 => variant.get::<u32>().into(),
+        glib::VariantClass::Int64 => variant.get::<i64>().into(),
+        glib::VariantClass::Uint64 => variant.get::<u64>().into(),
+        glib::VariantClass::Double => variant.get::<f64>().into(),
+        glib::VariantClass::String
+        | glib::VariantClass::ObjectPath
+        | glib::VariantClass::Signature => variant.str().into(),
+        glib::VariantClass::Variant => variant
+            .as_variant()
+            .map(|inner| variant_to_json(&inner))
+            .unwrap_or_default(),
+        glib::VariantClass::Maybe => variant
+            .as_maybe()
+            .map(|inner| variant_to_json(&inner))
+            .unwrap_or_default(),
+        glib::VariantClass::Array if variant.type_().element().is_dict_entry() => variant
+            .iter()
+            .map(|entry| {
+                let key = entry.child_value(0);
+                let key = key
+                    .str()
+                    .map(String::from)
+                    .unwrap_or_else(|| key.print(false).into());
+                (key, variant_to_json(&entry.child_value(1)))
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        glib::VariantClass::Array | glib::VariantClass::Tuple | glib::VariantClass::DictEntry => {
+            variant
+                .iter()
+                .map(|child| variant_to_json(&child))
+                .collect::<Vec<_>>()
+                .into()
+        }
+        _ => Value::Null,
+    }
+}
+
+/// Run the hooks for received notifications matching the notification in the background
+pub fn run(hooks: &[Hook], init: &NotificationInit) {
+    run_event(hooks, HookEvent::Received, init, &[]);
+}
+
+/// Run the hooks for closed notifications matching the notification in the background,
+/// with the action the notification was closed by if any
+pub fn run_closed(
+    hooks: &[Hook],
+    init: &NotificationInit,
+    reason: NotificationCloseReason,
+    action: Option<&str>,
+) {
+    match action {
+        Some(action) => run_event(
+            hooks,
+            HookEvent::Closed,
+            init,
+            &[("YAND_CLOSE_REASON", "action"), ("YAND_ACTION", action)],
+        ),
+        None => run_event(
+            hooks,
+            HookEvent::Closed,
+            init,
+            &[("YAND_CLOSE_REASON", &reason.to_string())],
+        ),
+    }
+}
+
+fn run_event(hooks: &[Hook], event: HookEvent, init: &NotificationInit, env: &[(&str, &str)]) {
+    for hook in hooks
+        .iter()
+        .filter(|hook| hook.event == event && hook.matches(init))
+    {
+        if let Err(why) = hook.spawn(init, env) {
+            error!("Failed to run hook {:?}: {why}", hook.command);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use gtk::{gdk, gdk::gdk_pixbuf, glib, prelude::*};
+use gtk4 as gtk;
+
+use crate::notification::ImageData;
+
+const MAX_ENTRIES: usize = 32;
+/// Upper bound for the decoded size of all cached textures combined
+const MAX_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+enum CacheKey {
+    File {
+        path: PathBuf,
+        modified: Option<SystemTime>,
+        /// The size vector images were rendered at
+        size: Option<i32>,
+    },
+    /// Hash of raw image data and its layout
+    Data(u64),
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    key: CacheKey,
+    texture: gdk::Texture,
+    bytes: usize,
+}
+
+/// Least recently used cache of decoded icons, so that notifications repeatedly using the same
+/// image don't have to decode it again
+#[derive(Debug, Default)]
+pub struct IconCache {
+    /// Ordered from least to most recently used
+    entries: VecDeque<CacheEntry>,
+    bytes: usize,
+}
+
+impl IconCache {
+    /// Load an image file, reusing the cached texture if the file has not been modified since.
+    ///
+    /// SVG images are rendered at `pixel_size` physical pixels rather than their own size, so
+    /// that they stay sharp instead of being scaled up afterwards.
+    pub fn file(&mut self, path: &Path, pixel_size: i32) -> Result<gdk::Texture, glib::Error> {
+        let vector = path
+            .extension()
+            .is_some_and(|extension| extension == "svg" || extension == "svgz");
+        let key = CacheKey::File {
+            path: path.to_path_buf(),
+            modified: fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+            size: vector.then_some(pixel_size),
+        };
+
+        if let Some(texture) = self.get(&key) {
+            return Ok(texture);
+        }
+
+        // Any outdated entry for the same file is of no use anymore
+        self.remove_where(
+            |entry| matches!(&entry.key, CacheKey::File { path: cached, .. } if cached == path),
+        );
+
+        let texture = if vector {
+            // Keeps the aspect ratio, fitting the image into the square
+            let pixbuf = gdk_pixbuf::Pixbuf::from_file_at_size(path, pixel_size, pixel_size)?;
+            gdk::Texture::for_pixbuf(&pixbuf)
+        } else {
+            raster_texture(path)?
+        };
+        self.insert(key, texture.clone());
+        Ok(texture)
+    }
+
+    /// The texture of raw image data decoded at `pixel_size` before, like the album art music
+    /// players send with every replacement
+    pub fn cached_data(&mut self, data: &ImageData, pixel_size: i32) -> Option<gdk::Texture> {
+        self.get(&data_key(data, pixel_size))
+    }
+
+    /// Keep the texture of raw image data decoded with `decode_data`
+    pub fn insert_data(&mut self, data: &ImageData, pixel_size: i32, texture: gdk::Texture) {
+        let key = data_key(data, pixel_size);
+        // Notifications with the same image may have decoded it at the same time
+        if self.get(&key).is_none() {
+            self.insert(key, texture);
+        }
+    }
+
+    /// The size in physical pixels for icons of `icon_size` logical pixels.
+    ///
+    /// Uses the largest scale of all connected monitors, so that icons stay sharp wherever the
+    /// notification ends up.
+    pub fn pixel_size(icon_size: i32) -> i32 {
+        let scale = gdk::Display::default()
+            .and_then(|display| {
+                display
+                    .monitors()
+                    .iter::<gdk::Monitor>()
+                    .filter_map(Result::ok)
+                    .map(|monitor| monitor.scale())
+                    .reduce(f64::max)
+            })
+            .unwrap_or(1.0);
+        (icon_size as f64 * scale).ceil() as i32
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<gdk::Texture> {
+        let index = self.entries.iter().position(|entry| entry.key == *key)?;
+        let entry = self.entries.remove(index)?;
+        let texture = entry.texture.clone();
+        self.entries.push_back(entry);
+        Some(texture)
+    }
+
+    fn insert(&mut self, key: CacheKey, texture: gdk::Texture) {
+        let bytes = texture.width() as usize * texture.height() as usize * 4;
+        // Not worth evicting everything else for
+        if bytes > MAX_BYTES {
+            return;
+        }
+
+        while self.entries.len() >= MAX_ENTRIES || self.bytes + bytes > MAX_BYTES {
+            let Some(entry) = self.entries.pop_front() else {
+                break;
+            };
+            self.bytes -= entry.bytes;
+        }
+
+        self.bytes += bytes;
+        self.entries.push_back(CacheEntry {
+            key,
+            texture,
+            bytes,
+        });
+    }
+
+    fn remove_where(&mut self, predicate: impl Fn(&CacheEntry) -> bool) {
+        let bytes = &mut self.bytes;
+        self.entries.retain(|entry| {
+            let remove = predicate(entry);
+            if remove {
+                *bytes -= entry.bytes;
+            }
+            !remove
+        });
+    }
+}
+
+fn data_key(data: &ImageData, pixel_size: i32) -> CacheKey {
+    let mut hasher = DefaultHasher::new();
+    (data.hash, pixel_size).hash(&mut hasher);
+    CacheKey::Data(hasher.finish())
+}
+
+/// Create a texture from raw image data. Takes long enough for large images to be done off the
+/// main thread, which this can be called from.
+///
+/// Images larger than `pixel_size` physical pixels are scaled down to it, smaller ones are left
+/// for GTK to scale up.
+pub fn decode_data(data: &ImageData, pixel_size: i32) -> gdk::Texture {
+    let bytes = &data.data;
+    let largest = data.width.max(data.height);
+
+    if largest > pixel_size {
+        let pixbuf = gdk_pixbuf::Pixbuf::from_bytes(
+            bytes,
+            gdk_pixbuf::Colorspace::Rgb,
+            data.has_alpha,
+            8,
+            data.width,
+            data.height,
+            data.rowstride,
+        );
+        let scaled = pixbuf
+            .scale_simple(
+                (data.width * pixel_size / largest).max(1),
+                (data.height * pixel_size / largest).max(1),
+                gdk_pixbuf::InterpType::Bilinear,
+            )
+            .unwrap_or(pixbuf);
+        gdk::Texture::for_pixbuf(&scaled)
+    } else {
+        let format = if data.has_alpha {
+            gdk::MemoryFormat::R8g8b8a8
+        } else {
+            gdk::MemoryFormat::R8g8b8
+        };
+        gdk::MemoryTexture::new(
+            data.width,
+            data.height,
+            format,
+            bytes,
+            data.rowstride as usize,
+        )
+        .into()
+    }
+}
+
+/// Load a raster image, turned upright according to its EXIF orientation, like photos taken
+/// with a phone often need to be.
+///
+/// GTK's own loaders are used for formats gdk-pixbuf can't load, without any orientation.
+fn raster_texture(path: &Path) -> Result<gdk::Texture, glib::Error> {
+    match gdk_pixbuf::Pixbuf::from_file(path) {
+        Ok(pixbuf) => {
+            let pixbuf = pixbuf.apply_embedded_orientation().unwrap_or(pixbuf);
+            Ok(gdk::Texture::for_pixbuf(&pixbuf))
+        }
+        Err(_) => gdk::Texture::from_filename(path),
+    }
+}
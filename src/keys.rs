@@ -0,0 +1,190 @@
+use std::{collections::HashMap, fmt::Display};
+
+use gtk::gdk;
+use gtk4 as gtk;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, ser::SerializeMap};
+
+/// What a key does in keyboard mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyAction {
+    /// Select the notification after the selected one in the stack
+    Next,
+    /// Select the notification before the selected one in the stack
+    Prev,
+    Dismiss,
+    DismissAll,
+    InvokeDefault,
+    /// Invoke the action button at this position, starting from 1
+    Invoke(usize),
+    Exit,
+}
+
+impl KeyAction {
+    const ALL: [KeyAction; 15] = [
+        KeyAction::Next,
+        KeyAction::Prev,
+        KeyAction::Dismiss,
+        KeyAction::DismissAll,
+        KeyAction::InvokeDefault,
+        KeyAction::Invoke(1),
+        KeyAction::Invoke(2),
+        KeyAction::Invoke(3),
+        KeyAction::Invoke(4),
+        KeyAction::Invoke(5),
+        KeyAction::Invoke(6),
+        KeyAction::Invoke(7),
+        KeyAction::Invoke(8),
+        KeyAction::Invoke(9),
+        KeyAction::Exit,
+    ];
+}
+
+impl Display for KeyAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyAction::Next => f.write_str("next"),
+            KeyAction::Prev => f.write_str("prev"),
+            KeyAction::Dismiss => f.write_str("dismiss"),
+            KeyAction::DismissAll => f.write_str("dismiss-all"),
+            KeyAction::InvokeDefault => f.write_str("invoke-default"),
+            KeyAction::Invoke(n) => write!(f, "invoke-{n}"),
+            KeyAction::Exit => f.write_str("exit"),
+        }
+    }
+}
+
+/// The keys of keyboard mode, by action
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keys {
+    bindings: Vec<(KeyAction, gdk::Key, gdk::ModifierType)>,
+}
+
+impl Keys {
+    /// The action bound to the pressed key, if any
+    pub fn action(&self, key: gdk::Key, modifiers: gdk::ModifierType) -> Option<KeyAction> {
+        let key = key.to_lower();
+        let modifiers = modifiers & ACCELERATOR_MODIFIERS;
+        self.bindings
+            .iter()
+            .find(|(_, bound_key, bound_modifiers)| {
+                *bound_key == key && *bound_modifiers == modifiers
+            })
+            .map(|(action, _, _)| *action)
+    }
+
+    fn bind(&mut self, action: KeyAction, accelerator: &str) -> Result<(), String> {
+        let (key, modifiers) = parse_accelerator(accelerator)
+            .ok_or_else(|| format!("cannot parse `{accelerator}` for {action} in [keys]"))?;
+        // Rebound in place, so that the bindings keep the order of KeyAction::ALL
+        match self
+            .bindings
+            .iter_mut()
+            .find(|(bound, _, _)| *bound == action)
+        {
+            Some(binding) => *binding = (action, key, modifiers),
+            None => self.bindings.push((action, key, modifiers)),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        let mut keys = Self {
+            bindings: Vec::new(),
+        };
+        for action in KeyAction::ALL {
+            let accelerator = match action {
+                KeyAction::Next => "j".to_string(),
+                KeyAction::Prev => "k".to_string(),
+                KeyAction::Dismiss => "d".to_string(),
+                KeyAction::DismissAll => "<Shift>d".to_string(),
+                KeyAction::InvokeDefault => "Return".to_string(),
+                KeyAction::Invoke(n) => n.to_string(),
+                KeyAction::Exit => "Escape".to_string(),
+            };
+            keys.bind(action, &accelerator).unwrap();
+        }
+        keys
+    }
+}
+
+/// A table of action names to accelerators, leaving the other actions at their defaults
+impl<'de> Deserialize<'de> for Keys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = HashMap::<String, String>::deserialize(deserializer)?;
+
+        let mut keys = Keys::default();
+        for (name, accelerator) in table {
+            let Some(action) = KeyAction::ALL
+                .into_iter()
+                .find(|action| action.to_string() == name)
+            else {
+                let names = KeyAction::ALL.map(|action| action.to_string());
+                return Err(serde::de::Error::custom(format!(
+                    "unknown action `{name}` in [keys], expected one of: {}",
+                    names.join(", ")
+                )));
+            };
+            keys.bind(action, &accelerator)
+                .map_err(serde::de::Error::custom)?;
+        }
+        Ok(keys)
+    }
+}
+
+/// Every action with its accelerator
+impl Serialize for Keys {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.bindings.len()))?;
+        for (action, key, modifiers) in &self.bindings {
+            let accelerator = format_accelerator(*key, *modifiers).ok_or_else(|| {
+                serde::ser::Error::custom(format!("{action} is bound to a key without a name"))
+            })?;
+            map.serialize_entry(&action.to_string(), &accelerator)?;
+        }
+        map.end()
+    }
+}
+
+const ACCELERATOR_MODIFIERS: gdk::ModifierType = gdk::ModifierType::SHIFT_MASK
+    .union(gdk::ModifierType::CONTROL_MASK)
+    .union(gdk::ModifierType::ALT_MASK)
+    .union(gdk::ModifierType::SUPER_MASK);
+
+/// Parse an accelerator like `<Shift>d` the way `gtk::accelerator_parse` does, which can't be
+/// used without a display, like when running headless or checking the config
+fn parse_accelerator(accelerator: &str) -> Option<(gdk::Key, gdk::ModifierType)> {
+    let mut modifiers = gdk::ModifierType::empty();
+    let mut rest = accelerator.trim();
+    while let Some(modifier) = rest.strip_prefix('<') {
+        let (name, after) = modifier.split_once('>')?;
+        modifiers |= match name.to_lowercase().as_str() {
+            "shift" => gdk::ModifierType::SHIFT_MASK,
+            "control" | "ctrl" | "primary" => gdk::ModifierType::CONTROL_MASK,
+            "alt" | "mod1" => gdk::ModifierType::ALT_MASK,
+            "super" => gdk::ModifierType::SUPER_MASK,
+            _ => return None,
+        };
+        rest = after;
+    }
+    let key = gdk::Key::from_name(rest)?;
+    Some((key.to_lower(), modifiers))
+}
+
+/// Write an accelerator that `parse_accelerator` reads back
+fn format_accelerator(key: gdk::Key, modifiers: gdk::ModifierType) -> Option<String> {
+    let names = [
+        (gdk::ModifierType::SHIFT_MASK, "<Shift>"),
+        (gdk::ModifierType::CONTROL_MASK, "<Control>"),
+        (gdk::ModifierType::ALT_MASK, "<Alt>"),
+        (gdk::ModifierType::SUPER_MASK, "<Super>"),
+    ];
+    let mut accelerator = names
+        .into_iter()
+        .filter(|(modifier, _)| modifiers.contains(*modifier))
+        .map(|(_, name)| name)
+        .collect::<String>();
+    accelerator.push_str(&key.name()?);
+    Some(accelerator)
+}
@@ -0,0 +1,69 @@
+use gtk4 as gtk;
+use gtk4_layer_shell::{Edge, Layer, LayerShell};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// The corner or edge of the screen that windows are placed at
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Position {
+    TopLeft,
+    TopCenter,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl Position {
+    /// The edge that windows are stacked away from
+    pub fn anchor_edge(self) -> Edge {
+        match self {
+            Position::TopLeft | Position::TopCenter | Position::TopRight => Edge::Top,
+            Position::BottomLeft | Position::BottomCenter | Position::BottomRight => Edge::Bottom,
+        }
+    }
+
+    /// The side edge, None when horizontally centered
+    fn side_edge(self) -> Option<Edge> {
+        match self {
+            Position::TopLeft | Position::BottomLeft => Some(Edge::Left),
+            Position::TopRight | Position::BottomRight => Some(Edge::Right),
+            Position::TopCenter | Position::BottomCenter => None,
+        }
+    }
+}
+
+/// Turn the window into a layer shell surface anchored to the configured position.
+///
+/// Must be called before the window is realized.
+pub fn init(window: &gtk::Window, config: &Config, namespace: &str, layer: Layer) {
+    window.init_layer_shell();
+    window.set_namespace(Some(namespace));
+    apply_config(window, config, layer);
+}
+
+/// Apply the config options shared by all of Yand's windows
+pub fn apply_config(window: &gtk::Window, config: &Config, layer: Layer) {
+    window.set_layer(layer);
+
+    let anchor_edge = config.position.anchor_edge();
+    let side_edge = config.position.side_edge();
+    // Edges from a previous position are cleared, as the window may be moved around
+    for edge in [Edge::Top, Edge::Bottom, Edge::Left, Edge::Right] {
+        let anchored = edge == anchor_edge || Some(edge) == side_edge;
+        window.set_anchor(edge, anchored);
+        window.set_margin(
+            edge,
+            if edge == anchor_edge {
+                config.margin_anchor
+            } else if Some(edge) == side_edge {
+                config.margin_side
+            } else {
+                0
+            },
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::{fmt::Display, io, path::PathBuf, process::ExitCode};
+
+use gtk4::glib;
+use log::error;
+use relm4::Sender;
+
+use crate::daemon::serve_renderer;
+
+pub use crate::{
+    cli::main,
+    config::{Config, ConfigBuilder},
+    daemon::NotificationLevel,
+    headless::{RendererMessage, ServerMessage},
+    hints::Hints,
+    notification::{ImageData, NotificationCloseReason, NotificationInit, Urgency},
+};
+
+mod app_matcher;
+mod check;
+mod cli;
+mod config;
+mod config_diff;
+mod config_keys;
+mod config_overlay;
+mod daemon;
+mod dbus;
+mod default_config;
+mod font;
+mod format;
+mod headless;
+mod hints;
+mod history;
+mod hooks;
+mod icon_cache;
+mod keys;
+mod layer;
+mod logger;
+mod monitor;
+mod notification;
+mod notification_log;
+mod notification_stack;
+mod output;
+mod overflow_indicator;
+mod panel;
+mod portal;
+mod queue;
+mod screencast;
+mod sound;
+mod stats;
+mod systemd;
+
+/// Errors that prevent Yand from starting up
+#[derive(Debug)]
+enum StartupError {
+    NoDisplay,
+    NoLayerShell,
+    NoHome,
+    ConfigDir(PathBuf, io::Error),
+    Dbus(glib::Error),
+}
+
+impl Display for StartupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StartupError::NoDisplay => {
+                f.write_str("no Wayland/X11 display found — is WAYLAND_DISPLAY set?")
+            }
+            StartupError::NoLayerShell => f.write_str(
+                "the compositor does not support the layer shell protocol (zwlr_layer_shell_v1)",
+            ),
+            StartupError::NoHome => {
+                f.write_str("cannot determine the config directory — is HOME set?")
+            }
+            StartupError::ConfigDir(path, why) => write!(
+                f,
+                "cannot create config directory {}: {}",
+                path.display(),
+                why.kind()
+            ),
+            StartupError::Dbus(why) => write!(f, "D-Bus error: {why}"),
+        }
+    }
+}
+
+impl From<glib::Error> for StartupError {
+    fn from(why: glib::Error) -> Self {
+        StartupError::Dbus(why)
+    }
+}
+
+/// Run the notification daemon for a program that renders the notifications itself, instead of
+/// the windows of yand. The notifications to show and close arrive on `tx`, and what the user did
+/// with them is read from `rx`. The config is read from the usual place. Blocks until the daemon
+/// quits, like `main`.
+pub fn run_dbus_server(
+    rx: relm4::Receiver<RendererMessage>,
+    tx: Sender<ServerMessage>,
+) -> ExitCode {
+    match serve_renderer(rx, tx) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(why) => {
+            error!("{why}");
+            ExitCode::FAILURE
+        }
+    }
+}
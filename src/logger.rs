@@ -0,0 +1,162 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use gtk4::glib;
+use log::{LevelFilter, Log, Metadata, Record};
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// A file opened for appending that keeps track of whether it was rotated
+pub struct LogFile {
+    pub path: PathBuf,
+    pub file: File,
+    inode: u64,
+}
+
+impl LogFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let inode = file.metadata()?.ino();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            inode,
+        })
+    }
+
+    /// Whether the file has been removed or replaced since it was opened, for example by logrotate
+    fn is_stale(&self) -> bool {
+        !fs::metadata(&self.path).is_ok_and(|metadata| metadata.ino() == self.inode)
+    }
+
+    /// Open the file again if it was rotated
+    pub fn reopen_if_stale(&mut self) -> io::Result<()> {
+        if self.is_stale() {
+            *self = LogFile::open(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+struct Logger {
+    /// The colog logger printing to stderr
+    console: Box<dyn Log>,
+    /// Whether the level is controlled by `RUST_LOG` instead of the config
+    env_filter: bool,
+    file: Mutex<Option<LogFile>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.console.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        self.console.log(record);
+
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+        let Some(log_file) = file.as_mut() else {
+            return;
+        };
+
+        if log_file.reopen_if_stale().is_err() {
+            return;
+        }
+
+        let timestamp = glib::DateTime::now_local()
+            .and_then(|time| time.format("%F %T"))
+            .map(|time| time.to_string())
+            .unwrap_or_default();
+
+        let _ = writeln!(
+            log_file.file,
+            "{timestamp} [{}] {}: {}",
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+        if let Some(log_file) = file.as_mut() {
+            let _ = log_file.file.flush();
+        }
+    }
+}
+
+/// Set up logging. If `RUST_LOG` is set, it takes precedence over the configured level.
+pub fn init() {
+    let env_filter = env::var_os("RUST_LOG").is_some();
+
+    let console = if env_filter {
+        colog::default_builder().build()
+    } else {
+        let mut builder = colog::basic_builder();
+        // The level is managed through `log::set_max_level` so that it can change on reload
+        builder.filter(None, LevelFilter::Trace);
+        builder.build()
+    };
+    let max_level = if env_filter {
+        console.filter()
+    } else {
+        LevelFilter::Info
+    };
+
+    let logger = LOGGER.get_or_init(|| Logger {
+        console: Box::new(console),
+        env_filter,
+        file: Mutex::new(None),
+    });
+
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Apply the logging related config options
+pub fn configure(level: LevelFilter, path: Option<&Path>) {
+    let Some(logger) = LOGGER.get() else {
+        return;
+    };
+
+    if !logger.env_filter {
+        log::set_max_level(level);
+    }
+
+    let unchanged = logger
+        .file
+        .lock()
+        .is_ok_and(|file| file.as_ref().map(|log_file| log_file.path.as_path()) == path);
+    if unchanged {
+        return;
+    }
+
+    // Opened without holding the lock, as logging the error would otherwise deadlock
+    let log_file = path.and_then(|path| {
+        LogFile::open(path)
+            .inspect_err(|why| log::error!("Failed to open log file {}: {why}", path.display()))
+            .ok()
+    });
+
+    if let Ok(mut file) = logger.file.lock() {
+        *file = log_file;
+    }
+}
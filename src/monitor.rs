@@ -0,0 +1,152 @@
+use std::fmt::Display;
+
+use gtk::{gdk, glib, prelude::*};
+use gtk4 as gtk;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// An output in the config, either by name or by index
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum Output {
+    Name(String),
+    /// Index into the monitors sorted by connector name, so that it stays the same across
+    /// machines with differently named connectors
+    Index(usize),
+}
+
+impl Display for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Output::Name(name) => write!(f, "{name:?}"),
+            Output::Index(index) => write!(f, "{index}"),
+        }
+    }
+}
+
+/// Either a single output or a list of outputs tried in order
+pub fn deserialize_outputs<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Output>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Outputs {
+        One(Output),
+        Many(Vec<Output>),
+    }
+
+    Ok(match Outputs::deserialize(deserializer)? {
+        Outputs::One(output) => vec![output],
+        Outputs::Many(outputs) => outputs,
+    })
+}
+
+/// The monitor property that matched the configured output
+#[derive(Debug, Clone, Copy)]
+pub enum MatchedBy {
+    Index,
+    Connector,
+    Model,
+    Description,
+}
+
+impl MatchedBy {
+    fn value(&self, monitor: &gdk::Monitor) -> Option<glib::GString> {
+        match self {
+            MatchedBy::Index | MatchedBy::Connector => monitor.connector(),
+            MatchedBy::Model => monitor.model(),
+            MatchedBy::Description => monitor.description(),
+        }
+    }
+}
+
+impl Display for MatchedBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchedBy::Index => f.write_str("index"),
+            MatchedBy::Connector => f.write_str("connector"),
+            MatchedBy::Model => f.write_str("model"),
+            MatchedBy::Description => f.write_str("description"),
+        }
+    }
+}
+
+pub struct MonitorMatch {
+    pub monitor: gdk::Monitor,
+    /// The configured output that matched
+    pub output: Output,
+    pub matched_by: MatchedBy,
+    /// Whether other monitors would have matched as well
+    pub ambiguous: bool,
+}
+
+/// Find the first of the configured outputs that is currently connected
+pub fn find(outputs: &[Output]) -> Option<MonitorMatch> {
+    if outputs.is_empty() {
+        return None;
+    }
+    let display = gdk::Display::default()?;
+
+    let mut monitors = display
+        .monitors()
+        .iter::<gdk::Monitor>()
+        .filter_map(Result::ok)
+        .filter(|monitor| monitor.is_valid())
+        .collect::<Vec<_>>();
+    monitors.sort_by_key(|monitor| monitor.connector());
+
+    outputs.iter().find_map(|output| match output {
+        Output::Name(name) => find_by_name(&monitors, name),
+        Output::Index(index) => {
+            let Some(monitor) = monitors.get(*index) else {
+                log::info!(
+                    "Output index {index} is out of range, {} outputs are connected",
+                    monitors.len()
+                );
+                return None;
+            };
+            Some(MonitorMatch {
+                monitor: monitor.clone(),
+                output: output.clone(),
+                matched_by: MatchedBy::Index,
+                ambiguous: false,
+            })
+        }
+    })
+}
+
+/// An exact connector name match is preferred, after which the model and description of
+/// the monitors are checked for a case-insensitive substring match.
+fn find_by_name(monitors: &[gdk::Monitor], output: &str) -> Option<MonitorMatch> {
+    if let Some(monitor) = monitors
+        .iter()
+        .find(|monitor| monitor.connector().as_deref() == Some(output))
+    {
+        return Some(MonitorMatch {
+            monitor: monitor.clone(),
+            output: Output::Name(output.to_string()),
+            matched_by: MatchedBy::Connector,
+            ambiguous: false,
+        });
+    }
+
+    let needle = output.to_lowercase();
+
+    for matched_by in [MatchedBy::Model, MatchedBy::Description] {
+        let mut matching = monitors.iter().filter(|monitor| {
+            matched_by
+                .value(monitor)
+                .is_some_and(|value| value.to_lowercase().contains(&needle))
+        });
+
+        if let Some(monitor) = matching.next() {
+            return Some(MonitorMatch {
+                monitor: monitor.clone(),
+                output: Output::Name(output.to_string()),
+                matched_by,
+                ambiguous: matching.next().is_some(),
+            });
+        }
+    }
+
+    None
+}
@@ -1,31 +1,89 @@
-use std::{fmt::Display, time::Duration};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Display,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
-use gtk::{gdk, glib, pango, prelude::*};
+use gtk::{cairo, gdk, gio, glib, pango, prelude::*};
 use gtk4 as gtk;
-use gtk4_layer_shell::LayerShell;
-use log::info;
+use gtk4_layer_shell::{KeyboardMode, LayerShell};
+use log::{info, warn};
 use relm4::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::{Config, ConfigOverrides};
+use crate::{
+    config::{
+        BodyEllipsize, Config, ConfigOverrides, ExclusiveZone, MediaLayout, ReplaceTimeout,
+        TextDirection,
+    },
+    format,
+    hints::Hints,
+    icon_cache::{self, IconCache},
+    keys::KeyAction,
+    layer,
+};
 
-const DEFAULT_ACTION: &str = "default";
+pub const DEFAULT_ACTION: &str = "default";
+/// The size of the icon of compact notifications
+const COMPACT_ICON_SIZE: i32 = 24;
 
 #[derive(Debug)]
 struct ActionButton {
     action: String,
     display: String,
+    /// Which notification the action belongs to, for screen readers
+    description: String,
+    /// Show the action key as an icon name instead of the label
+    icon: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ImageData {
     pub width: i32,
     pub height: i32,
     pub rowstride: i32,
     pub has_alpha: bool,
-    pub data: Vec<u8>,
+    /// Shared between the copies of the notification instead of being copied along
+    pub data: glib::Bytes,
+    /// Hash of the pixels and their layout, computed once when the image is received
+    pub hash: u64,
+}
+
+impl ImageData {
+    pub fn new(width: i32, height: i32, rowstride: i32, has_alpha: bool, data: Vec<u8>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        (width, height, rowstride, has_alpha).hash(&mut hasher);
+        data.hash(&mut hasher);
+        Self {
+            width,
+            height,
+            rowstride,
+            has_alpha,
+            data: glib::Bytes::from_owned(data),
+            hash: hasher.finish(),
+        }
+    }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Deserialize,
+    Serialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum Urgency {
     Low,
     #[default]
@@ -33,28 +91,37 @@ pub enum Urgency {
     Critical,
 }
 
-impl Display for Urgency {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Urgency {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            Urgency::Low => f.write_str("low"),
-            Urgency::Normal => f.write_str("normal"),
-            Urgency::Critical => f.write_str("critical"),
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
         }
     }
 }
 
-impl From<u8> for Urgency {
-    fn from(val: u8) -> Self {
+impl Display for Urgency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<u8> for Urgency {
+    type Error = u8;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
         match val {
-            0 => Urgency::Low,
-            1 => Urgency::Normal,
-            2 => Urgency::Critical,
-            _ => unreachable!(),
+            0 => Ok(Urgency::Low),
+            1 => Ok(Urgency::Normal),
+            2 => Ok(Urgency::Critical),
+            _ => Err(val),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum NotificationCloseReason {
     Expired,
     DismissedByUser,
@@ -62,6 +129,28 @@ pub enum NotificationCloseReason {
     Undefined,
 }
 
+impl Display for NotificationCloseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotificationCloseReason::Expired => f.write_str("expired"),
+            NotificationCloseReason::DismissedByUser => f.write_str("dismissed-by-user"),
+            NotificationCloseReason::DismissedByApp => f.write_str("dismissed-by-app"),
+            NotificationCloseReason::Undefined => f.write_str("undefined"),
+        }
+    }
+}
+
+impl From<u32> for NotificationCloseReason {
+    fn from(val: u32) -> Self {
+        match val {
+            1 => NotificationCloseReason::Expired,
+            2 => NotificationCloseReason::DismissedByUser,
+            3 => NotificationCloseReason::DismissedByApp,
+            _ => NotificationCloseReason::Undefined,
+        }
+    }
+}
+
 impl From<NotificationCloseReason> for u32 {
     fn from(val: NotificationCloseReason) -> Self {
         match val {
@@ -73,7 +162,7 @@ impl From<NotificationCloseReason> for u32 {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct NotificationInit {
     pub id: u32,
     pub app_name: String,
@@ -82,53 +171,93 @@ pub struct NotificationInit {
     pub body: String,
     pub actions: Vec<(String, String)>,
     pub expire_timeout: i32,
-
-    // Supported hints
-    pub action_icons: Option<bool>,
-    pub image_data: Option<ImageData>,
-    pub image_path: Option<String>,
-    pub resident: Option<bool>,
-    pub urgency: Option<Urgency>,
+    pub hints: Hints,
+    /// Every received hint including unsupported ones, except for raw image data
+    pub raw_hints: HashMap<String, glib::Variant>,
     // Extra data
     // pub offset: i32,
 }
 
 impl NotificationInit {
-    fn icon(&self) -> NotificationIcon {
-        if let Some(data) = &self.image_data {
-            let format = if data.has_alpha {
-                gdk::MemoryFormat::R8g8b8a8
-            } else {
-                gdk::MemoryFormat::R8g8b8
+    fn icon(
+        &self,
+        config: &Config,
+        icon_cache: &mut IconCache,
+        icon_size: i32,
+    ) -> NotificationIcon {
+        let pixel_size = IconCache::pixel_size(icon_size);
+        if let Some(data) = &self.hints.image_data {
+            return match icon_cache.cached_data(data, pixel_size) {
+                Some(texture) => NotificationIcon::Data(texture),
+                // Showing what there would be without the image until it is decoded
+                None => NotificationIcon::Decoding {
+                    data: data.clone(),
+                    pixel_size,
+                    placeholder: Box::new(self.icon_without_image(config, icon_cache, pixel_size)),
+                },
             };
-            let tex = gdk::MemoryTexture::new(
-                data.width,
-                data.height,
-                format,
-                &glib::Bytes::from_owned(data.data.clone()),
-                data.rowstride as usize,
-            );
-            NotificationIcon::Data(tex.into())
-        } else if let Some(path) = &self.image_path {
-            if let Ok((path, _)) = glib::filename_from_uri(path) {
-                NotificationIcon::Path(path.to_string_lossy().to_string())
-            } else {
-                NotificationIcon::Path(path.clone())
-            }
-        } else if !self.app_icon.is_empty() {
-            // The spec allows for URIs in the app_icon field, but GTK is not a fan of them. So we must commit this
-            // atrocity
-            if let Ok((path, _)) = glib::filename_from_uri(&self.app_icon) {
-                NotificationIcon::Path(path.to_string_lossy().to_string())
-            } else {
-                NotificationIcon::Name(self.app_icon.clone())
+        } else if let Some(path) = &self.hints.image_path {
+            return NotificationIcon::file(icon_path(path), icon_cache, pixel_size);
+        }
+        self.icon_without_image(config, icon_cache, pixel_size)
+    }
+
+    /// The app icon, or the icon of the desktop entry or the fallback icon if there is none
+    fn icon_without_image(
+        &self,
+        config: &Config,
+        icon_cache: &mut IconCache,
+        pixel_size: i32,
+    ) -> NotificationIcon {
+        match self.app_icon(icon_cache, pixel_size) {
+            NotificationIcon::None => (),
+            icon => return icon,
+        }
+        // Desktop entries are usually named after the icon of the application
+        if let Some(entry) = &self.hints.desktop_entry {
+            let name = entry.strip_suffix(".desktop").unwrap_or(entry);
+            if gdk::Display::default()
+                .is_some_and(|display| gtk::IconTheme::for_display(&display).has_icon(name))
+            {
+                return NotificationIcon::Name(name.to_string());
             }
+        }
+        if config.fallback_icons {
+            let urgency = self.hints.urgency.unwrap_or_default();
+            NotificationIcon::Fallback(config.fallback_icon_for(urgency).to_string())
         } else {
             NotificationIcon::None
         }
     }
 
-    fn default_action(&mut self) -> Option<String> {
+    fn app_icon(&self, icon_cache: &mut IconCache, pixel_size: i32) -> NotificationIcon {
+        if self.app_icon.is_empty() {
+            return NotificationIcon::None;
+        }
+        // The spec allows for URIs in the app_icon field, but GTK is not a fan of them. So we must commit this
+        // atrocity
+        if let Ok((path, _)) = glib::filename_from_uri(&self.app_icon) {
+            NotificationIcon::file(path, icon_cache, pixel_size)
+        } else {
+            NotificationIcon::Name(self.app_icon.clone())
+        }
+    }
+
+    /// The app icon shown small on the corner of the image, if there is both an image and an
+    /// app icon that isn't the image itself
+    fn badge(&self, icon_cache: &mut IconCache, icon_size: i32) -> NotificationIcon {
+        let duplicate = match (&self.hints.image_data, &self.hints.image_path) {
+            (Some(_), _) => false,
+            (None, Some(path)) => icon_path(path) == icon_path(&self.app_icon),
+            (None, None) => return NotificationIcon::None,
+        };
+        if duplicate {
+            return NotificationIcon::None;
+        }
+        self.app_icon(icon_cache, IconCache::pixel_size(icon_size / 3))
+    }
+
+    pub fn default_action(&mut self) -> Option<String> {
         let default_action_index = self
             .actions
             .iter()
@@ -138,29 +267,74 @@ impl NotificationInit {
         default_action_index.map(|i| self.actions.remove(i).1)
     }
 
+    /// The URI of the image file, if the image came from one
+    fn image_uri(&self) -> Option<String> {
+        // Image data takes precedence over the path
+        if self.hints.image_data.is_some() {
+            return None;
+        }
+        let path = self.hints.image_path.as_ref()?;
+        if glib::filename_from_uri(path).is_ok() {
+            Some(path.clone())
+        } else {
+            Some(gio::File::for_path(path).uri().into())
+        }
+    }
+
+    /// Whether the actions are media player controls shown as icons, like
+    /// `media-playback-pause`.
+    ///
+    /// Must be called after `Self::default_action`, as the default action is never shown
+    fn has_media_actions(&self) -> bool {
+        self.hints.action_icons == Some(true)
+            && !self.actions.is_empty()
+            && self
+                .actions
+                .iter()
+                .all(|(key, _)| key.starts_with("media-"))
+    }
+
     /// Determine the timeout that should be used.
     ///
     /// Must be called after `Self::default_action` to make sure the action Vec is representative of what
     /// is shown to users
-    fn timeout(&self, config: &Config, overrides: &ConfigOverrides) -> Option<u32> {
+    pub fn timeout(&self, config: &Config, overrides: &ConfigOverrides) -> Option<Duration> {
+        if overrides.sticky {
+            return None;
+        }
         // If notification has 2 or more actions alongside a default
         // disable timeout
         //
         // Odds are the notification wants some user input (looking at you blueman)
         if self.actions.len() >= 2 {
-            None
-        } else if self.expire_timeout == -1 || overrides.timeout {
-            Some(config.timeout)
+            return None;
+        }
+        if config.critical_sticky && self.hints.urgency == Some(Urgency::Critical) {
+            return None;
+        }
+        let timeout = if self.expire_timeout < 0 || overrides.timeout {
+            Duration::from_secs(config.timeout as u64)
         } else {
-            Some(self.expire_timeout as u32)
+            // Applications give their timeouts in milliseconds
+            Duration::from_millis(self.expire_timeout as u64)
+        };
+        if timeout.is_zero() {
+            return None;
         }
-        .and_then(|timeout| if timeout == 0 { None } else { Some(timeout) })
+
+        let scale = config
+            .timeout_scale
+            .for_urgency(self.hints.urgency.unwrap_or_default());
+        // A scale of 0 makes the notification sticky
+        Duration::try_from_secs_f64(timeout.as_secs_f64() * scale)
+            .ok()
+            .filter(|timeout| !timeout.is_zero())
     }
 }
 
 #[relm4::factory(pub)]
 impl FactoryComponent for ActionButton {
-    type Init = (String, String);
+    type Init = (String, String, String, bool);
     type Input = ();
     type Output = String;
     type CommandOutput = ();
@@ -169,8 +343,13 @@ impl FactoryComponent for ActionButton {
     view! {
         gtk::Button {
             set_css_classes: &["action"],
-            set_label: &self.display,
+            set_child: Some(&action_child(&self.action, &self.display, self.icon)),
+            set_tooltip_text: self.icon.then_some(self.display.as_str()),
             set_hexpand: true,
+            update_property: &[
+                gtk::accessible::Property::Label(&self.display),
+                gtk::accessible::Property::Description(&self.description),
+            ],
             connect_clicked: glib::clone!(
                 #[strong(rename_to = action)] self.action,
                 move |_| {
@@ -184,18 +363,167 @@ impl FactoryComponent for ActionButton {
         Self {
             action: init.0,
             display: init.1,
+            description: init.2,
+            icon: init.3,
         }
     }
 }
 
+/// The path of an image given as either a path or a file URI
+fn icon_path(path: &str) -> PathBuf {
+    match glib::filename_from_uri(path) {
+        Ok((path, _)) => path,
+        Err(_) => PathBuf::from(path),
+    }
+}
+
 #[derive(Debug)]
 enum NotificationIcon {
-    Path(String),
+    Path(PathBuf),
+    /// A symbolic icon file, recolored like symbolic icon names
+    Symbolic(PathBuf),
     Name(String),
+    /// A themed icon for the urgency of a notification that has no icon of its own
+    Fallback(String),
     Data(gdk::Texture),
+    /// Raw image data that is yet to be decoded, showing the placeholder meanwhile
+    Decoding {
+        data: ImageData,
+        pixel_size: i32,
+        placeholder: Box<NotificationIcon>,
+    },
     None,
 }
 
+impl NotificationIcon {
+    fn file(path: PathBuf, icon_cache: &mut IconCache, pixel_size: i32) -> Self {
+        // Symbolic icons take their color from the CSS of the icon, which textures don't
+        if path
+            .file_stem()
+            .is_some_and(|stem| stem.to_string_lossy().ends_with("-symbolic"))
+        {
+            return Self::Symbolic(path);
+        }
+        match icon_cache.file(&path, pixel_size) {
+            Ok(texture) => Self::Data(texture),
+            // Let GTK deal with it, which at least shows the missing image icon
+            Err(why) => {
+                warn!("Failed to load icon {}: {why}", path.display());
+                Self::Path(path)
+            }
+        }
+    }
+
+    /// Show the icon in the image, hiding the image if there is none
+    fn apply(&self, image: &gtk::Image) {
+        // A replacement may add an icon where there was none
+        image.set_visible(!matches!(self, NotificationIcon::None));
+        if matches!(self, NotificationIcon::Fallback(_)) {
+            image.add_css_class("fallback-icon");
+        } else {
+            image.remove_css_class("fallback-icon");
+        }
+        match self {
+            NotificationIcon::Path(path) => image.set_from_file(Some(path)),
+            NotificationIcon::Symbolic(path) => {
+                image.set_from_gicon(&gio::FileIcon::new(&gio::File::for_path(path)))
+            }
+            NotificationIcon::Name(name) | NotificationIcon::Fallback(name) => {
+                image.set_icon_name(Some(name))
+            }
+            NotificationIcon::Data(texture) => image.set_paintable(Some(texture)),
+            // The placeholder sets the visibility and classes of its own
+            NotificationIcon::Decoding { placeholder, .. } => placeholder.apply(image),
+            NotificationIcon::None => image.clear(),
+        }
+    }
+}
+
+/// A small label overlaid on the corner of the icon, showing how many notifications were
+/// merged into one
+#[derive(Debug)]
+pub struct CountBadge {
+    label: gtk::Label,
+}
+
+impl CountBadge {
+    pub fn new() -> Self {
+        let label = gtk::Label::builder()
+            .css_classes(["count-badge"])
+            .halign(gtk::Align::End)
+            .valign(gtk::Align::Start)
+            .can_target(false)
+            .visible(false)
+            .build();
+        Self { label }
+    }
+
+    /// Show the count, hiding the badge if nothing was merged
+    pub fn set_count(&self, count: u32) {
+        self.label.set_label(&count.to_string());
+        self.label.set_visible(count > 1);
+    }
+
+    pub fn widget(&self) -> &gtk::Label {
+        &self.label
+    }
+}
+
+/// How the contents of a notification are arranged
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Layout {
+    Full,
+    /// Large album art and icon buttons for media players
+    Media,
+    /// A single row with the icon and summary
+    Compact,
+}
+
+impl Layout {
+    fn new(config: &Config, compact: bool, media_actions: bool) -> Self {
+        if compact {
+            return Layout::Compact;
+        }
+        match config.media_layout {
+            MediaLayout::Auto if media_actions => Layout::Media,
+            MediaLayout::Always => Layout::Media,
+            _ => Layout::Full,
+        }
+    }
+
+    fn icon_size(self, config: &Config) -> i32 {
+        match self {
+            Layout::Full => config.icon_size,
+            Layout::Media => config.media_icon_size,
+            Layout::Compact => COMPACT_ICON_SIZE,
+        }
+    }
+}
+
+/// How the value hint is shown
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Progress {
+    Determinate {
+        fraction: f64,
+        /// The value was over 100, like with boosted volume
+        overfill: bool,
+    },
+    Indeterminate,
+}
+
+impl Progress {
+    fn from_value(value: i32) -> Self {
+        if value < 0 {
+            Progress::Indeterminate
+        } else {
+            Progress::Determinate {
+                fraction: value.min(100) as f64 / 100.0,
+                overfill: value > 100,
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum NotificationOutput {
     Closed {
@@ -206,112 +534,200 @@ pub enum NotificationOutput {
         id: u32,
         action: String,
     },
+    /// Hidden by the user to be shown again later
+    Snoozed {
+        id: u32,
+    },
+    /// A key bound to an action that concerns the other notifications was pressed in keyboard
+    /// mode
+    KeyPressed {
+        id: u32,
+        action: KeyAction,
+    },
 }
 
 #[derive(Debug)]
 pub enum NotificationInput {
     ChangeOffset(i32),
+    /// Hide the notification in the stack of older notifications, or show it again
+    SetCollapsed(bool),
+    /// Give the keyboard to the notification in keyboard mode, or take it away
+    SetSelected(bool),
+    KeyPressed(gdk::Key, gdk::ModifierType),
+    ChangeMonitor(Option<gdk::Monitor>),
+    ConfigChanged(Config),
     Close(NotificationCloseReason),
+    Clicked,
+    /// The image was clicked
+    OpenImage,
+    /// A link in the body was clicked
+    OpenLink(String),
+    /// Long pressed at the position in the notification
+    ShowMenu(f64, f64),
+    /// The size of the notification changed
+    UpdateInputRegion,
+    /// How many notifications this one stands for
+    SetCount(u32),
+    /// Stop the timeout, remembering how much of it is left
+    PauseTimeout,
+    /// Continue a paused timeout, with extra time added
+    ResumeTimeout(Duration),
+    /// The timeout started as `generation` ran out
+    TimeoutExpired(u64),
+    Snooze,
     Replace(Box<<Notification as Component>::Init>),
+    /// The image data was decoded off the main thread for the icon set at `generation`
+    ImageDecoded {
+        generation: u64,
+        data: ImageData,
+        pixel_size: i32,
+        texture: gdk::Texture,
+    },
 }
 
 #[derive(Debug)]
 pub struct Notification {
     pub id: u32,
     app_name: String,
+    desktop_entry: Option<String>,
     summary: String,
     body: String,
     urgency: Urgency,
+    css_classes: Vec<String>,
+    /// Whether the notification looks like it came from a media player
+    media_actions: bool,
+    /// Decided once when the notification is shown, so that it doesn't change size later
+    compact: bool,
+    /// The file of the image, opened by clicking it
+    image_uri: Option<String>,
+    /// Stays around after links are opened
+    resident: bool,
+    /// Hidden in the stack of older notifications
+    collapsed: bool,
+    /// Has the keyboard in keyboard mode
+    selected: bool,
 
     // Watched variables
     offset: i32,
     opacity: f64,
 
     config: Config,
+    monitor: Option<gdk::Monitor>,
 
+    icon_cache: Rc<RefCell<IconCache>>,
     icon_widget: gtk::Image,
+    /// The app icon on the corner of the image, if the notification has both
+    badge_widget: gtk::Image,
+    count_badge: CountBadge,
+    progress_bar: gtk::ProgressBar,
+    /// The ID to the glib timeout animating a progress bar of unknown length
+    pulse_source_id: Option<glib::SourceId>,
+    /// Counts the icons that were set, so that an image decoded for a replaced icon is dropped
+    icon_generation: u64,
     actions_factory: FactoryVecDeque<ActionButton>,
     default_action: Option<String>,
-    /// The ID to the glib timeout for possible cancellation during a replace event
+    /// The ID to the glib timeout for possible cancellation during a replace event. The source
+    /// stays alive until it is removed here, so the ID is always safe to remove.
     timeout_source_id: Option<glib::SourceId>,
+    /// Counts the timeouts that were started, so that a timeout that ran out just as it was
+    /// replaced doesn't close the notification
+    timeout_generation: u64,
+    /// When the timeout runs out
+    timeout_deadline: Option<Instant>,
+    /// What was left of the timeout when it was paused
+    paused_timeout: Option<Duration>,
 }
 
 #[allow(unused_assignments)]
 #[relm4::component(pub)]
 impl Component for Notification {
-    type Init = (NotificationInit, Config);
+    type Init = (
+        NotificationInit,
+        Config,
+        Option<gdk::Monitor>,
+        Rc<RefCell<IconCache>>,
+    );
     type Input = NotificationInput;
     type Output = NotificationOutput;
     type CommandOutput = NotificationInput;
 
     view! {
         gtk::Window {
-            init_layer_shell: (),
-            set_layer: model.config.layer.clone().into(),
-            set_anchor: (gtk4_layer_shell::Edge::Right, true),
-            set_anchor: (gtk4_layer_shell::Edge::Top, true),
-            set_margin: (gtk4_layer_shell::Edge::Right, model.config.margin_side),
             #[watch]
-            set_margin: (gtk4_layer_shell::Edge::Top, model.offset),
-            set_namespace: Some("yand"),
+            set_margin: (model.config.position.anchor_edge(), model.offset),
             #[watch]
             set_opacity: model.opacity,
-            #[watch]
-            set_monitor: {
-                let monitors = gdk::Display::default().unwrap().monitors();
-
-                if let Some(output) = &model.config.output {
-                    monitors.into_iter().find_map(|item| {
-                        let monitor = item.unwrap().downcast::<gdk::Monitor>().unwrap();
-
-                        if monitor.connector() == Some(output.clone().into()) {
-                            Some(monitor)
-                        } else {
-                            None
-                        }
-                    })
-                } else {
-                    None
-                }
-            }.as_ref(),
-            #[watch]
+            set_monitor: model.monitor.as_ref(),
             set_default_size: (model.config.width, 1),
+            add_controller = gtk::EventControllerKey {
+                connect_key_pressed: glib::clone!(
+                    #[strong] sender,
+                    move |_, key, _, modifiers| {
+                        sender.input(NotificationInput::KeyPressed(key, modifiers));
+                        glib::Propagation::Stop
+                    }
+                )
+            },
 
             #[name = "notification"]
             gtk::Box {
                 #[watch]
-                set_css_classes: &["notification", &model.urgency.to_string(), &model.app_name],
+                set_css_classes: &model.css_classes(),
                 set_orientation: gtk::Orientation::Vertical,
                 set_hexpand: true,
+                set_accessible_role: gtk::AccessibleRole::Alert,
                 add_controller = gtk::GestureClick {
                     connect_released: glib::clone!(
-                        #[strong(rename_to = default)] model.default_action,
-                        #[strong(rename_to = id)] model.id,
+                        #[strong] sender,
                         move |gesture, _, _, _| {
                             gesture.set_state(gtk::EventSequenceState::Claimed);
-                            if default.is_some() {
-                                sender.output(NotificationOutput::ActionInvoked {
-                                    id,
-                                    action: DEFAULT_ACTION.to_string()
-                                }).unwrap();
-                            } else {
-                                sender.input(NotificationInput::Close(
-                                    NotificationCloseReason::DismissedByUser
-                                ));
-                            }
+                            // The default action may change when the notification is replaced
+                            sender.input(NotificationInput::Clicked);
                         }
                     )
                 },
+                // The delay follows the gtk-long-press-time setting
+                add_controller = gtk::GestureLongPress {
+                    set_touch_only: true,
+                    connect_pressed[sender] => move |gesture, x, y| {
+                        // Keeps the tap from going through once the finger is lifted
+                        gesture.set_state(gtk::EventSequenceState::Claimed);
+                        sender.input(NotificationInput::ShowMenu(x, y));
+                    }
+                },
 
-                gtk::Label {
-                    #[watch]
-                    set_label: &model.summary,
-                    set_css_classes: &["summary"],
-                    set_justify: gtk::Justification::Left,
-                    set_halign: gtk::Align::Start,
-                    set_wrap: false,
-                    set_ellipsize: pango::EllipsizeMode::End,
-                    set_use_markup: true,
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+
+                    gtk::Label {
+                        #[watch]
+                        set_label: &model.summary,
+                        #[watch]
+                        set_visible: model.layout() == Layout::Full,
+                        set_css_classes: &["summary"],
+                        set_justify: gtk::Justification::Left,
+                        set_halign: gtk::Align::Start,
+                        set_hexpand: true,
+                        set_wrap: false,
+                        set_ellipsize: pango::EllipsizeMode::End,
+                        set_use_markup: true,
+                    },
+                    gtk::Button {
+                        set_icon_name: "alarm-symbolic",
+                        set_tooltip_text: Some("Snooze"),
+                        set_css_classes: &["snooze", "flat"],
+                        set_valign: gtk::Align::Start,
+                        set_halign: gtk::Align::End,
+                        // Takes the place of the summary, which is next to the icon in other layouts
+                        #[watch]
+                        set_hexpand: model.layout() != Layout::Full,
+                        #[watch]
+                        set_visible: model.config.snooze_duration > 0
+                            && model.layout() != Layout::Compact,
+                        connect_clicked[sender] => move |_| {
+                            sender.input(NotificationInput::Snooze);
+                        }
+                    },
                 },
 
 
@@ -320,49 +736,158 @@ impl Component for Notification {
                     set_vexpand: true,
                     set_hexpand: true,
 
-                    // For some reason the Image becomes larger if it is not inside a Stack
-                    attach[0, 0, 1, 1] = &gtk::Stack {
+                    // Overlay children don't affect the size, so the badge doesn't move anything
+                    attach[0, 0, 1, 2] = &gtk::Overlay {
+                        add_overlay: model.count_badge.widget(),
                         #[local_ref]
-                        icon_widget -> gtk::Image {
+                        add_overlay = badge_widget -> gtk::Image {
                             #[watch]
-                            set_pixel_size: model.config.icon_size,
-                            set_css_classes: &["icon"],
+                            set_pixel_size: model.layout().icon_size(&model.config) / 3,
+                            set_css_classes: &["app-badge"],
+                            set_halign: gtk::Align::End,
+                            set_valign: gtk::Align::End,
+                            set_can_target: false,
+                        },
+
+                        // For some reason the Image becomes larger if it is not inside a Stack
+                        #[wrap(Some)]
+                        set_child = &gtk::Stack {
+                            #[local_ref]
+                            icon_widget -> gtk::Image {
+                                #[watch]
+                                set_pixel_size: model.layout().icon_size(&model.config),
+                                set_css_classes: &["icon"],
+                                #[watch]
+                                set_cursor_from_name: model.image_uri.as_ref().map(|_| "pointer"),
+                                add_controller = gtk::GestureClick {
+                                    // Claiming the press keeps the notification from being clicked too
+                                    connect_pressed: |gesture, _, _, _| {
+                                        gesture.set_state(gtk::EventSequenceState::Claimed);
+                                    },
+                                    connect_released[sender] => move |_, _, _, _| {
+                                        sender.input(NotificationInput::OpenImage);
+                                    },
+                                },
+                            },
                         },
                     },
 
+                    // The title and artist are stacked next to the album art in the media layout,
+                    // and compact notifications only have the summary next to the icon
                     attach[1, 0, 1, 1] = &gtk::Label {
                         #[watch]
-                        set_label: &model.body,
-                        set_css_classes: &["body"],
+                        set_label: &model.summary,
+                        #[watch]
+                        set_visible: model.layout() != Layout::Full,
+                        set_css_classes: &["summary"],
                         set_halign: gtk::Align::Start,
-                        set_valign: gtk::Align::Center,
+                        #[watch]
+                        set_valign: if model.layout() == Layout::Media {
+                            gtk::Align::End
+                        } else {
+                            gtk::Align::Center
+                        },
+                        set_hexpand: true,
                         set_xalign: 0.0,
-                        set_wrap: true,
-                        set_use_markup: true,
-                        set_natural_wrap_mode: gtk::NaturalWrapMode::Word,
-                        set_wrap_mode: pango::WrapMode::WordChar,
-                        set_lines: model.config.max_lines,
                         set_ellipsize: pango::EllipsizeMode::End,
-                        set_visible: !model.body.is_empty(),
+                        set_use_markup: true,
+                    },
+
+                    // Labels only respect the line limit when they are ellipsized, so a body that
+                    // is not is laid over an invisible ellipsized copy of itself and clipped to it
+                    attach[1, 1, 1, 1] = &gtk::Overlay {
+                        set_overflow: gtk::Overflow::Hidden,
+                        #[watch]
+                        set_visible: !model.body.is_empty()
+                            && model.config.max_lines > 0
+                            && model.layout() != Layout::Compact,
+
+                        #[wrap(Some)]
+                        set_child = &gtk::Label {
+                            #[watch]
+                            set_label: &model.body,
+                            set_css_classes: &["body"],
+                            set_halign: gtk::Align::Start,
+                            set_valign: gtk::Align::Center,
+                            set_xalign: 0.0,
+                            set_wrap: true,
+                            set_use_markup: true,
+                            set_natural_wrap_mode: gtk::NaturalWrapMode::Word,
+                            set_wrap_mode: pango::WrapMode::WordChar,
+                            connect_activate_link[sender] => move |_, uri| {
+                                sender.input(NotificationInput::OpenLink(uri.to_string()));
+                                glib::Propagation::Stop
+                            },
+                            #[watch]
+                            set_lines: model.config.max_lines,
+                            #[watch]
+                            set_ellipsize: match model.config.body_ellipsize {
+                                BodyEllipsize::None => pango::EllipsizeMode::End,
+                                ellipsize => ellipsize.into(),
+                            },
+                            #[watch]
+                            set_opacity: if model.config.body_ellipsize == BodyEllipsize::None {
+                                0.0
+                            } else {
+                                1.0
+                            },
+                        },
+
+                        add_overlay = &gtk::Label {
+                            #[watch]
+                            set_label: &model.body,
+                            set_css_classes: &["body"],
+                            set_halign: gtk::Align::Fill,
+                            set_valign: gtk::Align::Start,
+                            set_xalign: 0.0,
+                            set_wrap: true,
+                            set_use_markup: true,
+                            set_natural_wrap_mode: gtk::NaturalWrapMode::Word,
+                            set_wrap_mode: pango::WrapMode::WordChar,
+                            connect_activate_link[sender] => move |_, uri| {
+                                sender.input(NotificationInput::OpenLink(uri.to_string()));
+                                glib::Propagation::Stop
+                            },
+                            #[watch]
+                            set_visible: model.config.body_ellipsize == BodyEllipsize::None,
+                        },
                     }
                 },
 
+                #[local_ref]
+                progress_bar -> gtk::ProgressBar {
+                    add_css_class: "progress",
+                },
+
                 #[local_ref]
                 action_buttons -> gtk::Box {
                     set_hexpand: true,
                     set_orientation: gtk4::Orientation::Horizontal,
                     set_homogeneous: true,
+                    #[watch]
+                    set_visible: model.layout() != Layout::Compact,
+                    // Media controls are a compact row of icons
+                    #[watch]
+                    set_halign: if model.layout() == Layout::Media {
+                        gtk::Align::Center
+                    } else {
+                        gtk::Align::Fill
+                    },
                 }
             }
         }
     }
 
     fn init(
-        (mut notification_init, config): Self::Init,
+        (mut notification_init, config, monitor, icon_cache): Self::Init,
         root: Self::Root,
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
-        let (config, overrides) = config.clone().overridden(&notification_init.app_name);
+        let (config, overrides) = config.clone().overridden(
+            &notification_init.app_name,
+            notification_init.hints.desktop_entry.as_deref(),
+        );
+        let config = config.with_urgency(notification_init.hints.urgency.unwrap_or_default());
 
         let id = notification_init.id;
 
@@ -377,41 +902,126 @@ impl Component for Notification {
             );
 
         let default_action = notification_init.default_action();
+        let media_actions = notification_init.has_media_actions();
+        let compact = config.low_urgency_compact
+            && notification_init.hints.urgency.unwrap_or_default() == Urgency::Low;
+        let layout = Layout::new(&config, compact, media_actions);
 
-        let icon = notification_init.icon();
+        let icon = notification_init.icon(
+            &config,
+            &mut icon_cache.borrow_mut(),
+            layout.icon_size(&config),
+        );
+        let badge = if config.show_app_icon_badge {
+            notification_init.badge(&mut icon_cache.borrow_mut(), layout.icon_size(&config))
+        } else {
+            NotificationIcon::None
+        };
 
-        for (action, display) in notification_init.actions.clone() {
-            info!("Action added for notification: {}, {}", action, display);
-            actions_factory.guard().push_back((action, display));
-        }
+        push_actions(
+            &mut actions_factory,
+            &notification_init,
+            default_action.as_ref(),
+            &config,
+            layout == Layout::Media,
+        );
 
         let icon_widget = gtk::Image::new();
+        let badge_widget = gtk::Image::new();
+        let progress_bar = gtk::ProgressBar::new();
+
+        let summary = format::expand(&config.summary_format, &notification_init);
+        let body = format::body(&config, &notification_init);
 
         let mut model = Self {
             offset: config.margin_anchor,
             // Opacity is set to 0 initially to make sure the window isn't visible before the correct position has been configured
             opacity: 0.0,
             config,
+            monitor,
+            icon_cache,
             icon_widget: icon_widget.clone(),
+            badge_widget: badge_widget.clone(),
+            count_badge: CountBadge::new(),
+            progress_bar: progress_bar.clone(),
+            pulse_source_id: None,
+            icon_generation: 0,
             default_action,
             actions_factory,
             id: notification_init.id,
             app_name: notification_init.app_name.clone(),
-            summary: notification_init.summary.clone(),
-            // Remove all newlines to make sure GTK can properly truncate the label
-            // TODO: Configurable, figure out a better way to do this
-            body: notification_init.body.replace('\n', " "),
-            urgency: notification_init.urgency.unwrap_or_default(),
+            desktop_entry: notification_init.hints.desktop_entry.clone(),
+            summary,
+            body,
+            urgency: notification_init.hints.urgency.unwrap_or_default(),
+            css_classes: notification_init.hints.css_classes.clone(),
+            media_actions,
+            compact,
+            image_uri: notification_init.image_uri(),
+            resident: notification_init.hints.resident.unwrap_or(false),
+            collapsed: false,
+            selected: false,
             timeout_source_id: None,
+            timeout_generation: 0,
+            timeout_deadline: None,
+            paused_timeout: None,
         };
 
         model.set_timeout(&notification_init, &overrides, sender.clone());
-        model.set_icon(icon);
+        model.set_exclusive_zone(&root);
+        model.set_icon(icon, badge, sender.clone());
+        model.set_progress(notification_init.hints.value);
 
         let action_buttons = model.actions_factory.widget();
 
+        layer::init(
+            &root,
+            &model.config,
+            &model.config.namespace,
+            model.config.layer_for(model.urgency),
+        );
         let widgets = view_output!();
 
+        root.insert_action_group("notification", Some(&menu_actions(id, &sender)));
+        model.update_accessible(&widgets.notification);
+        model.update_direction(&root);
+        // Screen readers don't pick up new windows on their own, so critical notifications are
+        // announced once they are shown
+        if model.urgency == Urgency::Critical {
+            let announcement = model.accessible_label();
+            root.connect_map(move |window| {
+                window.announce(&announcement, gtk::AccessibleAnnouncementPriority::High);
+            });
+        }
+
+        // The surface only exists once the window is shown
+        root.connect_realize(glib::clone!(
+            #[strong]
+            sender,
+            move |window| {
+                let Some(surface) = window.surface() else {
+                    return;
+                };
+                surface.connect_layout(glib::clone!(
+                    #[strong]
+                    sender,
+                    move |_, _, _| {
+                        // Wait for the widgets to be allocated with the new size
+                        glib::idle_add_local_once(glib::clone!(
+                            #[strong]
+                            sender,
+                            move || {
+                                // The notification may have been closed in the meantime
+                                let _ = sender
+                                    .input_sender()
+                                    .send(NotificationInput::UpdateInputRegion);
+                            }
+                        ));
+                    }
+                ));
+            }
+        ));
+
         ComponentParts { model, widgets }
     }
 
@@ -421,7 +1031,67 @@ impl Component for Notification {
                 self.offset = self.config.margin_anchor + offset;
                 self.opacity = 1.0;
             }
+            NotificationInput::SetCollapsed(collapsed) => {
+                if collapsed != self.collapsed {
+                    self.collapsed = collapsed;
+                    root.set_visible(!collapsed);
+                }
+            }
+            NotificationInput::SetSelected(selected) => {
+                self.selected = selected;
+                root.set_keyboard_mode(if selected {
+                    KeyboardMode::Exclusive
+                } else {
+                    KeyboardMode::None
+                });
+            }
+            NotificationInput::KeyPressed(key, modifiers) => {
+                match self.config.keys.action(key, modifiers) {
+                    Some(KeyAction::Dismiss) => sender.input(NotificationInput::Close(
+                        NotificationCloseReason::DismissedByUser,
+                    )),
+                    Some(KeyAction::InvokeDefault) => sender.input(NotificationInput::Clicked),
+                    Some(KeyAction::Invoke(n)) => {
+                        if let Some(button) = self.actions_factory.get(n - 1) {
+                            sender
+                                .output(NotificationOutput::ActionInvoked {
+                                    id: self.id,
+                                    action: button.action.clone(),
+                                })
+                                .unwrap();
+                        }
+                    }
+                    Some(action) => sender
+                        .output(NotificationOutput::KeyPressed {
+                            id: self.id,
+                            action,
+                        })
+                        .unwrap(),
+                    None => (),
+                }
+            }
+            NotificationInput::ChangeMonitor(monitor) => {
+                // gtk4-layer-shell remaps the surface on its own if it is already mapped
+                self.monitor = monitor;
+                root.set_monitor(self.monitor.as_ref());
+            }
+            NotificationInput::ConfigChanged(config) => {
+                // The timeout is left alone, as restarting it would keep reloads from expiring anything
+                let (config, _) = config.overridden(&self.app_name, self.desktop_entry.as_deref());
+                self.config = config.with_urgency(self.urgency);
+
+                layer::apply_config(root, &self.config, self.config.layer_for(self.urgency));
+                self.set_exclusive_zone(root);
+                self.update_direction(root);
+                self.resize_to_content(root);
+                sender.input(NotificationInput::UpdateInputRegion);
+            }
             NotificationInput::Close(reason) => {
+                if let Some(source_id) = self.timeout_source_id.take() {
+                    source_id.remove();
+                }
+                self.timeout_deadline = None;
+                self.set_progress(None);
                 // For some reason, this fixes things.
                 root.set_visible(false);
                 root.close();
@@ -432,24 +1102,155 @@ impl Component for Notification {
                     })
                     .unwrap();
             }
+            NotificationInput::Clicked => {
+                if self.default_action.is_some() {
+                    sender
+                        .output(NotificationOutput::ActionInvoked {
+                            id: self.id,
+                            action: DEFAULT_ACTION.to_string(),
+                        })
+                        .unwrap();
+                } else {
+                    sender.input(NotificationInput::Close(
+                        NotificationCloseReason::DismissedByUser,
+                    ));
+                }
+            }
+            NotificationInput::OpenImage => match self.image_uri.clone() {
+                Some(uri) => launch_uri(uri, self.config.image_click_dismiss, sender),
+                // Icons and raw image data have no file to open
+                None => sender.input(NotificationInput::Clicked),
+            },
+            NotificationInput::SetCount(count) => self.count_badge.set_count(count),
+            NotificationInput::ImageDecoded {
+                generation,
+                data,
+                pixel_size,
+                texture,
+            } => {
+                self.icon_cache
+                    .borrow_mut()
+                    .insert_data(&data, pixel_size, texture.clone());
+                // Otherwise a replacement came in while the image was being decoded, and the
+                // image is no longer the icon
+                if generation == self.icon_generation {
+                    NotificationIcon::Data(texture).apply(&self.icon_widget);
+                    self.badge_widget
+                        .set_visible(self.badge_widget.storage_type() != gtk::ImageType::Empty);
+                }
+            }
+            NotificationInput::PauseTimeout => {
+                if let (Some(source_id), Some(deadline)) =
+                    (self.timeout_source_id.take(), self.timeout_deadline.take())
+                {
+                    source_id.remove();
+                    self.paused_timeout = Some(deadline.saturating_duration_since(Instant::now()));
+                }
+            }
+            NotificationInput::ResumeTimeout(grace) => {
+                if let Some(remaining) = self.paused_timeout.take() {
+                    self.start_timeout(remaining + grace, sender);
+                }
+            }
+            NotificationInput::TimeoutExpired(generation) => {
+                // The source may have gone off again before it was removed
+                if generation != self.timeout_generation {
+                    return;
+                }
+                if let Some(source_id) = self.timeout_source_id.take() {
+                    source_id.remove();
+                    self.timeout_deadline = None;
+                    sender.input(NotificationInput::Close(NotificationCloseReason::Expired));
+                }
+            }
+            NotificationInput::UpdateInputRegion => {
+                if let Some(notification) = root.child() {
+                    update_input_region(root, &notification, self.config.click_through);
+                }
+            }
+            NotificationInput::ShowMenu(x, y) => {
+                if let Some(notification) = root.child() {
+                    self.show_menu(&notification, x, y);
+                }
+            }
+            NotificationInput::OpenLink(uri) => {
+                let dismiss = self.config.close_on_link && !self.resident;
+                launch_uri(uri, dismiss, sender);
+            }
+            NotificationInput::Snooze => {
+                if let Some(source_id) = self.timeout_source_id.take() {
+                    source_id.remove();
+                }
+                self.set_progress(None);
+                root.set_visible(false);
+                root.close();
+                sender
+                    .output(NotificationOutput::Snoozed { id: self.id })
+                    .unwrap();
+            }
             NotificationInput::Replace(init) => {
-                let (mut notification_init, config) = *init;
-                let icon = notification_init.icon();
+                // The daemon keeps the monitor up to date separately
+                let (mut notification_init, config, _, _) = *init;
                 let default_action = notification_init.default_action();
-                let (config, overrides) = config.clone().overridden(&notification_init.app_name);
-                self.set_timeout(&notification_init, &overrides, sender);
-
-                self.actions_factory.guard().clear();
-                for (action, display) in notification_init.actions {
-                    info!("Action added for notification: {}, {}", action, display);
-                    self.actions_factory.guard().push_back((action, display));
+                let urgency = notification_init.hints.urgency.unwrap_or_default();
+                let (config, overrides) = config.clone().overridden(
+                    &notification_init.app_name,
+                    notification_init.hints.desktop_entry.as_deref(),
+                );
+                self.config = config.with_urgency(urgency);
+                // A player changing tracks stays in the media layout, so nothing moves around
+                self.media_actions = notification_init.has_media_actions();
+                let icon = notification_init.icon(
+                    &self.config,
+                    &mut self.icon_cache.borrow_mut(),
+                    self.layout().icon_size(&self.config),
+                );
+                let badge = if self.config.show_app_icon_badge {
+                    notification_init.badge(
+                        &mut self.icon_cache.borrow_mut(),
+                        self.layout().icon_size(&self.config),
+                    )
+                } else {
+                    NotificationIcon::None
+                };
+                // Keeping the timeout stops chatty applications from keeping their notification
+                // around forever by replacing it
+                if self.config.replace_timeout == ReplaceTimeout::Reset
+                    || (self.timeout_source_id.is_none() && self.paused_timeout.is_none())
+                {
+                    self.set_timeout(&notification_init, &overrides, sender.clone());
                 }
+                self.set_exclusive_zone(root);
 
-                self.set_icon(icon);
+                push_actions(
+                    &mut self.actions_factory,
+                    &notification_init,
+                    default_action.as_ref(),
+                    &self.config,
+                    self.layout() == Layout::Media,
+                );
+
+                // Only the changed properties are updated, keeping the window and its widgets around
+                self.set_icon(icon, badge, sender);
+                self.set_progress(notification_init.hints.value);
                 self.default_action = default_action;
-                self.config = config;
-                self.summary = notification_init.summary;
-                self.body = notification_init.body;
+                self.app_name = notification_init.app_name.clone();
+                self.desktop_entry = notification_init.hints.desktop_entry.clone();
+                self.urgency = urgency;
+                self.css_classes = notification_init.hints.css_classes.clone();
+                self.image_uri = notification_init.image_uri();
+                self.resident = notification_init.hints.resident.unwrap_or(false);
+                // The surface is moved to the new layer and position on its own if the urgency
+                // changed
+                layer::apply_config(root, &self.config, self.config.layer_for(self.urgency));
+                self.summary = format::expand(&self.config.summary_format, &notification_init);
+                self.body = format::body(&self.config, &notification_init);
+                if let Some(notification) = root.child() {
+                    self.update_accessible(&notification);
+                }
+                self.update_direction(root);
+
+                self.resize_to_content(root);
             }
         }
     }
@@ -475,31 +1276,397 @@ impl Notification {
         if let Some(source_id) = self.timeout_source_id.take() {
             source_id.remove();
         }
+        self.timeout_deadline = None;
+        self.paused_timeout = None;
         if let Some(timeout) = notification_init.timeout(&self.config, overrides) {
-            let source_id = glib::timeout_add_local_once(
-                Duration::from_secs(timeout as u64),
-                glib::clone!(
-                    #[strong]
-                    sender,
-                    move || {
-                        // This will error out if the notification was already closed for some other reason,
-                        // so we just discard the error
-                        let _ = sender
-                            .input_sender()
-                            .send(NotificationInput::Close(NotificationCloseReason::Expired));
-                    }
-                ),
+            self.start_timeout(timeout, sender);
+        }
+    }
+
+    fn start_timeout(&mut self, timeout: Duration, sender: ComponentSender<Self>) {
+        self.timeout_generation += 1;
+        let generation = self.timeout_generation;
+        // Removing a source that already ran out panics, so the source keeps going until the
+        // notification removes it when closing
+        let source_id = glib::timeout_add_local(
+            timeout,
+            glib::clone!(
+                #[strong]
+                sender,
+                move || {
+                    // This will error out if the notification was already closed for some other reason,
+                    // so we just discard the error
+                    let _ = sender
+                        .input_sender()
+                        .send(NotificationInput::TimeoutExpired(generation));
+                    glib::ControlFlow::Continue
+                }
+            ),
+        );
+        self.timeout_source_id = Some(source_id);
+        self.timeout_deadline = Some(Instant::now() + timeout);
+    }
+
+    /// Pop up the actions and closing in a menu at the position, for touch screens
+    fn show_menu(&self, notification: &gtk::Widget, x: f64, y: f64) {
+        let menu = gio::Menu::new();
+        for button in self.actions_factory.iter() {
+            let item = gio::MenuItem::new(Some(&button.display), None);
+            item.set_action_and_target_value(
+                Some("notification.invoke"),
+                Some(&button.action.to_variant()),
             );
-            self.timeout_source_id = Some(source_id);
+            menu.append_item(&item);
         }
+        menu.append(Some("Close"), Some("notification.close"));
+
+        let popover = gtk::PopoverMenu::from_model(Some(&menu));
+        popover.set_parent(notification);
+        popover.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+        popover.connect_closed(|popover| {
+            // The menu is closed before the chosen item is activated, which needs the parent
+            glib::idle_add_local_once(glib::clone!(
+                #[weak]
+                popover,
+                move || popover.unparent()
+            ));
+        });
+        popover.popup();
     }
 
-    fn set_icon(&self, icon: NotificationIcon) {
-        match &icon {
-            NotificationIcon::Path(path) => self.icon_widget.set_from_file(Some(path)),
-            NotificationIcon::Name(name) => self.icon_widget.set_icon_name(Some(name)),
-            NotificationIcon::Data(texture) => self.icon_widget.set_paintable(Some(texture)),
-            NotificationIcon::None => self.icon_widget.set_visible(false),
+    /// Reserve space for sticky notifications if configured to do so.
+    ///
+    /// Must be called after `Self::set_timeout`
+    fn set_exclusive_zone(&self, root: &gtk::Window) {
+        if self.config.exclusive_zone == ExclusiveZone::Auto && self.timeout_source_id.is_none() {
+            root.auto_exclusive_zone_enable();
+        } else {
+            root.set_exclusive_zone(0);
         }
     }
+
+    /// Make the window fit the current content, shrinking it if the content got smaller
+    fn resize_to_content(&self, root: &gtk::Window) {
+        // A height of 1 makes GTK fall back to the minimum height of the content
+        root.set_default_size(self.config.width, 1);
+        root.queue_resize();
+    }
+
+    fn set_progress(&mut self, value: Option<i32>) {
+        let progress = value.map(Progress::from_value);
+        self.progress_bar.set_visible(progress.is_some());
+
+        if progress != Some(Progress::Indeterminate) {
+            if let Some(source_id) = self.pulse_source_id.take() {
+                source_id.remove();
+            }
+        }
+
+        match progress {
+            Some(Progress::Determinate { fraction, overfill }) => {
+                self.progress_bar.set_fraction(fraction);
+                if overfill {
+                    self.progress_bar.add_css_class("overfill");
+                } else {
+                    self.progress_bar.remove_css_class("overfill");
+                }
+            }
+            Some(Progress::Indeterminate) if self.pulse_source_id.is_none() => {
+                self.progress_bar.remove_css_class("overfill");
+                self.pulse_source_id = Some(glib::timeout_add_local(
+                    Duration::from_millis(100),
+                    glib::clone!(
+                        #[weak(rename_to = progress_bar)]
+                        self.progress_bar,
+                        #[upgrade_or]
+                        glib::ControlFlow::Break,
+                        move || {
+                            progress_bar.pulse();
+                            glib::ControlFlow::Continue
+                        }
+                    ),
+                ));
+            }
+            _ => (),
+        }
+    }
+
+    /// Mirror the layout for right-to-left text.
+    ///
+    /// Labels flip their alignment and boxes and grids their children in right-to-left mode,
+    /// while Pango still lays out each paragraph in the direction of its own text.
+    fn update_direction(&self, root: &gtk::Window) {
+        let direction = match self.config.text_direction {
+            TextDirection::Ltr => gtk::TextDirection::Ltr,
+            TextDirection::Rtl => gtk::TextDirection::Rtl,
+            TextDirection::Auto => {
+                // The summary decides, unless it has no strongly directional characters
+                let direction = [&self.summary, &self.body]
+                    .into_iter()
+                    .map(|text| pango::find_base_dir(&format::strip_markup(text)))
+                    .find(|direction| *direction != pango::Direction::Neutral);
+                match direction {
+                    Some(pango::Direction::Rtl | pango::Direction::WeakRtl) => {
+                        gtk::TextDirection::Rtl
+                    }
+                    _ => gtk::TextDirection::Ltr,
+                }
+            }
+        };
+
+        // Widgets don't inherit the direction of their parent
+        let mut widgets = vec![root.clone().upcast::<gtk::Widget>()];
+        while let Some(widget) = widgets.pop() {
+            widget.set_direction(direction);
+            let mut child = widget.first_child();
+            while let Some(widget) = child {
+                child = widget.next_sibling();
+                widgets.push(widget);
+            }
+        }
+    }
+
+    /// What screen readers announce the notification as
+    fn accessible_label(&self) -> String {
+        let summary = format::strip_markup(&self.summary);
+        match self.urgency {
+            Urgency::Critical => format!("Critical notification from {}: {summary}", self.app_name),
+            _ => format!("Notification from {}: {summary}", self.app_name),
+        }
+    }
+
+    fn layout(&self) -> Layout {
+        Layout::new(&self.config, self.compact, self.media_actions)
+    }
+
+    fn css_classes(&self) -> Vec<&str> {
+        let mut css_classes = vec!["notification", self.urgency.as_str(), &self.app_name];
+        if self.config.critical_sticky && self.urgency == Urgency::Critical {
+            css_classes.push("awaiting-dismissal");
+        }
+        if self.selected {
+            css_classes.push("selected");
+        }
+        match self.layout() {
+            Layout::Full => (),
+            Layout::Media => css_classes.push("media"),
+            Layout::Compact => css_classes.push("compact"),
+        }
+        css_classes.extend(self.css_classes.iter().map(String::as_str));
+        css_classes
+    }
+
+    fn update_accessible(&self, notification: &impl IsA<gtk::Accessible>) {
+        notification.update_property(&[
+            gtk::accessible::Property::Label(&self.accessible_label()),
+            gtk::accessible::Property::Description(&format::strip_markup(&self.body)),
+        ]);
+    }
+
+    fn set_icon(
+        &mut self,
+        icon: NotificationIcon,
+        badge: NotificationIcon,
+        sender: ComponentSender<Self>,
+    ) {
+        self.icon_generation += 1;
+        icon.apply(&self.icon_widget);
+        badge.apply(&self.badge_widget);
+
+        let NotificationIcon::Decoding {
+            data, pixel_size, ..
+        } = icon
+        else {
+            return;
+        };
+        // The placeholder may well be the app icon itself
+        self.badge_widget.set_visible(false);
+        let id = self.id;
+        let generation = self.icon_generation;
+        glib::spawn_future_local(async move {
+            let decoded = gio::spawn_blocking(move || {
+                let texture = icon_cache::decode_data(&data, pixel_size);
+                (data, texture)
+            })
+            .await;
+            match decoded {
+                Ok((data, texture)) => sender.input(NotificationInput::ImageDecoded {
+                    generation,
+                    data,
+                    pixel_size,
+                    texture,
+                }),
+                Err(_) => warn!("Decoding the image of notification {id} panicked"),
+            }
+        });
+    }
+}
+
+/// Open the URI with the default application, closing the notification afterwards if it was
+/// opened and `dismiss` is set
+fn launch_uri(uri: String, dismiss: bool, sender: ComponentSender<Notification>) {
+    gio::AppInfo::launch_default_for_uri_async(
+        &uri.clone(),
+        None::<&gio::AppLaunchContext>,
+        None::<&gio::Cancellable>,
+        move |result| match result {
+            Ok(()) if dismiss => {
+                // The notification may have been closed in the meantime
+                let _ = sender.input_sender().send(NotificationInput::Close(
+                    NotificationCloseReason::DismissedByUser,
+                ));
+            }
+            Ok(()) => (),
+            Err(why) => warn!("Failed to open {uri}: {why}"),
+        },
+    );
+}
+
+/// The actions of the long press menu, which go through the same outputs as the buttons
+fn menu_actions(id: u32, sender: &ComponentSender<Notification>) -> gio::SimpleActionGroup {
+    let invoke = gio::SimpleAction::new("invoke", Some(glib::VariantTy::STRING));
+    invoke.connect_activate(glib::clone!(
+        #[strong]
+        sender,
+        move |_, parameter| {
+            if let Some(action) = parameter.and_then(|parameter| parameter.str()) {
+                sender
+                    .output(NotificationOutput::ActionInvoked {
+                        id,
+                        action: action.to_string(),
+                    })
+                    .unwrap();
+            }
+        }
+    ));
+    let close = gio::SimpleAction::new("close", None);
+    close.connect_activate(glib::clone!(
+        #[strong]
+        sender,
+        move |_, _| {
+            sender.input(NotificationInput::Close(
+                NotificationCloseReason::DismissedByUser,
+            ));
+        }
+    ));
+
+    let actions = gio::SimpleActionGroup::new();
+    actions.add_action(&invoke);
+    actions.add_action(&close);
+    actions
+}
+
+/// Replace the action buttons with the actions of the notification.
+///
+/// The default action is only included if configured, and never counts towards the timeout as
+/// it has already been taken out of the actions. In the media layout the other actions are
+/// shown as icons if the application asked for it.
+fn push_actions(
+    actions_factory: &mut FactoryVecDeque<ActionButton>,
+    notification_init: &NotificationInit,
+    default_action: Option<&String>,
+    config: &Config,
+    media: bool,
+) {
+    let icons = media && notification_init.hints.action_icons == Some(true);
+    let default_action = default_action
+        .filter(|_| config.show_default_action_button)
+        .map(|display| {
+            // Some applications leave the label of the default action empty
+            let display = if display.is_empty() {
+                "Open".to_string()
+            } else {
+                display.clone()
+            };
+            (DEFAULT_ACTION.to_string(), display)
+        });
+
+    let mut guard = actions_factory.guard();
+    guard.clear();
+    let action_description = action_description(notification_init);
+    for (action, display) in default_action
+        .into_iter()
+        .chain(notification_init.actions.clone())
+    {
+        info!("Action added for notification: {}, {}", action, display);
+        let icon = icons && action != DEFAULT_ACTION;
+        guard.push_back((action, display, action_description.clone(), icon));
+    }
+}
+
+/// The label of an action button, or the icon named by the action key
+fn action_child(action: &str, display: &str, icon: bool) -> gtk::Widget {
+    if icon {
+        gtk::Image::from_icon_name(action).upcast()
+    } else {
+        gtk::Label::new(Some(display)).upcast()
+    }
+}
+
+/// Tells screen reader users which notification an action button belongs to
+fn action_description(notification_init: &NotificationInit) -> String {
+    format!(
+        "Action of the notification from {}: {}",
+        notification_init.app_name,
+        format::strip_markup(&notification_init.summary)
+    )
+}
+
+/// Restrict pointer input to the notification itself, so that the transparent area around it
+/// does not block clicks to whatever is underneath. With `click_through` the notification lets
+/// all input through as well.
+fn update_input_region(window: &gtk::Window, notification: &gtk::Widget, click_through: bool) {
+    let Some(surface) = window.surface() else {
+        return;
+    };
+    if click_through {
+        surface.set_input_region(&cairo::Region::create());
+        return;
+    }
+    let Some(bounds) = notification.compute_bounds(window) else {
+        return;
+    };
+    let (offset_x, offset_y) = window.surface_transform();
+
+    let region = cairo::Region::create_rectangle(&cairo::RectangleInt::new(
+        (bounds.x() as f64 + offset_x).floor() as i32,
+        (bounds.y() as f64 + offset_y).floor() as i32,
+        bounds.width().ceil() as i32,
+        bounds.height().ceil() as i32,
+    ));
+    surface.set_input_region(&region);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_progress_is_indeterminate() {
+        assert_eq!(Progress::from_value(-1), Progress::Indeterminate);
+    }
+
+    #[test]
+    fn progress_in_range() {
+        for (value, fraction) in [(0, 0.0), (55, 0.55), (100, 1.0)] {
+            assert_eq!(
+                Progress::from_value(value),
+                Progress::Determinate {
+                    fraction,
+                    overfill: false
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn progress_over_100_is_clamped() {
+        assert_eq!(
+            Progress::from_value(150),
+            Progress::Determinate {
+                fraction: 1.0,
+                overfill: true
+            }
+        );
+    }
 }
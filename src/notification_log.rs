@@ -0,0 +1,95 @@
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+use gtk4::glib;
+
+use crate::{
+    format,
+    logger::LogFile,
+    notification::{NotificationCloseReason, NotificationInit},
+};
+
+/// Appends a line for every received and closed notification to a file.
+///
+/// Lines are tab separated, starting with a timestamp and either `received` or `closed`. They
+/// are written from a separate thread, so that a slow disk can't hold up the rest of Yand.
+pub struct NotificationLog {
+    path: PathBuf,
+    sender: mpsc::Sender<String>,
+}
+
+impl NotificationLog {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut log_file = LogFile::open(path)?;
+        let (sender, receiver) = mpsc::channel::<String>();
+
+        // Exits once the log is dropped and everything sent before has been written
+        thread::spawn(move || {
+            while let Ok(line) = receiver.recv() {
+                let mut lines = line;
+                // Lines sent in quick succession are written at once
+                for line in receiver.try_iter() {
+                    lines.push_str(&line);
+                }
+
+                let result = log_file
+                    .reopen_if_stale()
+                    .and_then(|_| log_file.file.write_all(lines.as_bytes()));
+                if let Err(why) = result {
+                    log::warn!(
+                        "Failed to write to notification log {}: {why}",
+                        log_file.path.display()
+                    );
+                }
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            sender,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn received(&self, init: &NotificationInit) {
+        self.write(&[
+            "received",
+            &init.id.to_string(),
+            &init.app_name,
+            &init.hints.urgency.unwrap_or_default().to_string(),
+            &init.summary,
+            &format::strip_markup(&init.body),
+        ]);
+    }
+
+    pub fn closed(&self, id: u32, reason: NotificationCloseReason) {
+        self.write(&["closed", &id.to_string(), &reason.to_string()]);
+    }
+
+    fn write(&self, fields: &[&str]) {
+        let timestamp = glib::DateTime::now_local()
+            .and_then(|time| time.format("%FT%T%:z"))
+            .map(|time| time.to_string())
+            .unwrap_or_default();
+
+        let mut line = timestamp;
+        for field in fields {
+            line.push('\t');
+            // Every entry must stay on its own line
+            line.extend(field.chars().map(|c| match c {
+                '\t' | '\n' | '\r' => ' ',
+                c => c,
+            }));
+        }
+        line.push('\n');
+
+        let _ = self.sender.send(line);
+    }
+}
@@ -0,0 +1,160 @@
+use gtk::{gdk, prelude::*};
+use gtk4 as gtk;
+use gtk4_layer_shell::LayerShell;
+use relm4::prelude::*;
+
+use crate::{config::Config, layer, notification::CountBadge, panel};
+
+/// How many app icons are shown at most
+const MAX_ICONS: usize = 5;
+
+#[derive(Debug)]
+pub enum NotificationStackInput {
+    /// The app icons of the collapsed notifications, none hiding the stack
+    SetCollapsed(Vec<String>),
+    ChangeOffset(i32),
+    ChangeMonitor(Option<gdk::Monitor>),
+    ConfigChanged(Config),
+}
+
+#[derive(Debug)]
+pub enum NotificationStackOutput {
+    /// Show the collapsed notifications again
+    Expand,
+    /// Dismiss the collapsed notifications
+    Clear,
+}
+
+/// A single card standing in for the older notifications once too many are shown
+#[derive(Debug)]
+pub struct NotificationStack {
+    config: Config,
+    count: usize,
+    offset: i32,
+    icons: gtk::Box,
+}
+
+#[relm4::component(pub)]
+impl Component for NotificationStack {
+    type Init = (Config, Option<gdk::Monitor>);
+    type Input = NotificationStackInput;
+    type Output = NotificationStackOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            #[watch]
+            set_margin: (model.config.position.anchor_edge(), model.offset),
+            set_monitor: monitor.as_ref(),
+            set_default_size: (model.config.width, 1),
+            #[watch]
+            set_visible: model.count > 0,
+
+            gtk::Box {
+                set_css_classes: &["notification-stack"],
+                set_orientation: gtk::Orientation::Horizontal,
+                add_controller = gtk::GestureClick {
+                    connect_released[sender] => move |gesture, _, _, _| {
+                        gesture.set_state(gtk::EventSequenceState::Claimed);
+                        sender.output(NotificationStackOutput::Expand).unwrap();
+                    }
+                },
+
+                #[local_ref]
+                icons_box -> gtk::Box {
+                    set_css_classes: &["icons"],
+                    set_orientation: gtk::Orientation::Horizontal,
+                },
+                gtk::Label {
+                    set_css_classes: &["summary"],
+                    set_halign: gtk::Align::Start,
+                    set_hexpand: true,
+                    #[watch]
+                    set_label: &match model.count {
+                        1 => "1 older notification".to_string(),
+                        count => format!("{count} older notifications"),
+                    },
+                },
+                gtk::Button {
+                    set_label: "Clear",
+                    set_css_classes: &["clear", "flat"],
+                    set_valign: gtk::Align::Center,
+                    connect_clicked[sender] => move |_| {
+                        sender.output(NotificationStackOutput::Clear).unwrap();
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(
+        (config, monitor): Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self {
+            offset: config.margin_anchor,
+            config,
+            count: 0,
+            icons: gtk::Box::default(),
+        };
+
+        let icons_box = &model.icons;
+
+        layer::init(
+            &root,
+            &model.config,
+            &format!("{}-stack", model.config.namespace),
+            model.config.layer.clone().into(),
+        );
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, message: Self::Input, _sender: ComponentSender<Self>, root: &Self::Root) {
+        match message {
+            NotificationStackInput::SetCollapsed(app_icons) => {
+                self.count = app_icons.len();
+                self.set_icons(app_icons);
+            }
+            NotificationStackInput::ChangeOffset(offset) => {
+                self.offset = self.config.margin_anchor + offset;
+            }
+            NotificationStackInput::ChangeMonitor(monitor) => root.set_monitor(monitor.as_ref()),
+            NotificationStackInput::ConfigChanged(config) => {
+                self.config = config;
+                layer::apply_config(root, &self.config, self.config.layer.clone().into());
+                root.set_default_size(self.config.width, 1);
+            }
+        }
+    }
+}
+
+impl NotificationStack {
+    /// Show each distinct app icon once, newest first, with how many notifications of the app
+    /// were collapsed
+    fn set_icons(&self, mut app_icons: Vec<String>) {
+        while let Some(child) = self.icons.first_child() {
+            self.icons.remove(&child);
+        }
+        app_icons.reverse();
+        let mut shown = Vec::new();
+        for app_icon in &app_icons {
+            if shown.len() == MAX_ICONS {
+                break;
+            }
+            if shown.contains(&app_icon) {
+                continue;
+            }
+            let image = gtk::Image::from_gicon(&panel::entry_icon(app_icon));
+            image.set_pixel_size(24);
+            let badge = CountBadge::new();
+            badge.set_count(app_icons.iter().filter(|icon| *icon == app_icon).count() as u32);
+            let overlay = gtk::Overlay::builder().child(&image).build();
+            overlay.add_overlay(badge.widget());
+            self.icons.append(&overlay);
+            shown.push(app_icon);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::notification::NotificationCloseReason;
+
+/// What a command prints, either as text for people or as JSON with `--json`
+pub trait CommandOutput: Serialize {
+    fn print_text(&self);
+}
+
+/// Print the output of a command in the format asked for
+pub fn print(output: &impl CommandOutput, json: bool) {
+    if json {
+        println!("{}", serde_json::to_string(output).unwrap());
+    } else {
+        output.print_text();
+    }
+}
+
+/// Report an error, as a JSON object on stderr with `--json`
+pub fn error(why: &impl Display, json: bool) {
+    if json {
+        eprintln!("{}", json!({ "error": why.to_string() }));
+    } else {
+        log::error!("{why}");
+    }
+}
+
+#[derive(Serialize)]
+pub struct ListedNotification {
+    pub id: u32,
+    pub app_name: String,
+    pub summary: String,
+}
+
+/// The shown or snoozed notifications
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct NotificationList(pub Vec<ListedNotification>);
+
+impl CommandOutput for NotificationList {
+    fn print_text(&self) {
+        for notification in &self.0 {
+            println!(
+                "{}\t{}\t{}",
+                notification.id, notification.app_name, notification.summary
+            );
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Level {
+    pub level: String,
+}
+
+impl CommandOutput for Level {
+    fn print_text(&self) {
+        println!("{}", self.level);
+    }
+}
+
+/// How many notifications were closed
+#[derive(Serialize)]
+pub struct Closed {
+    pub closed: u32,
+}
+
+impl CommandOutput for Closed {
+    fn print_text(&self) {
+        println!("{}", self.closed);
+    }
+}
+
+/// The config changes, config errors and style errors of a reload
+#[derive(Serialize)]
+pub struct Reload {
+    pub changes: Vec<String>,
+    pub config_errors: Vec<String>,
+    pub style_errors: Vec<String>,
+}
+
+impl Reload {
+    /// Whether the config and style files were read without errors
+    pub fn ok(&self) -> bool {
+        self.config_errors.is_empty() && self.style_errors.is_empty()
+    }
+}
+
+impl CommandOutput for Reload {
+    fn print_text(&self) {
+        if self.changes.is_empty() {
+            println!("No changes in the config");
+        }
+        for change in &self.changes {
+            println!("{change}");
+        }
+        for why in self.config_errors.iter().chain(&self.style_errors) {
+            eprintln!("{why}");
+        }
+    }
+}
+
+/// A config option laid over the config file
+#[derive(Serialize)]
+pub struct SetOption {
+    pub key: String,
+    /// `command-line` for `--set`, `runtime` for `yand set`
+    pub source: String,
+}
+
+/// The config the daemon runs with
+#[derive(Serialize)]
+pub struct RunningConfig {
+    pub config: toml::Table,
+    /// The options that don't come from the config file
+    pub options: Vec<SetOption>,
+}
+
+impl CommandOutput for RunningConfig {
+    fn print_text(&self) {
+        let printed = toml::to_string(&self.config).unwrap_or_default();
+        // Only top-level options can be set, which come before the first table
+        let mut top_level = true;
+        for line in printed.lines() {
+            top_level &= !line.starts_with('[');
+            let option = line
+                .split_once(" = ")
+                .filter(|_| top_level)
+                .and_then(|(key, _)| self.options.iter().find(|option| option.key == key));
+            match option.map(|option| option.source.as_str()) {
+                Some("command-line") => println!("{line} # --set"),
+                Some(_) => println!("{line} # yand set"),
+                None => println!("{line}"),
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MinUrgency {
+    pub urgency: String,
+    /// How many notifications were kept off the screen since the urgency was last set
+    pub suppressed: u32,
+}
+
+impl CommandOutput for MinUrgency {
+    fn print_text(&self) {
+        println!("{} ({} suppressed)", self.urgency, self.suppressed);
+    }
+}
+
+#[derive(Serialize)]
+pub struct DndStatus {
+    pub enabled: bool,
+    /// What enabled do not disturb
+    pub source: String,
+    /// How many notifications do not disturb holds back
+    pub queued: u32,
+}
+
+impl CommandOutput for DndStatus {
+    fn print_text(&self) {
+        println!("{}", if self.enabled { "on" } else { "off" });
+    }
+}
+
+/// The ID of a sent notification
+#[derive(Serialize)]
+pub struct Sent {
+    pub id: u32,
+}
+
+impl CommandOutput for Sent {
+    fn print_text(&self) {
+        println!("{}", self.id);
+    }
+}
+
+/// How a notification that was waited for was resolved
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolved {
+    Closed(String),
+    Action(String),
+}
+
+impl Resolved {
+    pub fn closed(reason: NotificationCloseReason) -> Self {
+        Self::Closed(reason.to_string())
+    }
+}
+
+impl CommandOutput for Resolved {
+    fn print_text(&self) {
+        match self {
+            Resolved::Closed(reason) => println!("closed:{reason}"),
+            Resolved::Action(action) => println!("action:{action}"),
+        }
+    }
+}
+
+/// The action invoked on a sent notification, if it wasn't closed without one
+#[derive(Serialize)]
+pub struct Invoked {
+    pub action: Option<String>,
+}
+
+impl CommandOutput for Invoked {
+    fn print_text(&self) {
+        if let Some(action) = &self.action {
+            println!("{action}");
+        }
+    }
+}
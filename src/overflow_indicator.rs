@@ -0,0 +1,93 @@
+use gtk::{gdk, prelude::*};
+use gtk4 as gtk;
+use gtk4_layer_shell::LayerShell;
+use relm4::prelude::*;
+
+use crate::{config::Config, layer};
+
+#[derive(Debug)]
+pub enum OverflowIndicatorInput {
+    /// How many notifications are waiting for room, 0 hiding the indicator
+    SetCount(usize),
+    ChangeOffset(i32),
+    ChangeMonitor(Option<gdk::Monitor>),
+    ConfigChanged(Config),
+}
+
+/// A row below the notifications telling how many are waiting for room on the screen.
+///
+/// Outputs `()` when clicked.
+#[derive(Debug)]
+pub struct OverflowIndicator {
+    config: Config,
+    count: usize,
+    offset: i32,
+}
+
+#[relm4::component(pub)]
+impl Component for OverflowIndicator {
+    type Init = (Config, Option<gdk::Monitor>);
+    type Input = OverflowIndicatorInput;
+    type Output = ();
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            #[watch]
+            set_margin: (model.config.position.anchor_edge(), model.offset),
+            set_monitor: monitor.as_ref(),
+            set_default_size: (model.config.width, 1),
+            #[watch]
+            set_visible: model.count > 0,
+
+            gtk::Button {
+                set_css_classes: &["overflow-indicator", "flat"],
+                #[watch]
+                set_label: &match model.count {
+                    1 => "+ 1 more notification".to_string(),
+                    count => format!("+ {count} more notifications"),
+                },
+                connect_clicked[sender] => move |_| {
+                    sender.output(()).unwrap();
+                }
+            }
+        }
+    }
+
+    fn init(
+        (config, monitor): Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self {
+            offset: config.margin_anchor,
+            config,
+            count: 0,
+        };
+
+        layer::init(
+            &root,
+            &model.config,
+            &format!("{}-overflow", model.config.namespace),
+            model.config.layer.clone().into(),
+        );
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, message: Self::Input, _sender: ComponentSender<Self>, root: &Self::Root) {
+        match message {
+            OverflowIndicatorInput::SetCount(count) => self.count = count,
+            OverflowIndicatorInput::ChangeOffset(offset) => {
+                self.offset = self.config.margin_anchor + offset;
+            }
+            OverflowIndicatorInput::ChangeMonitor(monitor) => root.set_monitor(monitor.as_ref()),
+            OverflowIndicatorInput::ConfigChanged(config) => {
+                self.config = config;
+                layer::apply_config(root, &self.config, self.config.layer.clone().into());
+                root.set_default_size(self.config.width, 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::time::SystemTime;
+
+use gtk::{gdk, gio, glib, pango, prelude::*};
+use gtk4 as gtk;
+use gtk4_layer_shell::LayerShell;
+use relm4::prelude::*;
+
+use crate::{config::Config, format, history::HistoryEntry, layer};
+
+#[derive(Debug)]
+pub struct PanelEntry {
+    entry: HistoryEntry,
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for PanelEntry {
+    type Init = HistoryEntry;
+    type Input = ();
+    type Output = PanelOutput;
+    type CommandOutput = ();
+    type ParentWidget = gtk::Box;
+
+    view! {
+        gtk::Box {
+            set_css_classes: &["entry", &self.entry.urgency.to_string(), &self.entry.app_name],
+            set_orientation: gtk::Orientation::Horizontal,
+            add_controller = gtk::GestureClick {
+                connect_released: glib::clone!(
+                    #[strong(rename_to = id)] self.entry.id,
+                    #[strong] sender,
+                    move |gesture, _, _, _| {
+                        gesture.set_state(gtk::EventSequenceState::Claimed);
+                        sender.output(PanelOutput::Activate(id)).unwrap();
+                    }
+                )
+            },
+
+            gtk::Image {
+                set_from_gicon: &entry_icon(&self.entry.app_icon),
+                set_pixel_size: 32,
+                set_valign: gtk::Align::Start,
+                set_css_classes: &["icon"],
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_hexpand: true,
+
+                gtk::Label {
+                    set_label: &self.entry.summary,
+                    set_css_classes: &["summary"],
+                    set_halign: gtk::Align::Start,
+                    set_ellipsize: pango::EllipsizeMode::End,
+                    set_use_markup: true,
+                },
+                gtk::Label {
+                    set_label: &first_line(&self.entry.body),
+                    set_css_classes: &["body"],
+                    set_halign: gtk::Align::Start,
+                    set_ellipsize: pango::EllipsizeMode::End,
+                    set_visible: !self.entry.body.is_empty(),
+                },
+                gtk::Label {
+                    set_label: &relative_time(self.entry.received),
+                    set_css_classes: &["time"],
+                    set_halign: gtk::Align::Start,
+                },
+            },
+
+            gtk::Button {
+                set_icon_name: "window-close-symbolic",
+                set_css_classes: &["dismiss", "flat"],
+                set_valign: gtk::Align::Start,
+                connect_clicked: glib::clone!(
+                    #[strong(rename_to = id)] self.entry.id,
+                    move |_| {
+                        sender.output(PanelOutput::Dismiss(id)).unwrap();
+                    }
+                )
+            }
+        }
+    }
+
+    fn init_model(entry: Self::Init, _index: &Self::Index, _sender: FactorySender<Self>) -> Self {
+        Self { entry }
+    }
+}
+
+#[derive(Debug)]
+pub enum PanelInput {
+    Toggle,
+    /// Replace the listed notifications, newest first
+    SetEntries(Vec<HistoryEntry>),
+    ChangeMonitor(Option<gdk::Monitor>),
+    ConfigChanged(Config),
+}
+
+#[derive(Debug)]
+pub enum PanelOutput {
+    Dismiss(u32),
+    Activate(u32),
+    Clear,
+}
+
+/// A notification center listing the notifications in the history
+#[derive(Debug)]
+pub struct Panel {
+    visible: bool,
+    config: Config,
+    history: Vec<HistoryEntry>,
+    entries: FactoryVecDeque<PanelEntry>,
+}
+
+#[relm4::component(pub)]
+impl Component for Panel {
+    type Init = (Config, Option<gdk::Monitor>);
+    type Input = PanelInput;
+    type Output = PanelOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_monitor: monitor.as_ref(),
+            set_default_size: (model.config.width, 1),
+            #[watch]
+            set_visible: model.visible,
+
+            gtk::Box {
+                set_css_classes: &["panel"],
+                set_orientation: gtk::Orientation::Vertical,
+
+                gtk::Box {
+                    set_css_classes: &["header"],
+                    set_orientation: gtk::Orientation::Horizontal,
+
+                    gtk::Label {
+                        set_label: "Notifications",
+                        set_css_classes: &["title"],
+                        set_halign: gtk::Align::Start,
+                        set_hexpand: true,
+                    },
+                    gtk::Button {
+                        set_label: "Clear all",
+                        set_css_classes: &["clear", "flat"],
+                        connect_clicked[sender] => move |_| {
+                            sender.output(PanelOutput::Clear).unwrap();
+                        }
+                    }
+                },
+
+                gtk::ScrolledWindow {
+                    set_hscrollbar_policy: gtk::PolicyType::Never,
+                    set_propagate_natural_height: true,
+                    set_max_content_height: 600,
+
+                    #[local_ref]
+                    entries_box -> gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_css_classes: &["entries"],
+                    }
+                },
+
+                gtk::Label {
+                    set_label: "No notifications",
+                    set_css_classes: &["empty"],
+                    #[watch]
+                    set_visible: model.history.is_empty(),
+                }
+            }
+        }
+    }
+
+    fn init(
+        (config, monitor): Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let entries = FactoryVecDeque::builder()
+            .launch(gtk::Box::default())
+            .forward(sender.output_sender(), |output| output);
+
+        let model = Self {
+            visible: false,
+            config,
+            history: Vec::new(),
+            entries,
+        };
+
+        let entries_box = model.entries.widget();
+
+        layer::init(
+            &root,
+            &model.config,
+            &format!("{}-panel", model.config.namespace),
+            model.config.layer.clone().into(),
+        );
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, message: Self::Input, _sender: ComponentSender<Self>, root: &Self::Root) {
+        match message {
+            PanelInput::Toggle => {
+                self.visible = !self.visible;
+                // Refresh the relative times
+                if self.visible {
+                    self.populate();
+                }
+            }
+            PanelInput::SetEntries(history) => {
+                self.history = history;
+                self.populate();
+            }
+            PanelInput::ChangeMonitor(monitor) => root.set_monitor(monitor.as_ref()),
+            PanelInput::ConfigChanged(config) => {
+                self.config = config;
+                layer::apply_config(root, &self.config, self.config.layer.clone().into());
+                root.set_default_size(self.config.width, 1);
+            }
+        }
+    }
+}
+
+impl Panel {
+    fn populate(&mut self) {
+        let mut entries = self.entries.guard();
+        entries.clear();
+        for entry in &self.history {
+            entries.push_back(entry.clone());
+        }
+    }
+}
+
+/// The icon of the application, falling back to a generic one
+pub fn entry_icon(app_icon: &str) -> gio::Icon {
+    if let Ok((path, _)) = glib::filename_from_uri(app_icon) {
+        gio::FileIcon::new(&gio::File::for_path(path)).upcast()
+    } else if app_icon.starts_with('/') {
+        gio::FileIcon::new(&gio::File::for_path(app_icon)).upcast()
+    } else {
+        gio::ThemedIcon::from_names(&[app_icon, "dialog-information-symbolic"]).upcast()
+    }
+}
+
+/// The first line of the body without markup
+fn first_line(body: &str) -> String {
+    format::strip_markup(body)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn relative_time(received: SystemTime) -> String {
+    let seconds = received.elapsed().unwrap_or_default().as_secs();
+    match seconds {
+        0..60 => "Just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} d ago", seconds / 86400),
+    }
+}
@@ -0,0 +1,621 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use gtk4::{gdk::gdk_pixbuf, gio, glib, prelude::*};
+
+use crate::{
+    daemon::{DaemonState, close_notification, notify},
+    dbus::NotifyArgs,
+    notification::DEFAULT_ACTION,
+};
+
+pub const BUS_NAME: &str = "org.freedesktop.impl.portal.desktop.yand";
+pub const PATH: &str = "/org/freedesktop/portal/desktop";
+pub const IFACE: &str = "org.freedesktop.impl.portal.Notification";
+const PROPERTIES_IFACE: &str = "org.freedesktop.DBus.Properties";
+
+const INTERFACE_XML: &str = r#"
+<node>
+    <interface name="org.freedesktop.impl.portal.Notification">
+        <method name="AddNotification">
+            <arg type="s" name="app_id" direction="in"/>
+            <arg type="s" name="id" direction="in"/>
+            <arg type="a{sv}" name="notification" direction="in"/>
+        </method>
+        <method name="RemoveNotification">
+            <arg type="s" name="app_id" direction="in"/>
+            <arg type="s" name="id" direction="in"/>
+        </method>
+        <signal name="ActionInvoked">
+            <arg type="s" name="app_id"/>
+            <arg type="s" name="id"/>
+            <arg type="s" name="action"/>
+            <arg type="av" name="parameter"/>
+        </signal>
+        <property type="u" name="version" access="read"/>
+    </interface>
+</node>
+"#;
+
+#[derive(Debug, glib::Variant)]
+struct AddNotificationArgs {
+    app_id: String,
+    id: String,
+    notification: HashMap<String, glib::Variant>,
+}
+
+#[derive(Debug, glib::Variant)]
+struct RemoveNotificationArgs {
+    app_id: String,
+    id: String,
+}
+
+#[derive(Debug)]
+enum PortalMethod {
+    AddNotification(AddNotificationArgs),
+    RemoveNotification(RemoveNotificationArgs),
+    /// Properties are handled like methods, as GDBus passes them on when no property handler
+    /// is registered, which lets unknown ones be answered with an error
+    GetProperty(String),
+    GetAllProperties,
+}
+
+impl DBusMethodCall for PortalMethod {
+    fn parse_call(
+        _obj_path: &str,
+        interface: Option<&str>,
+        method: &str,
+        params: glib::Variant,
+    ) -> Result<Self, glib::Error> {
+        match (interface, method) {
+            (Some(PROPERTIES_IFACE), "Get") => Ok(params
+                .get::<(String, String)>()
+                .map(|(_, name)| Self::GetProperty(name))),
+            (Some(PROPERTIES_IFACE), "GetAll") => Ok(Some(Self::GetAllProperties)),
+            (Some(PROPERTIES_IFACE), "Set") => Err(glib::Error::new(
+                gio::DBusError::PropertyReadOnly,
+                "The properties are read-only",
+            )),
+            (_, "AddNotification") => Ok(params
+                .get::<AddNotificationArgs>()
+                .map(Self::AddNotification)),
+            (_, "RemoveNotification") => Ok(params
+                .get::<RemoveNotificationArgs>()
+                .map(Self::RemoveNotification)),
+            _ => Err(glib::Error::new(
+                gio::DBusError::UnknownMethod,
+                "No such method",
+            )),
+        }
+        .and_then(|p| {
+            p.ok_or_else(|| glib::Error::new(gio::DBusError::InvalidArgs, "Invalid parameters"))
+        })
+    }
+}
+
+/// An action of a portal notification, by the key it was given in Yand
+#[derive(Debug)]
+struct PortalAction {
+    /// The name of the action in the application, like `app.reply`
+    name: String,
+    target: Option<glib::Variant>,
+}
+
+/// A notification sent through the portal
+#[derive(Debug)]
+struct PortalNotification {
+    app_id: String,
+    id: String,
+    /// The IDs of identical notifications of the application that were absorbed into this one
+    absorbed: Vec<String>,
+    actions: HashMap<String, PortalAction>,
+}
+
+/// The notifications sent through the portal that are still around, by their Yand ID
+#[derive(Debug, Default)]
+pub struct Portal {
+    notifications: HashMap<u32, PortalNotification>,
+    /// Notifications waiting for their icon to be decoded, by application and ID, with the
+    /// number of the decode that is still wanted
+    decoding: HashMap<(String, String), u64>,
+    decodes: u64,
+}
+
+impl Portal {
+    /// The Yand ID of the notification the application sent with `id`, so that sending it
+    /// again replaces it
+    fn find(&self, app_id: &str, id: &str) -> Option<u32> {
+        self.notifications
+            .iter()
+            .find(|(_, notification)| {
+                notification.app_id == app_id
+                    && (notification.id == id || notification.absorbed.iter().any(|a| a == id))
+            })
+            .map(|(yand_id, _)| *yand_id)
+    }
+
+    /// Remember the notification the application sent with `id` as Yand notification
+    /// `yand_id`, which belongs to another notification of the application if it was absorbed
+    /// as a duplicate of it
+    fn insert(
+        &mut self,
+        yand_id: u32,
+        app_id: String,
+        id: String,
+        actions: HashMap<String, PortalAction>,
+    ) {
+        match self.notifications.get_mut(&yand_id) {
+            Some(notification) if notification.app_id == app_id && notification.id != id => {
+                if !notification.absorbed.contains(&id) {
+                    notification.absorbed.push(id);
+                }
+            }
+            _ => {
+                self.notifications.insert(
+                    yand_id,
+                    PortalNotification {
+                        app_id,
+                        id,
+                        absorbed: Vec::new(),
+                        actions,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Forget a closed notification
+    pub fn remove(&mut self, id: u32) {
+        self.notifications.remove(&id);
+    }
+
+    /// The parameters of the ActionInvoked signal of the portal for the action invoked on
+    /// notification `id`, if the notification came from the portal
+    pub fn action_invoked(&self, id: u32, key: &str) -> Option<glib::Variant> {
+        let notification = self.notifications.get(&id)?;
+        let action = notification.actions.get(key)?;
+        let parameter = action.target.iter().cloned().collect::<Vec<_>>();
+        Some(
+            (
+                &notification.app_id,
+                &notification.id,
+                &action.name,
+                parameter,
+            )
+                .to_variant(),
+        )
+    }
+}
+
+/// Serve the portal backend on the connection, asking for its bus name
+pub fn serve(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    conn: &gio::DBusConnection,
+) -> Result<(), glib::Error> {
+    let node_info = gio::DBusNodeInfo::for_xml(INTERFACE_XML).unwrap();
+    let portal_iface = node_info.lookup_interface(IFACE).unwrap();
+
+    conn.register_object(PATH, &portal_iface)
+        .typed_method_call::<PortalMethod>()
+        .invoke(glib::clone!(
+            #[weak_allow_none]
+            app,
+            #[strong]
+            state,
+            move |conn, _sender, method, invocation| {
+                let app = app.unwrap();
+                match method {
+                    PortalMethod::AddNotification(args) => {
+                        add_notification(&app, &state, conn, args);
+                        invocation.return_value(None);
+                    }
+                    PortalMethod::RemoveNotification(args) => {
+                        let mut _state = state.borrow_mut();
+                        // An icon still being decoded is not wanted anymore
+                        _state
+                            .portal
+                            .decoding
+                            .remove(&(args.app_id.clone(), args.id.clone()));
+                        if let Some(id) = _state.portal.find(&args.app_id, &args.id) {
+                            close_notification(&mut _state, &conn, id);
+                        }
+                        invocation.return_value(None);
+                    }
+                    PortalMethod::GetProperty(name) => match property(&name) {
+                        Some(value) => invocation.return_value(Some(&(value,).to_variant())),
+                        None => invocation.return_error(
+                            gio::DBusError::InvalidArgs,
+                            &format!("No such property {name}"),
+                        ),
+                    },
+                    PortalMethod::GetAllProperties => {
+                        let properties = ["version"]
+                            .into_iter()
+                            .filter_map(|name| Some((name.to_string(), property(name)?)))
+                            .collect::<HashMap<_, _>>();
+                        invocation.return_value(Some(&(properties,).to_variant()));
+                    }
+                }
+            }
+        ))
+        .build()?;
+
+    conn.call(
+        Some("org.freedesktop.DBus"),
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "RequestName",
+        // DBUS_NAME_FLAG_DO_NOT_QUEUE
+        Some(&(BUS_NAME, 4u32).to_variant()),
+        Some(glib::VariantTy::new("(u)").unwrap()),
+        gio::DBusCallFlags::NONE,
+        -1,
+        Option::<&gio::Cancellable>::None,
+        |reply| match reply.map(|reply| reply.get::<(u32,)>()) {
+            // DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER
+            Ok(Some((1,))) => log::info!("Serving the notification portal as {BUS_NAME}"),
+            Ok(_) => log::warn!("{BUS_NAME} is owned by someone else, not serving the portal"),
+            Err(why) => log::error!("Failed to request {BUS_NAME}: {why}"),
+        },
+    );
+    Ok(())
+}
+
+/// The value of a property of the interface
+fn property(name: &str) -> Option<glib::Variant> {
+    match name {
+        "version" => Some(1u32.to_variant()),
+        _ => None,
+    }
+}
+
+/// Send a portal notification through the same path as the ones from Notify, replacing the
+/// notification the application sent with the same ID before. An image sent as the icon is
+/// decoded on another thread first.
+fn add_notification(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    conn: gio::DBusConnection,
+    args: AddNotificationArgs,
+) {
+    let key = (args.app_id.clone(), args.id.clone());
+    let Some(bytes) = icon_bytes(&args.notification) else {
+        // Any icon of an earlier version of the notification is not wanted anymore
+        state.borrow_mut().portal.decoding.remove(&key);
+        send(app, state, conn, args, None);
+        return;
+    };
+
+    let decode = {
+        let portal = &mut state.borrow_mut().portal;
+        portal.decodes += 1;
+        portal.decoding.insert(key.clone(), portal.decodes);
+        portal.decodes
+    };
+    let app = app.clone();
+    let state = state.clone();
+    glib::spawn_future_local(async move {
+        let image_data = match gio::spawn_blocking(move || image_data(&bytes)).await {
+            Ok(image_data) => image_data,
+            Err(_) => {
+                log::warn!(
+                    "Decoding the icon of portal notification {} panicked",
+                    key.1
+                );
+                None
+            }
+        };
+        // Replaced or removed while the icon was decoded
+        if state.borrow().portal.decoding.get(&key) != Some(&decode) {
+            return;
+        }
+        state.borrow_mut().portal.decoding.remove(&key);
+        send(&app, &state, conn, args, image_data);
+    });
+}
+
+/// Hand the notification to `notify`, remembering it for as long as it stays around
+fn send(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    conn: gio::DBusConnection,
+    args: AddNotificationArgs,
+    image_data: Option<glib::Variant>,
+) {
+    let replaces_id = state
+        .borrow()
+        .portal
+        .find(&args.app_id, &args.id)
+        .unwrap_or(0);
+    let (mut notify_args, actions) = notify_args(&args.app_id, &args.notification, replaces_id);
+    if let Some(image_data) = image_data {
+        notify_args
+            .hints
+            .insert("image-data".to_string(), image_data);
+    }
+    // Activating from the panel goes through the application, which the portal stands between
+    let notified = notify(app, state, conn, notify_args, None);
+    // Notifications closed right away, like during do not disturb, have nothing to act on
+    if let Some(id) = notified.live {
+        state
+            .borrow_mut()
+            .portal
+            .insert(id, args.app_id, args.id, actions);
+    }
+}
+
+/// Translate a portal notification into the arguments of Notify, along with its actions by
+/// the keys they were given. Images sent as bytes are left to `icon_bytes`.
+fn notify_args(
+    app_id: &str,
+    notification: &HashMap<String, glib::Variant>,
+    replaces_id: u32,
+) -> (NotifyArgs, HashMap<String, PortalAction>) {
+    let string = |key: &str| {
+        notification
+            .get(key)
+            .and_then(|value| value.get::<String>())
+    };
+
+    let mut hints = HashMap::new();
+    hints.insert("desktop-entry".to_string(), app_id.to_variant());
+    let urgency: u8 = match string("priority").as_deref() {
+        Some("low") => 0,
+        Some("urgent") => 2,
+        _ => 1,
+    };
+    hints.insert("urgency".to_string(), urgency.to_variant());
+    if let Some(category) = string("category") {
+        hints.insert("category".to_string(), category.to_variant());
+    }
+
+    let mut app_icon = String::new();
+    if let Some(icon) = notification.get("icon").and_then(gio::Icon::deserialize) {
+        if let Some(themed) = icon.downcast_ref::<gio::ThemedIcon>() {
+            app_icon = themed
+                .names()
+                .first()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+        } else if let Some(path) = icon
+            .downcast_ref::<gio::FileIcon>()
+            .and_then(|file| file.file().path())
+        {
+            hints.insert(
+                "image-path".to_string(),
+                path.to_string_lossy().into_owned().to_variant(),
+            );
+        }
+    }
+
+    let mut actions = HashMap::new();
+    let mut action_list = Vec::new();
+    if let Some(name) = string("default-action") {
+        let target = notification.get("default-action-target").cloned();
+        actions.insert(DEFAULT_ACTION.to_string(), PortalAction { name, target });
+        action_list.extend([DEFAULT_ACTION.to_string(), String::new()]);
+    }
+    let buttons = notification
+        .get("buttons")
+        .and_then(|buttons| buttons.get::<Vec<HashMap<String, glib::Variant>>>())
+        .unwrap_or_default();
+    for (i, button) in buttons.into_iter().enumerate() {
+        let (Some(label), Some(name)) = (
+            button.get("label").and_then(|label| label.get::<String>()),
+            button
+                .get("action")
+                .and_then(|action| action.get::<String>()),
+        ) else {
+            continue;
+        };
+        // Buttons may share an action with different targets
+        let key = if actions.contains_key(&name) {
+            format!("{name}-{i}")
+        } else {
+            name.clone()
+        };
+        let target = button.get("target").cloned();
+        actions.insert(key.clone(), PortalAction { name, target });
+        action_list.extend([key, label]);
+    }
+
+    let args = NotifyArgs {
+        app_name: app_id.to_string(),
+        replaces_id,
+        app_icon,
+        summary: string("title").unwrap_or_default(),
+        // Version 2 of the portal has markup in a separate field
+        body: string("markup-body")
+            .or_else(|| string("body"))
+            .unwrap_or_default(),
+        actions: action_list,
+        hints,
+        expire_timeout: -1,
+    };
+    (args, actions)
+}
+
+/// The image file sent as the icon of a portal notification
+fn icon_bytes(notification: &HashMap<String, glib::Variant>) -> Option<glib::Bytes> {
+    let icon = notification.get("icon").and_then(gio::Icon::deserialize)?;
+    Some(icon.downcast_ref::<gio::BytesIcon>()?.bytes())
+}
+
+/// Decode an image file sent as bytes into the image-data hint
+fn image_data(bytes: &glib::Bytes) -> Option<glib::Variant> {
+    let stream = gio::MemoryInputStream::from_bytes(bytes);
+    let pixbuf = gdk_pixbuf::Pixbuf::from_stream(&stream, Option::<&gio::Cancellable>::None)
+        .inspect_err(|why| log::warn!("Failed to decode the icon of a portal notification: {why}"))
+        .ok()?;
+    Some(
+        (
+            pixbuf.width(),
+            pixbuf.height(),
+            pixbuf.rowstride(),
+            pixbuf.has_alpha(),
+            pixbuf.bits_per_sample(),
+            pixbuf.n_channels(),
+            pixbuf.read_pixel_bytes().to_vec(),
+        )
+            .to_variant(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP_ID: &str = "org.example.App";
+
+    fn notification(entries: &[(&str, glib::Variant)]) -> HashMap<String, glib::Variant> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    fn button(label: &str, action: &str, target: Option<glib::Variant>) -> glib::Variant {
+        let mut button = notification(&[
+            ("label", label.to_variant()),
+            ("action", action.to_variant()),
+        ]);
+        if let Some(target) = target {
+            button.insert("target".to_string(), target);
+        }
+        button.to_variant()
+    }
+
+    fn buttons(buttons: Vec<glib::Variant>) -> glib::Variant {
+        glib::Variant::array_from_iter_with_type(glib::VariantTy::VARDICT, buttons)
+    }
+
+    #[test]
+    fn priority_sets_the_urgency() {
+        for (priority, urgency) in [("low", 0u8), ("normal", 1), ("high", 1), ("urgent", 2)] {
+            let (args, _) = notify_args(
+                APP_ID,
+                &notification(&[("priority", priority.to_variant())]),
+                0,
+            );
+            assert_eq!(
+                args.hints["urgency"].get::<u8>(),
+                Some(urgency),
+                "{priority}"
+            );
+        }
+        let (args, _) = notify_args(APP_ID, &HashMap::new(), 0);
+        assert_eq!(args.hints["urgency"].get::<u8>(), Some(1));
+        assert_eq!(args.hints["desktop-entry"].str(), Some(APP_ID));
+    }
+
+    #[test]
+    fn buttons_become_actions() {
+        let (args, actions) = notify_args(
+            APP_ID,
+            &notification(&[
+                ("default-action", "app.open".to_variant()),
+                (
+                    "buttons",
+                    buttons(vec![
+                        button("Reply", "app.reply", None),
+                        button("Mark as read", "app.read", Some("42".to_variant())),
+                        // Buttons without a label can't be shown
+                        notification(&[("action", "app.hidden".to_variant())]).to_variant(),
+                    ]),
+                ),
+            ]),
+            0,
+        );
+        assert_eq!(
+            args.actions,
+            [
+                DEFAULT_ACTION,
+                "",
+                "app.reply",
+                "Reply",
+                "app.read",
+                "Mark as read"
+            ]
+        );
+        assert_eq!(actions[DEFAULT_ACTION].name, "app.open");
+        assert!(actions["app.reply"].target.is_none());
+        assert_eq!(
+            actions["app.read"]
+                .target
+                .as_ref()
+                .and_then(|target| target.str()),
+            Some("42")
+        );
+        assert!(!actions.contains_key("app.hidden"));
+    }
+
+    #[test]
+    fn buttons_sharing_an_action_get_keys_of_their_own() {
+        let (args, actions) = notify_args(
+            APP_ID,
+            &notification(&[(
+                "buttons",
+                buttons(vec![
+                    button("5 minutes", "app.snooze", Some(5u32.to_variant())),
+                    button("10 minutes", "app.snooze", Some(10u32.to_variant())),
+                ]),
+            )]),
+            0,
+        );
+        assert_eq!(
+            args.actions,
+            ["app.snooze", "5 minutes", "app.snooze-1", "10 minutes"]
+        );
+
+        let mut portal = Portal::default();
+        portal.insert(1, APP_ID.to_string(), "reminder".to_string(), actions);
+        let (app_id, id, action, parameter) = portal
+            .action_invoked(1, "app.snooze-1")
+            .unwrap()
+            .get::<(String, String, String, Vec<glib::Variant>)>()
+            .unwrap();
+        assert_eq!((app_id.as_str(), id.as_str()), (APP_ID, "reminder"));
+        // The application gets the name it gave the action, with the target of the button
+        assert_eq!(action, "app.snooze");
+        assert_eq!(parameter[0].get::<u32>(), Some(10));
+    }
+
+    #[test]
+    fn icons_become_the_app_icon_or_image() {
+        let icon = |icon: &gio::Icon| notification(&[("icon", icon.serialize().unwrap())]);
+
+        let themed = icon(gio::ThemedIcon::new("mail-unread").upcast_ref());
+        let (args, _) = notify_args(APP_ID, &themed, 0);
+        assert_eq!(args.app_icon, "mail-unread");
+        assert!(icon_bytes(&themed).is_none());
+
+        let file = icon(gio::FileIcon::new(&gio::File::for_path("/tmp/icon.png")).upcast_ref());
+        let (args, _) = notify_args(APP_ID, &file, 0);
+        assert_eq!(args.app_icon, "");
+        assert_eq!(args.hints["image-path"].str(), Some("/tmp/icon.png"));
+
+        // Decoded off the main thread instead
+        let bytes =
+            icon(gio::BytesIcon::new(&glib::Bytes::from_static(b"not an image")).upcast_ref());
+        let (args, _) = notify_args(APP_ID, &bytes, 0);
+        assert_eq!(args.app_icon, "");
+        assert!(!args.hints.contains_key("image-data"));
+        let bytes = icon_bytes(&bytes).unwrap();
+        assert_eq!(&*bytes, b"not an image");
+        assert!(image_data(&bytes).is_none());
+    }
+
+    #[test]
+    fn absorbed_notifications_are_found_by_their_own_id() {
+        let mut portal = Portal::default();
+        portal.insert(1, APP_ID.to_string(), "first".to_string(), HashMap::new());
+        portal.insert(1, APP_ID.to_string(), "second".to_string(), HashMap::new());
+        assert_eq!(portal.find(APP_ID, "first"), Some(1));
+        assert_eq!(portal.find(APP_ID, "second"), Some(1));
+        assert_eq!(portal.find("org.example.Other", "second"), None);
+
+        portal.remove(1);
+        assert_eq!(portal.find(APP_ID, "first"), None);
+        assert_eq!(portal.find(APP_ID, "second"), None);
+    }
+}
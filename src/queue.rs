@@ -0,0 +1,204 @@
+use std::collections::VecDeque;
+
+use crate::{
+    config::{Config, QueueOverflow},
+    notification::{NotificationInit, Urgency},
+};
+
+/// What became of a notification that has to wait for room on the screen
+#[derive(Debug, PartialEq)]
+pub enum Queued {
+    /// Waiting in the queue, having pushed out the queued notification with the ID, if any
+    Waiting { dropped: Option<u32> },
+    /// Dropped, as the queue is full
+    Dropped,
+    /// Dropped, as the queue is full, to be counted in the summary notification
+    Summarized,
+}
+
+/// Whether there is room on the screen for another notification with the given urgency, with
+/// `shown` notifications already on it
+pub fn has_room(config: &Config, shown: usize, urgency: Urgency) -> bool {
+    let mut max_visible = config.max_visible;
+    if max_visible == 0 {
+        return true;
+    }
+    if urgency == Urgency::Critical {
+        max_visible += config.critical_overflow;
+    }
+    shown < max_visible
+}
+
+/// Queue a notification until there is room for it, following the queue policy of the config.
+/// Critical notifications go ahead of everything else. The summary notification doesn't count
+/// towards the limit.
+pub fn enqueue(
+    pending: &mut VecDeque<NotificationInit>,
+    config: &Config,
+    summary_id: Option<u32>,
+    init: NotificationInit,
+) -> Queued {
+    let queued = pending
+        .iter()
+        .filter(|pending| Some(pending.id) != summary_id)
+        .count();
+    let sticky = config.sticky(&init.app_name, init.hints.desktop_entry.as_deref());
+    let mut dropped = None;
+    if config.queue_limit > 0 && queued >= config.queue_limit {
+        match config.queue_overflow {
+            // Notifications of sticky apps are never dropped, the queue grows past the limit
+            // instead
+            _ if sticky => (),
+            QueueOverflow::DropOldest => {
+                // Critical notifications wait at the front, and are only dropped if nothing
+                // else is queued
+                let droppable = |pending: &NotificationInit| {
+                    Some(pending.id) != summary_id
+                        && !config.sticky(&pending.app_name, pending.hints.desktop_entry.as_deref())
+                };
+                let index = pending
+                    .iter()
+                    .position(|pending| {
+                        pending.hints.urgency != Some(Urgency::Critical) && droppable(pending)
+                    })
+                    .or_else(|| pending.iter().position(droppable));
+                dropped = index
+                    .and_then(|index| pending.remove(index))
+                    .map(|dropped| dropped.id);
+            }
+            QueueOverflow::DropNewest => return Queued::Dropped,
+            QueueOverflow::Summarize => return Queued::Summarized,
+        }
+    }
+
+    let index = if init.hints.urgency == Some(Urgency::Critical) {
+        pending
+            .iter()
+            .position(|pending| pending.hints.urgency != Some(Urgency::Critical))
+            .unwrap_or(pending.len())
+    } else {
+        pending.len()
+    };
+    pending.insert(index, init);
+    Queued::Waiting { dropped }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Hints;
+
+    const FLOOD: u32 = 200;
+    const MAX_VISIBLE: usize = 3;
+
+    /// The IDs of the shown and queued notifications after flooding the daemon, along with how
+    /// the others were dropped
+    struct Flood {
+        shown: Vec<u32>,
+        pending: Vec<u32>,
+        dropped: Vec<u32>,
+        summarized: Vec<u32>,
+    }
+
+    fn flood(config: &Config) -> Flood {
+        let mut shown = Vec::new();
+        let mut pending = VecDeque::new();
+        let mut dropped = Vec::new();
+        let mut summarized = Vec::new();
+        for id in 1..=FLOOD {
+            let init = NotificationInit {
+                id,
+                app_name: "flood".to_string(),
+                ..Default::default()
+            };
+            if has_room(config, shown.len(), Urgency::Normal) {
+                shown.push(id);
+                continue;
+            }
+            match enqueue(&mut pending, config, None, init) {
+                Queued::Waiting { dropped: Some(id) } => dropped.push(id),
+                Queued::Waiting { dropped: None } => (),
+                Queued::Dropped => dropped.push(id),
+                Queued::Summarized => summarized.push(id),
+            }
+        }
+        Flood {
+            shown,
+            pending: pending.iter().map(|init| init.id).collect(),
+            dropped,
+            summarized,
+        }
+    }
+
+    fn config(queue_limit: usize, queue_overflow: QueueOverflow) -> Config {
+        Config {
+            max_visible: MAX_VISIBLE,
+            queue_limit,
+            queue_overflow,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unbounded_queue_keeps_everything_in_order() {
+        let flood = flood(&config(0, QueueOverflow::DropOldest));
+        assert_eq!(flood.shown, [1, 2, 3]);
+        assert_eq!(flood.pending, (4..=FLOOD).collect::<Vec<_>>());
+        assert!(flood.dropped.is_empty());
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest() {
+        let flood = flood(&config(50, QueueOverflow::DropOldest));
+        assert_eq!(flood.shown, [1, 2, 3]);
+        assert_eq!(flood.pending, (151..=FLOOD).collect::<Vec<_>>());
+        assert_eq!(flood.dropped, (4..=150).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_oldest() {
+        let flood = flood(&config(50, QueueOverflow::DropNewest));
+        assert_eq!(flood.shown, [1, 2, 3]);
+        assert_eq!(flood.pending, (4..=53).collect::<Vec<_>>());
+        assert_eq!(flood.dropped, (54..=FLOOD).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn summarize_counts_the_rest() {
+        let flood = flood(&config(50, QueueOverflow::Summarize));
+        assert_eq!(flood.shown, [1, 2, 3]);
+        assert_eq!(flood.pending, (4..=53).collect::<Vec<_>>());
+        assert!(flood.dropped.is_empty());
+        assert_eq!(flood.summarized, (54..=FLOOD).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn critical_notifications_skip_the_queue() {
+        let config = config(2, QueueOverflow::DropOldest);
+        let mut pending = VecDeque::new();
+        for (id, urgency) in [(1, Urgency::Normal), (2, Urgency::Critical)] {
+            let init = NotificationInit {
+                id,
+                hints: Hints {
+                    urgency: Some(urgency),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            enqueue(&mut pending, &config, None, init);
+        }
+        let init = NotificationInit {
+            id: 3,
+            ..Default::default()
+        };
+        // The normal notification is dropped before the critical one
+        assert_eq!(
+            enqueue(&mut pending, &config, None, init),
+            Queued::Waiting { dropped: Some(1) }
+        );
+        assert_eq!(
+            pending.iter().map(|init| init.id).collect::<Vec<_>>(),
+            [2, 3]
+        );
+    }
+}
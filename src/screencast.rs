@@ -0,0 +1,247 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use gtk4::{
+    gio,
+    glib::{self, thread_guard::ThreadGuard},
+    prelude::*,
+};
+
+use crate::{
+    daemon::{DaemonState, release_held},
+    dbus::emit_properties_changed,
+};
+
+/// What the portal frontend asks of the backend when screencasts start and stop. Sessions of
+/// other portals are closed through the same interface, but never started as screencasts.
+/// Replies carry no interface to match them by, so every reply is looked at for the ones to
+/// Start.
+const MATCH_RULES: [&str; 4] = [
+    "type='method_call',interface='org.freedesktop.impl.portal.ScreenCast',member='Start'",
+    "type='method_return'",
+    "type='method_call',interface='org.freedesktop.impl.portal.Session',member='Close'",
+    "type='signal',interface='org.freedesktop.impl.portal.Session',member='Closed'",
+];
+
+/// Keep track of the open screencast sessions of xdg-desktop-portal, by watching the calls it
+/// makes to its backend on a separate connection to the session bus that only monitors it
+pub fn watch(app: &gio::Application, state: &Rc<RefCell<DaemonState>>) {
+    let address =
+        match gio::dbus_address_get_for_bus_sync(gio::BusType::Session, gio::Cancellable::NONE) {
+            Ok(address) => address,
+            Err(why) => {
+                log::warn!("Failed to find the session bus to follow screencasts: {why}");
+                return;
+            }
+        };
+    gio::DBusConnection::for_address(
+        &address,
+        gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+            | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+        None,
+        gio::Cancellable::NONE,
+        glib::clone!(
+            #[weak]
+            app,
+            #[strong]
+            state,
+            move |conn| {
+                let conn = match conn {
+                    Ok(conn) => conn,
+                    Err(why) => {
+                        log::warn!(
+                            "Failed to connect to the session bus to follow screencasts: {why}"
+                        );
+                        return;
+                    }
+                };
+                monitor(&app, &state, &conn);
+                state.borrow_mut().screencast_conn = Some(conn);
+            }
+        ),
+    );
+}
+
+fn monitor(app: &gio::Application, state: &Rc<RefCell<DaemonState>>, conn: &gio::DBusConnection) {
+    // Filters run on the thread of GDBus, so the changes are handed to the main context
+    let state = Arc::new(ThreadGuard::new((app.downgrade(), Rc::downgrade(state))));
+    // The sessions of the Start calls waiting for their reply, by caller and serial
+    let starting = Mutex::new(HashMap::new());
+    conn.add_filter(move |conn, message, incoming| {
+        // Only the replies to the connection itself are let through, as a monitor may not
+        // send anything, including the errors GDBus replies to unknown method calls with
+        if !incoming || message.destination() == conn.unique_name() {
+            return Some(message.clone());
+        }
+        if let Some((session, active)) = session_change(message, &mut starting.lock().unwrap()) {
+            let state = state.clone();
+            glib::MainContext::default().invoke(move || {
+                let (app, state) = state.get_ref();
+                if let (Some(app), Some(state)) = (app.upgrade(), state.upgrade()) {
+                    changed(&app, &state, session, active);
+                }
+            });
+        }
+        None
+    });
+    conn.call(
+        Some("org.freedesktop.DBus"),
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus.Monitoring",
+        "BecomeMonitor",
+        Some(&(MATCH_RULES.to_vec(), 0u32).to_variant()),
+        None,
+        gio::DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+        |reply| match reply {
+            Ok(_) => log::info!("Following screencasts to enable do not disturb"),
+            Err(why) => log::warn!("Failed to follow screencasts: {why}"),
+        },
+    );
+}
+
+/// The screencast session a monitored message starts or ends. A session only starts once the
+/// backend replies to Start with success, as the user may still cancel it.
+fn session_change(
+    message: &gio::DBusMessage,
+    starting: &mut HashMap<(String, u32), String>,
+) -> Option<(String, bool)> {
+    if message.message_type() == gio::DBusMessageType::MethodReturn {
+        let session =
+            starting.remove(&(message.destination()?.to_string(), message.reply_serial()))?;
+        // 0 for success, 1 if the user cancelled and 2 for anything else
+        let (response, _) = message
+            .body()?
+            .get::<(u32, HashMap<String, glib::Variant>)>()?;
+        return (response == 0).then_some((session, true));
+    }
+    match message.member()?.as_str() {
+        "Start" => {
+            // The session handle follows the request handle
+            let session = message.body()?.try_child_value(1)?.str()?.to_string();
+            starting.insert((message.sender()?.to_string(), message.serial()), session);
+            None
+        }
+        "Close" | "Closed" => {
+            let session = message.path()?.to_string();
+            starting.retain(|_, starting| *starting != session);
+            Some((session, false))
+        }
+        _ => None,
+    }
+}
+
+/// Enable do not disturb while any screencast is open, showing the notifications it held back
+/// once the last one ends
+fn changed(
+    app: &gio::Application,
+    state: &Rc<RefCell<DaemonState>>,
+    session: String,
+    active: bool,
+) {
+    let mut _state = state.borrow_mut();
+    let was_casting = !_state.screencasts.is_empty();
+    if active {
+        _state.screencasts.insert(session);
+    } else {
+        _state.screencasts.remove(&session);
+    }
+    match (was_casting, _state.screencasts.is_empty()) {
+        (false, false) => log::info!("Screencast started, enabling do not disturb"),
+        (true, true) => log::info!("Last screencast ended, disabling do not disturb"),
+        _ => return,
+    }
+    emit_properties_changed(
+        &_state.conn,
+        &[("NotificationLevel", _state.level().to_variant())],
+    );
+    release_held(app, state, &mut _state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRONTEND: &str = ":1.42";
+    const SESSION: &str = "/org/freedesktop/portal/desktop/session/1_42/t";
+
+    fn start(serial: u32) -> gio::DBusMessage {
+        let message = gio::DBusMessage::new_method_call(
+            Some("org.freedesktop.impl.portal.desktop.example"),
+            "/org/freedesktop/portal/desktop",
+            Some("org.freedesktop.impl.portal.ScreenCast"),
+            "Start",
+        );
+        message.set_body(
+            &(
+                "/org/freedesktop/portal/desktop/request/1_42/t",
+                SESSION,
+                "org.example.App",
+                "",
+                HashMap::<String, glib::Variant>::new(),
+            )
+                .to_variant(),
+        );
+        message.set_sender(Some(FRONTEND));
+        message.set_serial(serial);
+        message
+    }
+
+    fn reply(start: &gio::DBusMessage, response: u32) -> gio::DBusMessage {
+        let reply = start.new_method_reply();
+        reply.set_body(&(response, HashMap::<String, glib::Variant>::new()).to_variant());
+        reply
+    }
+
+    #[test]
+    fn screencasts_start_once_the_backend_replies() {
+        let mut starting = HashMap::new();
+        let start = start(7);
+        assert_eq!(session_change(&start, &mut starting), None);
+        assert_eq!(
+            session_change(&reply(&start, 0), &mut starting),
+            Some((SESSION.to_string(), true))
+        );
+        assert!(starting.is_empty());
+    }
+
+    #[test]
+    fn cancelled_screencasts_never_start() {
+        let mut starting = HashMap::new();
+        let start = start(7);
+        session_change(&start, &mut starting);
+        assert_eq!(session_change(&reply(&start, 1), &mut starting), None);
+        assert!(starting.is_empty());
+    }
+
+    #[test]
+    fn unrelated_replies_are_ignored() {
+        let mut starting = HashMap::new();
+        session_change(&start(7), &mut starting);
+        assert_eq!(session_change(&reply(&start(8), 0), &mut starting), None);
+        assert_eq!(starting.len(), 1);
+    }
+
+    #[test]
+    fn closing_a_session_forgets_its_start() {
+        let mut starting = HashMap::new();
+        let start = start(7);
+        session_change(&start, &mut starting);
+        let close = gio::DBusMessage::new_method_call(
+            None,
+            SESSION,
+            Some("org.freedesktop.impl.portal.Session"),
+            "Close",
+        );
+        assert_eq!(
+            session_change(&close, &mut starting),
+            Some((SESSION.to_string(), false))
+        );
+        assert_eq!(session_change(&reply(&start, 0), &mut starting), None);
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use gtk4::gio;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::expand_path, notification::Urgency};
+
+/// Sounds played when notifications are shown, either names from the XDG sound theme or
+/// paths to sound files
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct SoundConfig {
+    enabled: bool,
+    low: Option<String>,
+    normal: Option<String>,
+    critical: Option<String>,
+}
+
+impl Default for SoundConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            low: None,
+            normal: None,
+            critical: None,
+        }
+    }
+}
+
+impl SoundConfig {
+    pub fn for_urgency(&self, urgency: Urgency) -> Option<&str> {
+        if !self.enabled {
+            return None;
+        }
+        match urgency {
+            Urgency::Low => &self.low,
+            Urgency::Normal => &self.normal,
+            Urgency::Critical => &self.critical,
+        }
+        .as_deref()
+    }
+}
+
+/// Plays sounds in the background with `canberra-gtk-play`
+#[derive(Debug, Default)]
+pub struct Player {
+    /// Problems that have been logged already, so that every notification doesn't repeat them
+    reported: Rc<RefCell<HashSet<String>>>,
+    /// When a sound was last played for each urgency
+    last_played: HashMap<Urgency, Instant>,
+}
+
+impl Player {
+    /// Play the sound for a notification of the urgency, unless a sound of the same urgency
+    /// played within `cooldown`
+    pub fn play(&mut self, sound: &str, urgency: Urgency, cooldown: Duration) {
+        if self.cooling_down(urgency, cooldown, Instant::now()) {
+            log::debug!("Skipping the {urgency} notification sound, played too recently");
+            return;
+        }
+
+        // Anything that looks like a path is a file, the rest are sound theme names
+        let arg = if sound.contains('/') {
+            let path = expand_path(sound);
+            if !path.exists() {
+                self.report(format!("Sound file {} does not exist", path.display()));
+                return;
+            }
+            format!("--file={}", path.display())
+        } else {
+            format!("--id={sound}")
+        };
+
+        let process = match gio::Subprocess::newv(
+            &[
+                OsStr::new("canberra-gtk-play"),
+                OsStr::new(&arg),
+                OsStr::new("--description=Notification"),
+            ],
+            gio::SubprocessFlags::STDOUT_SILENCE | gio::SubprocessFlags::STDERR_SILENCE,
+        ) {
+            Ok(process) => process,
+            Err(why) => {
+                self.report(format!("Failed to play notification sounds: {why}"));
+                return;
+            }
+        };
+
+        let reported = self.reported.clone();
+        let sound = sound.to_string();
+        process.wait_check_async(gio::Cancellable::NONE, move |result| {
+            if let Err(why) = result {
+                report(&reported, format!("Failed to play sound {sound}: {why}"));
+            }
+        });
+    }
+
+    /// Whether a sound of the urgency played less than `cooldown` before `now`, recording `now`
+    /// as the last time otherwise
+    fn cooling_down(&mut self, urgency: Urgency, cooldown: Duration, now: Instant) -> bool {
+        if let Some(last_played) = self.last_played.get(&urgency) {
+            if now.duration_since(*last_played) < cooldown {
+                return true;
+            }
+        }
+        self.last_played.insert(urgency, now);
+        false
+    }
+
+    fn report(&self, message: String) {
+        report(&self.reported, message);
+    }
+}
+
+fn report(reported: &RefCell<HashSet<String>>, message: String) {
+    if reported.borrow_mut().insert(message.clone()) {
+        warn!("{message}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(1000);
+
+    #[test]
+    fn cooldown_boundaries() {
+        let start = Instant::now();
+        for (elapsed, cooling_down) in [(999, true), (1000, false), (1001, false)] {
+            let mut player = Player::default();
+            assert!(!player.cooling_down(Urgency::Normal, COOLDOWN, start));
+            let now = start + Duration::from_millis(elapsed);
+            assert_eq!(
+                player.cooling_down(Urgency::Normal, COOLDOWN, now),
+                cooling_down,
+                "{elapsed}ms after the last sound"
+            );
+        }
+    }
+
+    #[test]
+    fn skipped_sounds_do_not_extend_the_cooldown() {
+        let start = Instant::now();
+        let mut player = Player::default();
+        assert!(!player.cooling_down(Urgency::Normal, COOLDOWN, start));
+        assert!(player.cooling_down(Urgency::Normal, COOLDOWN, start + COOLDOWN / 2));
+        assert!(!player.cooling_down(Urgency::Normal, COOLDOWN, start + COOLDOWN));
+    }
+
+    #[test]
+    fn cooldown_is_per_urgency() {
+        let start = Instant::now();
+        let mut player = Player::default();
+        assert!(!player.cooling_down(Urgency::Normal, COOLDOWN, start));
+        let now = start + Duration::from_millis(1);
+        assert!(!player.cooling_down(Urgency::Critical, COOLDOWN, now));
+        assert!(!player.cooling_down(Urgency::Low, COOLDOWN, now));
+        assert!(player.cooling_down(Urgency::Normal, COOLDOWN, now));
+        assert!(player.cooling_down(Urgency::Critical, COOLDOWN, now));
+    }
+
+    #[test]
+    fn zero_disables_the_cooldown() {
+        let now = Instant::now();
+        let mut player = Player::default();
+        assert!(!player.cooling_down(Urgency::Normal, Duration::ZERO, now));
+        assert!(!player.cooling_down(Urgency::Normal, Duration::ZERO, now));
+    }
+}
@@ -0,0 +1,170 @@
+use std::{collections::HashMap, time::Instant};
+
+use gtk4::glib;
+use serde::{Serialize, Serializer};
+use serde_json::{Value, json};
+
+use crate::{notification::NotificationCloseReason, output::CommandOutput};
+
+/// What happened to the notifications of an application
+#[derive(Debug, Default, Clone, Copy, glib::Variant)]
+pub struct AppStats {
+    pub received: u32,
+    pub expired: u32,
+    pub dismissed: u32,
+    pub actions: u32,
+}
+
+impl AppStats {
+    fn add(&mut self, other: &AppStats) {
+        self.received += other.received;
+        self.expired += other.expired;
+        self.dismissed += other.dismissed;
+        self.actions += other.actions;
+    }
+
+    fn to_json(self) -> Value {
+        json!({
+            "received": self.received,
+            "expired": self.expired,
+            "dismissed": self.dismissed,
+            "actions": self.actions,
+        })
+    }
+}
+
+/// Counters per application since the daemon started
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    apps: HashMap<String, AppStats>,
+    /// The application of every open notification, and whether an action was invoked on it
+    open: HashMap<u32, (String, bool)>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            apps: HashMap::new(),
+            open: HashMap::new(),
+        }
+    }
+}
+
+impl Stats {
+    pub fn received(&mut self, id: u32, app_name: &str) {
+        self.apps.entry(app_name.to_string()).or_default().received += 1;
+        self.open.insert(id, (app_name.to_string(), false));
+    }
+
+    pub fn action_invoked(&mut self, id: u32) {
+        let Some((app_name, invoked)) = self.open.get_mut(&id) else {
+            return;
+        };
+        // Invoking an action closes the notification, which is not counted as a dismissal
+        if !*invoked {
+            *invoked = true;
+            self.apps.entry(app_name.clone()).or_default().actions += 1;
+        }
+    }
+
+    pub fn closed(&mut self, id: u32, reason: NotificationCloseReason) {
+        let Some((app_name, invoked)) = self.open.remove(&id) else {
+            return;
+        };
+        let stats = self.apps.entry(app_name).or_default();
+        match reason {
+            NotificationCloseReason::Expired => stats.expired += 1,
+            NotificationCloseReason::DismissedByUser if !invoked => stats.dismissed += 1,
+            _ => (),
+        }
+    }
+
+    /// Start counting from zero, keeping track of the notifications that are still open
+    pub fn reset(&mut self) {
+        self.apps.clear();
+    }
+
+    pub fn apps(&self) -> HashMap<String, AppStats> {
+        self.apps.clone()
+    }
+
+    /// Seconds since the daemon started
+    pub fn uptime(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+}
+
+/// The stats as received from the daemon
+pub struct Report {
+    pub apps: HashMap<String, AppStats>,
+    /// Seconds since the daemon started
+    pub uptime: u64,
+}
+
+impl Serialize for Report {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        to_json(&self.apps, self.uptime).serialize(serializer)
+    }
+}
+
+impl CommandOutput for Report {
+    fn print_text(&self) {
+        print_table(&self.apps, self.uptime);
+    }
+}
+
+/// Print a table of the applications sorted by how many notifications they sent, followed by
+/// the totals
+fn print_table(apps: &HashMap<String, AppStats>, uptime: u64) {
+    let mut apps = apps.iter().collect::<Vec<_>>();
+    apps.sort_by(|(a_name, a), (b_name, b)| {
+        b.received.cmp(&a.received).then_with(|| a_name.cmp(b_name))
+    });
+
+    let mut total = AppStats::default();
+    for (_, stats) in &apps {
+        total.add(stats);
+    }
+
+    let width = apps
+        .iter()
+        .map(|(app_name, _)| app_name.chars().count())
+        .max()
+        .unwrap_or_default()
+        .max("Application".len());
+    println!(
+        "{:width$}  {:>8}  {:>8}  {:>9}  {:>7}",
+        "Application", "Received", "Expired", "Dismissed", "Actions"
+    );
+    let total_name = "Total".to_string();
+    for (app_name, stats) in apps.into_iter().chain([(&total_name, &total)]) {
+        println!(
+            "{:width$}  {:>8}  {:>8}  {:>9}  {:>7}",
+            app_name, stats.received, stats.expired, stats.dismissed, stats.actions
+        );
+    }
+    println!(
+        "\nUptime: {}h {:02}m {:02}s",
+        uptime / 3600,
+        uptime / 60 % 60,
+        uptime % 60
+    );
+}
+
+/// The stats as a JSON object with the totals, the applications and the uptime in seconds
+fn to_json(apps: &HashMap<String, AppStats>, uptime: u64) -> Value {
+    let mut total = AppStats::default();
+    for stats in apps.values() {
+        total.add(stats);
+    }
+    json!({
+        "uptime": uptime,
+        "total": total.to_json(),
+        "apps": apps
+            .iter()
+            .map(|(app_name, stats)| (app_name.clone(), stats.to_json()))
+            .collect::<serde_json::Map<_, _>>(),
+    })
+}
@@ -0,0 +1,40 @@
+use std::{
+    env,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+};
+
+use gtk4::glib;
+
+/// Tell the service manager about a state change, like `READY=1`.
+///
+/// Does nothing unless started by systemd with `NOTIFY_SOCKET` set.
+pub fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let path = path.to_string_lossy();
+
+    // Sockets in the abstract namespace are prefixed with @
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+        None => SocketAddr::from_pathname(path.as_ref()),
+    };
+
+    let result = addr.and_then(|addr| {
+        let socket = UnixDatagram::unbound()?;
+        socket.send_to_addr(state.as_bytes(), &addr)
+    });
+    if let Err(why) = result {
+        log::warn!("Failed to notify the service manager: {why}");
+    }
+}
+
+/// Tell the service manager that the config is being reloaded, to be followed up by `READY=1`
+pub fn reloading() {
+    // Both use CLOCK_MONOTONIC
+    let usec = glib::monotonic_time();
+    notify(&format!("RELOADING=1\nMONOTONIC_USEC={usec}"));
+}
@@ -0,0 +1,129 @@
+use std::{collections::HashMap, thread, time::Duration};
+
+use gtk4::{gio, glib, prelude::*};
+use yand::{NotificationCloseReason, RendererMessage, ServerMessage};
+
+const NAME: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+
+fn name_has_owner(conn: &gio::DBusConnection) -> bool {
+    conn.call_sync(
+        Some("org.freedesktop.DBus"),
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "NameHasOwner",
+        Some(&(NAME,).to_variant()),
+        Some(glib::VariantTy::new("(b)").unwrap()),
+        gio::DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+    )
+    .ok()
+    .and_then(|reply| reply.get::<(bool,)>())
+    .is_some_and(|(owned,)| owned)
+}
+
+fn notify(conn: &gio::DBusConnection, summary: &str) -> u32 {
+    let reply = conn
+        .call_sync(
+            Some(NAME),
+            PATH,
+            NAME,
+            "Notify",
+            Some(
+                &(
+                    "yand-test",
+                    0u32,
+                    "",
+                    summary,
+                    "Body",
+                    Vec::<String>::new(),
+                    HashMap::<String, glib::Variant>::new(),
+                    -1i32,
+                )
+                    .to_variant(),
+            ),
+            Some(glib::VariantTy::new("(u)").unwrap()),
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+        )
+        .unwrap();
+    reply.get::<(u32,)>().unwrap().0
+}
+
+/// Runs against the session bus, so it is skipped without one or while another notification
+/// daemon owns the name
+#[test]
+fn renderer_gets_notifications_and_dismisses_them() {
+    let Ok(conn) = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE) else {
+        eprintln!("No session bus, skipping");
+        return;
+    };
+    if name_has_owner(&conn) {
+        eprintln!("{NAME} is already owned, skipping");
+        return;
+    }
+
+    // Nothing else runs in the test binary to race with
+    let config_home = std::env::temp_dir().join(format!("yand-test-{}", std::process::id()));
+    unsafe { std::env::set_var("XDG_CONFIG_HOME", &config_home) };
+
+    let (server_tx, server_rx) = relm4::channel::<ServerMessage>();
+    let (renderer_tx, renderer_rx) = relm4::channel::<RendererMessage>();
+    thread::spawn(move || yand::run_dbus_server(renderer_rx, server_tx));
+    for _ in 0..50 {
+        if name_has_owner(&conn) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert!(name_has_owner(&conn), "the server never took the name");
+
+    // Closed by the application
+    let id = notify(&conn, "Closed by the app");
+    match server_rx.recv_sync() {
+        Some(ServerMessage::Notify(init)) => {
+            assert_eq!(init.id, id);
+            assert_eq!(init.app_name, "yand-test");
+            assert_eq!(init.summary, "Closed by the app");
+        }
+        message => panic!("expected the notification, got {message:?}"),
+    }
+    conn.call_sync(
+        Some(NAME),
+        PATH,
+        NAME,
+        "CloseNotification",
+        Some(&(id,).to_variant()),
+        None,
+        gio::DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+    )
+    .unwrap();
+    match server_rx.recv_sync() {
+        Some(ServerMessage::Closed {
+            id: closed,
+            reason: NotificationCloseReason::DismissedByApp,
+        }) => assert_eq!(closed, id),
+        message => panic!("expected the notification to close, got {message:?}"),
+    }
+
+    // Dismissed in the renderer
+    let id = notify(&conn, "Dismissed by the user");
+    assert!(matches!(
+        server_rx.recv_sync(),
+        Some(ServerMessage::Notify(init)) if init.id == id
+    ));
+    renderer_tx.send(RendererMessage::Dismissed(id)).unwrap();
+    match server_rx.recv_sync() {
+        Some(ServerMessage::Closed {
+            id: closed,
+            reason: NotificationCloseReason::DismissedByUser,
+        }) => assert_eq!(closed, id),
+        message => panic!("expected the notification to close, got {message:?}"),
+    }
+
+    let _ = std::fs::remove_dir_all(config_home);
+}